@@ -6,7 +6,7 @@ class PalangLexer(RegexLexer):
     aliases = ['palang']
     filenames = ['*.palang']
 
-    keywords = ('module', 'model', 'prompt', 'function', 'return', 'if', 'for', 'in', 'rag')
+    keywords = ('module', 'model', 'prompt', 'function', 'uses', 'return', 'if', 'for', 'in', 'rag')
 
     tokens = {
         'root': [
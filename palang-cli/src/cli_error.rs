@@ -0,0 +1,125 @@
+use std::path::PathBuf;
+
+use palang_compiler::compile_error::CompileError;
+#[cfg(feature = "opentelemetry")]
+use palang_virtual_machine::virtualization::telemetry_error::TelemetryError;
+use palang_virtual_machine::{assembly::load_error::LoadError, llm::{configuration_error::ConfigurationError, llm_error::LlmError, storage_error::StorageError}, virtualization::runtime_error::RuntimeError};
+use thiserror::Error;
+
+/// Failures of the commands, each kind exiting with its own code.
+#[derive(Debug, Error)]
+pub enum CliError {
+    #[error("Could not compile {file:?} ({source})")]
+    Compile {
+        file: PathBuf,
+        #[source]
+        source: Box<CompileError>,
+    },
+
+    #[error("Could not load assembly {file:?} ({source})")]
+    Load {
+        file: PathBuf,
+        #[source]
+        source: LoadError,
+    },
+
+    #[error("Could not create large language model \"{llm}\" ({source})")]
+    Llm {
+        llm: String,
+        #[source]
+        source: LlmError,
+    },
+
+    #[error("Could not execute program ({0})")]
+    Runtime(#[from] RuntimeError),
+
+    #[error("Invalid profile ({0})")]
+    Configuration(#[from] ConfigurationError),
+
+    #[error(transparent)]
+    Storage(#[from] StorageError),
+
+    #[error("Invalid session name {0:?}, use only letters, digits, - and _")]
+    SessionName(String),
+
+    #[cfg(feature = "opentelemetry")]
+    #[error(transparent)]
+    Telemetry(#[from] TelemetryError),
+
+    #[error("{0}")]
+    Other(String),
+}
+
+impl CliError {
+    /// 1 for any other failure, 2 being taken by invalid arguments.
+    pub fn get_exit_code(&self) -> u8 {
+        match self {
+            CliError::Other(_) | CliError::Configuration(_) | CliError::Storage(_) | CliError::SessionName(_) => 1,
+            #[cfg(feature = "opentelemetry")]
+            CliError::Telemetry(_) => 1,
+            CliError::Compile { .. } => 3,
+            CliError::Load { .. } => 4,
+            CliError::Llm { .. } => 5,
+            CliError::Runtime(error) => match error.without_stack_trace() {
+                RuntimeError::Llm(_) => 5,
+                RuntimeError::BudgetExceeded(_) => 7,
+                RuntimeError::Cancelled => 130,
+                _ => 6,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io, time::Duration};
+
+    use palang_virtual_machine::virtualization::{budget::{BudgetExceeded, BudgetLimit}, execution_report::Usage, stack_trace::StackTrace};
+
+    use super::*;
+
+    fn io_error() -> io::Error {
+        io::Error::new(io::ErrorKind::NotFound, "missing")
+    }
+
+    fn runtime(error: RuntimeError) -> CliError {
+        CliError::Runtime(error)
+    }
+
+    #[test]
+    fn maps_failures_to_exit_codes() {
+        let file = PathBuf::from("main.palang");
+        let budget_exceeded = BudgetExceeded {
+            limit: BudgetLimit::LlmCalls(1),
+            usage: Usage::default(),
+            elapsed: Duration::ZERO,
+        };
+        let cases = [
+            (CliError::Other("failed".to_string()), 1),
+            (ConfigurationError::Missing("llm".to_string()).into(), 1),
+            (StorageError::CassetteNotFound(file.clone()).into(), 1),
+            (CliError::SessionName("../session".to_string()), 1),
+            (CliError::Compile { file: file.clone(), source: Box::new(CompileError::Io { file: file.clone(), source: io_error() }) }, 3),
+            (CliError::Load { file: file.clone(), source: LoadError::Io { file: file.clone(), source: io_error() } }, 4),
+            (CliError::Llm { llm: "llm".to_string(), source: LlmError::NotFound("llm".to_string()) }, 5),
+            (runtime(RuntimeError::Llm(LlmError::NotFound("llm".to_string()))), 5),
+            (runtime(RuntimeError::TaskNotFound("tests/missing".to_string())), 6),
+            (runtime(RuntimeError::BudgetExceeded(budget_exceeded)), 7),
+            (runtime(RuntimeError::Cancelled), 130),
+        ];
+
+        for (error, exit_code) in cases {
+            assert_eq!(error.get_exit_code(), exit_code, "{error}");
+        }
+    }
+
+    #[test]
+    fn maps_failures_with_stack_traces_to_exit_codes() {
+        let error = runtime(RuntimeError::WithStackTrace {
+            error: Box::new(RuntimeError::Cancelled),
+            trace: StackTrace::default(),
+        });
+
+        assert_eq!(error.get_exit_code(), 130);
+    }
+}
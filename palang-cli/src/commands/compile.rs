@@ -1,7 +1,10 @@
 use std::{env, fs, path::{Path, PathBuf}};
 
 use clap::{arg, Parser};
-use palang_compiler::{compile_file, compile_package};
+use palang_compiler::{compile_file_with_native_functions, compile_package_with_native_functions};
+use palang_virtual_machine::llm::storage_error::StorageError;
+
+use crate::{cli_error::CliError, native_util::{get_native_functions, get_native_signatures}};
 
 #[derive(Debug, Parser)]
 pub struct CompileArgs {
@@ -15,44 +18,47 @@ pub struct CompileArgs {
     target: Option<PathBuf>,
 }
 
-pub fn compile_command(args: &CompileArgs) -> Result<(), String> {
+pub fn compile_command(args: &CompileArgs) -> Result<(), CliError> {
     if let Some(target) = &args.target {
         if let Some(source) = &args.source {
-            return compile_file_to_target(&source, &target);
+            compile_file_to_target(source, target)
         }
         else if let Some(package) = &args.package {
-            return compile_package_to_target(&package, &target);
+            compile_package_to_target(package, target)
+        }
+        else if let Ok(package) = env::current_dir() {
+            compile_package_to_target(&package, target)
         }
         else {
-            if let Ok(package) = env::current_dir().map_err(|e| e.to_string()) {
-                return compile_package_to_target(&package, &target);
-            }
-            else {
-                return Err("Working directory not found, please specify a source or package directory".to_string());
-            }
+            Err(CliError::Other("Working directory not found, please specify a source or package directory".to_string()))
         }
     }
     else {
-        return Err("No compilation target specified".to_string());
+        Err(CliError::Other("No compilation target specified".to_string()))
     }
 }
 
-fn compile_file_to_target(source_path: &Path, target_path: &Path) -> Result<(), String> {
+fn compile_file_to_target(source_path: &Path, target_path: &Path) -> Result<(), CliError> {
     println!("Compiling {:?} to {:?}", source_path, target_path);
 
-    let source_code: String = fs::read_to_string(source_path).map_err(|e| e.to_string())?;
-    let assembly_code: String = compile_file(&source_code)?;
+    let source_code: String = fs::read_to_string(source_path)
+        .map_err(|e| StorageError::Read { file: source_path.to_path_buf(), source: e })?;
+    let assembly_code: String = compile_file_with_native_functions(&source_code, &get_native_signatures(&get_native_functions()))
+        .map_err(|e| CliError::Compile { file: source_path.to_path_buf(), source: Box::new(e) })?;
 
-    fs::write(&target_path, assembly_code).map_err(|e| e.to_string())?;
+    fs::write(target_path, assembly_code)
+        .map_err(|e| StorageError::Write { file: target_path.to_path_buf(), source: e })?;
 
     Ok(())
 }
 
-fn compile_package_to_target(package_root: &Path, target_path: &Path) -> Result<(), String> {
+fn compile_package_to_target(package_root: &Path, target_path: &Path) -> Result<(), CliError> {
     println!("Compiling package {:?} to {:?}", package_root, target_path);
 
-    let assembly_code: String = compile_package(&package_root)?;
-    fs::write(&target_path, assembly_code).map_err(|e| e.to_string())?;
+    let assembly_code: String = compile_package_with_native_functions(package_root, &get_native_signatures(&get_native_functions()))
+        .map_err(|e| CliError::Compile { file: package_root.to_path_buf(), source: Box::new(e) })?;
+    fs::write(target_path, assembly_code)
+        .map_err(|e| StorageError::Write { file: target_path.to_path_buf(), source: e })?;
 
     Ok(())
 }
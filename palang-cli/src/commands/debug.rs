@@ -0,0 +1,156 @@
+use std::{io::{self, Write}, path::PathBuf};
+
+use clap::Parser;
+use palang_virtual_machine::{boot_machine, choose_llm_with_configuration, virtualization::{debugger::{Breakpoint, DebugCommand, DebugHandler, DebugState, Debugger}, runtime_error::RuntimeError, virtual_machine::VirtualMachine}};
+use tokio::runtime::Runtime;
+
+use crate::{cli_error::CliError, commands::run::get_assembly, native_util::register_native_functions, server_proxy::models::profile::load_profile_from_directory};
+
+const HELP: &str = "\
+c, continue          run until the next breakpoint
+s, step              step to the next instruction, into invoked functions
+n, next              step to the next instruction of this function
+o, out               run until this function returns
+p, print <variable>  print a variable
+v, variables         print every variable
+r, registry          print the invocation registry
+bt, backtrace        print the call stack
+l, list              print the instructions of this function
+b, break [<breakpoint>]   add a breakpoint (function or function:instruction), or list them
+d, delete <breakpoint>    remove a breakpoint
+q, quit              stop the execution";
+
+#[derive(Debug, Parser)]
+pub struct DebugArgs {
+    #[arg(value_name = "ASSEMBLY FILE")]
+    assembly_file: PathBuf,
+
+    #[arg(short, long)]
+    task: String,
+
+    #[arg(short, long, num_args = 1.., value_delimiter = ',')]
+    args: Vec<String>,
+
+    #[arg(short, long)]
+    profile: String,
+
+    #[arg(long)]
+    profiles_directory: Option<PathBuf>,
+
+    /// Function or function:instruction to pause at, pauses at the first instruction when omitted
+    #[arg(short, long = "break", value_name = "BREAKPOINT")]
+    breakpoints: Vec<Breakpoint>,
+}
+
+pub fn debug_command(args: &DebugArgs) -> Result<(), CliError> {
+    let profile = load_profile_from_directory(&args.profile, &args.profiles_directory)?;
+    let llm = choose_llm_with_configuration(&profile.llm, &profile.llm_configuration)
+        .map_err(|e| CliError::Llm { llm: profile.llm.clone(), source: e })?;
+    let assembly = get_assembly(&args.assembly_file)?;
+
+    let mut vm: VirtualMachine = boot_machine(&llm);
+    register_native_functions(&mut vm);
+    vm.load_assembly(&assembly)
+        .map_err(|e| CliError::Load { file: args.assembly_file.clone(), source: e })?;
+
+    let debugger: Debugger = Debugger::new(ConsoleDebugHandler {}).with_breakpoints(&args.breakpoints);
+    vm.set_debugger(
+        match args.breakpoints.is_empty() {
+            true => debugger.stop_on_entry(),
+            false => debugger,
+        }
+    );
+
+    let runtime: Runtime = tokio::runtime::Runtime::new().unwrap();
+    let result: Result<String, RuntimeError> = runtime.block_on(async {
+        vm.execute(&args.task, &args.args, &profile.get_model_settings()).await.await
+    });
+
+    match result {
+        Ok(output) => {
+            println!("{}", output);
+            Ok(())
+        },
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Reads debugger commands from the standard input.
+struct ConsoleDebugHandler {}
+
+impl DebugHandler for ConsoleDebugHandler {
+    fn on_pause(&mut self, state: &DebugState, breakpoints: &mut Vec<Breakpoint>) -> DebugCommand {
+        println!(
+            "Paused in {} at {}: {}",
+            state.function.name,
+            state.program_counter,
+            state.instruction,
+        );
+
+        loop {
+            print!("(debug) ");
+            let _ = io::stdout().flush();
+
+            let mut line: String = String::new();
+            match io::stdin().read_line(&mut line) {
+                Ok(0) | Err(_) => return DebugCommand::Abort,
+                Ok(_) => {},
+            }
+
+            let (command, argument) = match line.trim().split_once(' ') {
+                Some((command, argument)) => (command, argument.trim()),
+                None => (line.trim(), ""),
+            };
+
+            match command {
+                "c" | "continue" => return DebugCommand::Continue,
+                "s" | "step" => return DebugCommand::StepInto,
+                "n" | "next" => return DebugCommand::StepOver,
+                "o" | "out" => return DebugCommand::StepOut,
+                "q" | "quit" => return DebugCommand::Abort,
+                "p" | "print" => match state.variables.get(argument) {
+                    Some(value) => println!("{} = {}", argument, value),
+                    None => println!("Variable {} not found", argument),
+                },
+                "v" | "variables" => {
+                    let mut variables: Vec<(&String, &String)> = state.variables.iter().collect();
+                    variables.sort();
+                    for (name, value) in variables {
+                        println!("{} = {}", name, value);
+                    }
+                },
+                "r" | "registry" => match state.invocation_registry {
+                    Some(value) => println!("@invocation_registry = {}", value),
+                    None => println!("@invocation_registry is empty"),
+                },
+                "bt" | "backtrace" => {
+                    for (depth, task) in state.call_stack.iter().rev().enumerate() {
+                        println!("#{} {}", depth, task);
+                    }
+                },
+                "l" | "list" => {
+                    for (index, instruction) in state.function.instructions.iter().enumerate() {
+                        let marker: &str = if index == state.program_counter { "=>" } else { "  " };
+                        println!("{} {:>3} {}", marker, index, instruction);
+                    }
+                },
+                "b" | "break" if argument.is_empty() => {
+                    for breakpoint in breakpoints.iter() {
+                        println!("{}", breakpoint);
+                    }
+                },
+                "b" | "break" => match argument.parse::<Breakpoint>() {
+                    Ok(breakpoint) => breakpoints.push(breakpoint),
+                    Err(e) => println!("{}", e),
+                },
+                "d" | "delete" => match argument.parse::<Breakpoint>() {
+                    Ok(breakpoint) => breakpoints.retain(|existing| *existing != breakpoint),
+                    Err(e) => println!("{}", e),
+                },
+                "h" | "help" => println!("{}", HELP),
+                "" => {},
+                _ => println!("Unknown command {}, type help to list the commands", command),
+            }
+        }
+    }
+}
@@ -1,5 +1,6 @@
 pub mod compile;
 pub mod run;
+pub mod debug;
 pub mod serve;
 pub mod connect;
 pub mod disconnect;
@@ -66,7 +66,7 @@ pub fn profiles_command(args: &ProfilesArgs) -> Result<(), String> {
 
 fn new_profile_command(name: &String, from: &Option<PathBuf>) -> Result<(), String> {
     if let Some(from) = from {
-        let profile: Profile = load_profile(from)?;
+        let profile: Profile = load_profile(from).map_err(|e| e.to_string())?;
 
         if ServerProxy::is_connected() {
             ServerProxy::find_server()?.add_profile(&profile)
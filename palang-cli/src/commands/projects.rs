@@ -104,13 +104,13 @@ fn new_assembly_command(
                         },
                         "palang" => {
                             let source_code: String = fs::read_to_string(path).map_err(|e| e.to_string())?;
-                            compile_file(&source_code)
+                            compile_file(&source_code).map_err(|e| e.to_string())
                         },
                         _ => Err(format!("Unsupported file extension: {}", extension)),
                     }
                 },
                 None => {
-                    compile_package(&path)
+                    compile_package(&path).map_err(|e| e.to_string())
                 },
             }?;
 
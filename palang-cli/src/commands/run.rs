@@ -1,11 +1,19 @@
-use std::{fs, path::PathBuf};
+use std::{fs, io::Write, path::PathBuf, sync::{atomic::{AtomicBool, Ordering}, Arc}, time::Duration};
 
 use clap::Parser;
-use palang_compiler::compile_file;
-use palang_virtual_machine::{assembly::{assembly::Assembly, loader::load_assembly}, boot_machine, choose_llm, load_assembly_file, virtualization::virtual_machine::VirtualMachine};
+use palang_compiler::compile_file_with_native_functions;
+use palang_virtual_machine::{assembly::{assembly::Assembly, loader::load_assembly}, boot_machine, choose_llm_with_configuration, llm::{cassette::{Cassette, CassetteMode}, llm::LargeLanguageModel, mock_llm::{load_fixtures, MockFixtures, MockLargeLanguageModel}, price_table::PriceTable, rate_limiter::RateLimiter, response_cache::ResponseCache, retry_policy::RetryPolicy, storage_error::StorageError}, load_assembly_file, virtualization::{budget::Budget, cancellation_token::CancellationToken, execution_event::{ExecutionEvent, ExecutionEventKind}, execution_report::ExecutionReport, runtime_error::RuntimeError, trace_writer::TraceWriter, virtual_machine::VirtualMachine}};
+use serde_json::Value;
 use tokio::runtime::Runtime;
+#[cfg(feature = "opentelemetry")]
+use palang_virtual_machine::virtualization::telemetry::{self, OpenTelemetryObserver};
 
-use crate::server_proxy::models::profile::load_profile_from_directory;
+use crate::{
+    cli_error::CliError,
+    native_util::{get_native_functions, get_native_signatures, register_native_functions},
+    server_proxy::models::profile::load_profile_from_directory,
+    session_util::{load_session_from_directory, save_session_to_directory}
+};
 
 #[derive(Debug, Parser)]
 pub struct RunArgs {
@@ -23,69 +31,398 @@ pub struct RunArgs {
 
     #[arg(long)]
     profiles_directory: Option<PathBuf>,
+
+    #[arg(short, long)]
+    session: Option<String>,
+
+    #[arg(long)]
+    sessions_directory: Option<PathBuf>,
+
+    #[arg(long)]
+    cassette: Option<PathBuf>,
+
+    #[arg(long, default_value = "replay", requires = "cassette")]
+    cassette_mode: CassetteMode,
+
+    #[arg(long)]
+    no_cache: bool,
+
+    /// Run independent calls one after another
+    #[arg(long)]
+    no_parallel: bool,
+
+    #[arg(long, value_name = "DEPTH")]
+    max_call_depth: Option<usize>,
+
+    #[arg(long)]
+    report: bool,
+
+    #[arg(long, value_name = "SECONDS")]
+    timeout: Option<u64>,
+
+    #[arg(long, value_name = "SECONDS")]
+    invocation_timeout: Option<u64>,
+
+    /// Abort once this many calls to the model have been made, overriding the profile's budget
+    #[arg(long, value_name = "CALLS")]
+    max_llm_calls: Option<u64>,
+
+    /// Abort once this many tokens have been used, overriding the profile's budget
+    #[arg(long, value_name = "TOKENS")]
+    max_total_tokens: Option<u64>,
+
+    /// Abort once the execution has run this long, overriding the profile's budget
+    #[arg(long, value_name = "SECONDS")]
+    max_duration: Option<u64>,
+
+    #[arg(long)]
+    cost: bool,
+
+    #[arg(long)]
+    price_table: Option<PathBuf>,
+
+    #[arg(long)]
+    stream: bool,
+
+    #[arg(long, value_name = "TRACE FILE")]
+    trace: Option<PathBuf>,
+
+    /// Print the rendered prompts instead of sending them, answering them with placeholders
+    #[arg(long, conflicts_with_all = ["cassette", "stream"])]
+    dry_run: bool,
+
+    /// Response given to every prompt during a dry run
+    #[arg(long, requires = "dry_run")]
+    placeholder: Option<String>,
+
+    /// Mock fixtures choosing the response given to each prompt during a dry run
+    #[arg(long, requires = "dry_run")]
+    fixtures: Option<PathBuf>,
 }
 
-pub fn run_command(args: &RunArgs) -> Result<(), String> {
+pub fn run_command(args: &RunArgs) -> Result<(), CliError> {
     match load_profile_from_directory(
         &args.profile,
         &args.profiles_directory,
     ) {
         Ok(profile) => {
-            match choose_llm(&profile.llm) {
+            let replaying: bool = args.cassette.is_some() && args.cassette_mode == CassetteMode::Replay;
+            let llm: Result<LargeLanguageModel, CliError> = match (&args.cassette, args.dry_run) {
+                (_, true) => get_placeholder_llm(&args.placeholder, &args.fixtures),
+                // Replays need no provider, so they run without its credentials
+                (Some(cassette), false) if replaying => Cassette::open(cassette, args.cassette_mode)
+                    .map(|cassette| LargeLanguageModel::new_replay(&profile.llm, &cassette))
+                    .map_err(CliError::from),
+                _ => choose_llm_with_configuration(&profile.llm, &profile.llm_configuration)
+                    .map_err(|e| CliError::Llm { llm: profile.llm.clone(), source: e }),
+            };
+
+            match llm {
                 Ok(llm) => {
+                    let llm: LargeLanguageModel = match &profile.retry_policy {
+                        Value::Null => llm,
+                        retry_policy => llm.with_retry_policy(&RetryPolicy::from_configuration(retry_policy)?),
+                    };
+                    let llm: LargeLanguageModel = match &profile.rate_limits {
+                        Value::Null => llm,
+                        _ if args.dry_run || replaying => llm,
+                        rate_limits => llm.with_rate_limiter(&RateLimiter::from_configuration(rate_limits)?),
+                    };
+                    let llm: LargeLanguageModel = match &args.cassette {
+                        Some(cassette) if !replaying => llm.with_cassette(&Cassette::open(cassette, args.cassette_mode)?),
+                        _ => llm,
+                    };
+
                     match get_assembly(&args.assembly_file) {
                         Ok(asm) => {
                             let mut vm: VirtualMachine = boot_machine(&llm);
-                            vm.load_assembly(&asm);
+                            register_native_functions(&mut vm);
+                            vm.load_assembly(&asm)
+                                .map_err(|e| CliError::Load { file: args.assembly_file.clone(), source: e })?;
+
+                            if !profile.response_cache.is_null() && !args.no_cache && !args.dry_run {
+                                vm.set_response_cache(&ResponseCache::from_configuration(&profile.response_cache)?);
+                            }
+
+                            if args.dry_run {
+                                vm.add_observer(print_rendered_prompt);
+                            }
+
+                            if let Some(trace) = &args.trace {
+                                vm.add_observer(TraceWriter::create(trace)?);
+                            }
+
+                            #[cfg(feature = "opentelemetry")]
+                            let tracer_provider = match telemetry::is_configured() {
+                                true => {
+                                    let tracer_provider = telemetry::new_tracer_provider()?;
+                                    vm.add_observer(OpenTelemetryObserver::new(&tracer_provider));
+                                    Some(tracer_provider)
+                                },
+                                false => None,
+                            };
+
+                            vm.set_parallel_execution(!args.no_parallel);
+                            if let Some(max_call_depth) = args.max_call_depth {
+                                vm.set_max_call_depth(max_call_depth);
+                            }
+                            vm.set_task_timeout(args.timeout.map(Duration::from_secs));
+                            vm.set_invocation_timeout(args.invocation_timeout.map(Duration::from_secs));
+                            vm.set_budget(
+                                &Budget::from_configuration(&profile.budget)?.with_overrides(&Budget {
+                                    max_llm_calls: args.max_llm_calls,
+                                    max_tokens: args.max_total_tokens,
+                                    max_duration: args.max_duration.map(Duration::from_secs),
+                                })
+                            );
+
+                            let streamed: Arc<AtomicBool> = Arc::new(AtomicBool::new(false));
+                            if args.stream {
+                                let streamed: Arc<AtomicBool> = streamed.clone();
+                                vm.set_stream_handler(move |delta| {
+                                    streamed.store(true, Ordering::Relaxed);
+                                    print!("{}", delta);
+                                    let _ = std::io::stdout().flush();
+                                });
+                            }
 
                             let runtime: Runtime = tokio::runtime::Runtime::new().unwrap();
-                            let result: Result<String, String> = runtime.block_on(async {
-                                vm.execute(
-                                    &args.task,
-                                    &args.args,
-                                    &profile.get_model_settings()
-                                ).await.await
+
+                            let cancellation: CancellationToken = CancellationToken::new();
+                            vm.set_cancellation_token(&cancellation);
+                            runtime.spawn(async move {
+                                if tokio::signal::ctrl_c().await.is_ok() {
+                                    eprintln!("Cancelling...");
+                                    cancellation.cancel();
+                                }
                             });
+                            let result: Result<String, RuntimeError> = match &args.session {
+                                Some(session) => {
+                                    vm.open_session(
+                                        &load_session_from_directory(session, &args.sessions_directory)?
+                                    );
+
+                                    let result: Result<String, RuntimeError> = runtime.block_on(async {
+                                        vm.execute_in_session(
+                                            session,
+                                            &args.task,
+                                            &args.args,
+                                            &profile.get_model_settings()
+                                        ).await
+                                    });
+
+                                    // Placeholder answers must not end up in the history of the real session
+                                    if let Some(session) = vm.close_session(session).filter(|_| !args.dry_run) {
+                                        save_session_to_directory(&session, &args.sessions_directory)?;
+                                    }
+
+                                    result
+                                },
+                                None => {
+                                    runtime.block_on(async {
+                                        vm.execute(
+                                            &args.task,
+                                            &args.args,
+                                            &profile.get_model_settings()
+                                        ).await.await
+                                    })
+                                },
+                            };
+                            if streamed.load(Ordering::Relaxed) {
+                                println!();
+                            }
+
+                            // Flushes the spans still waiting to be exported
+                            #[cfg(feature = "opentelemetry")]
+                            if let Some(tracer_provider) = tracer_provider {
+                                if let Err(e) = tracer_provider.shutdown() {
+                                    eprintln!("Could not export traces ({})", e);
+                                }
+                            }
+
+                            if args.report {
+                                eprintln!(
+                                    "{}",
+                                    serde_yaml::to_string(vm.get_execution_report())
+                                        .map_err(|e| CliError::Other(format!("Could not write the report ({})", e)))?
+                                );
+                            }
+
+                            if args.cost {
+                                let price_table: PriceTable = match &args.price_table {
+                                    Some(price_table) => PriceTable::load(price_table)?,
+                                    None => PriceTable::from_configuration(&profile.prices)?,
+                                };
+                                print_cost_summary(vm.get_execution_report(), &price_table);
+                            }
+
                             match result {
                                 Ok(output) => {
-                                    println!("{}", output);
+                                    // Streamed output has already been printed
+                                    if !streamed.load(Ordering::Relaxed) {
+                                        println!("{}", output);
+                                    }
                                     Ok(())
                                 },
-                                Err(e) => {
-                                    return Err(format!("Could not execute program ({})", e));
-                                },
+                                Err(e) => Err(e.into()),
                             }
                         },
-                        Err(e) => {
-                            return Err(
-                                format!(
-                                    "Could not find assembly file {:?} ({})",
-                                    args.assembly_file,
-                                    e
-                                )
-                            );
-                        }
+                        Err(e) => Err(e),
                     }
                 },
-                Err(e) => {
-                    return Err(format!("Specified large language model \"{}\" not found ({})", profile.llm, e));
-                },
+                Err(e) => Err(e),
             }
         },
-        Err(e) => Err(format!("{}", e)),
+        Err(e) => Err(e.into()),
     }
 }
 
-fn get_assembly(file_path: &PathBuf) -> Result<Assembly, String> {
+fn get_placeholder_llm(placeholder: &Option<String>, fixtures: &Option<PathBuf>) -> Result<LargeLanguageModel, CliError> {
+    let mut fixtures: MockFixtures = match fixtures {
+        Some(fixtures) => load_fixtures(fixtures)?,
+        None => MockFixtures::default(),
+    };
+    fixtures.default = placeholder
+        .clone()
+        .or(fixtures.default)
+        .or_else(|| Some("placeholder".to_string()));
+
+    Ok(LargeLanguageModel::new_mock(MockLargeLanguageModel::new(&fixtures)?))
+}
+
+fn print_rendered_prompt(event: &ExecutionEvent) {
+    if let Some(rendered_prompt) = format_rendered_prompt(event) {
+        println!("{}", rendered_prompt);
+    }
+}
+
+fn format_rendered_prompt(event: &ExecutionEvent) -> Option<String> {
+    match &event.kind {
+        ExecutionEventKind::PromptRendered { prompt, system, user } => Some(
+            format!("--- {} (system) ---\n{}\n--- {} (user) ---\n{}\n", prompt, system, prompt, user)
+        ),
+        _ => None,
+    }
+}
+
+fn print_cost_summary(report: &ExecutionReport, price_table: &PriceTable) {
+    let mut total_cost: f64 = 0.0;
+
+    for (model, usage) in &report.usage_per_model {
+        let cost: String = match price_table.estimate_cost(model, &usage.tokens) {
+            Some(cost) => {
+                total_cost += cost;
+                format!("{:.6}", cost)
+            },
+            None => "unknown (no price)".to_string(),
+        };
+
+        eprintln!(
+            "{}: {} invocations, {} prompt tokens, {} completion tokens, estimated cost {}",
+            model,
+            usage.invocations,
+            usage.tokens.prompt_tokens,
+            usage.tokens.completion_tokens,
+            cost,
+        );
+    }
+
+    eprintln!("Total estimated cost: {:.6}", total_cost);
+}
+
+pub fn get_assembly(file_path: &PathBuf) -> Result<Assembly, CliError> {
     let extension = file_path.extension().and_then(|ext| ext.to_str()).unwrap_or("");
+    let load_error = |e| CliError::Load { file: file_path.clone(), source: e };
 
     match extension {
-        "palasm" => load_assembly_file(file_path),
+        "palasm" => load_assembly_file(file_path).map_err(load_error),
         "palang" => {
-            let source_code: String = fs::read_to_string(file_path).map_err(|e| e.to_string())?;
-            let assembly_code: String = compile_file(&source_code)?;
-            load_assembly(&assembly_code)
+            let source_code: String = fs::read_to_string(file_path)
+                .map_err(|e| StorageError::Read { file: file_path.clone(), source: e })?;
+            let assembly_code: String = compile_file_with_native_functions(&source_code, &get_native_signatures(&get_native_functions()))
+                .map_err(|e| CliError::Compile { file: file_path.clone(), source: Box::new(e) })?;
+            load_assembly(&assembly_code).map_err(load_error)
         },
-        _ => Err(format!("Unsupported file extension: {}", extension)),
+        _ => Err(CliError::Other(format!("Unsupported file extension: {}", extension))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use palang_virtual_machine::{llm::message::Message, virtualization::session::Session};
+
+    use super::*;
+
+    const ASSEMBLY: &str = "PALASM 1
+MODULE tests
+PROMPT tests/greet
+ARGUMENTS name
+RETURNS std/text
+START
+Greet @{name}.
+END";
+
+    #[test]
+    fn formats_rendered_prompts() {
+        let event: ExecutionEvent = ExecutionEvent::new(
+            1,
+            None,
+            ExecutionEventKind::PromptRendered {
+                prompt: "tests/greet".to_string(),
+                system: "Be kind.".to_string(),
+                user: "Greet Ada.".to_string(),
+            },
+        );
+
+        assert_eq!(
+            format_rendered_prompt(&event).unwrap(),
+            "--- tests/greet (system) ---\nBe kind.\n--- tests/greet (user) ---\nGreet Ada.\n",
+        );
+    }
+
+    #[test]
+    fn dry_runs_call_no_model_and_leave_sessions_untouched() {
+        let directory: PathBuf = env::temp_dir().join(format!("palang-dry-run-{}", std::process::id()));
+        let profiles_directory: PathBuf = directory.join("profiles");
+        let sessions_directory: PathBuf = directory.join("sessions");
+        fs::create_dir_all(&profiles_directory).unwrap();
+        fs::create_dir_all(&sessions_directory).unwrap();
+
+        // Any request to this provider fails, so the run only succeeds without one
+        fs::write(
+            profiles_directory.join("unreachable.yaml"),
+            "llm: ollama\nmodel: llama3\ntemperature: 0.0\nmax_tokens: 10\nllm_configuration:\n  base_url: http://127.0.0.1:9\n",
+        ).unwrap();
+        fs::write(directory.join("greet.palasm"), ASSEMBLY).unwrap();
+
+        let mut session: Session = Session::new("chat");
+        session.history.push(Message::user("Hello"));
+        session.save(&sessions_directory.join("chat.json")).unwrap();
+        let saved_session: String = fs::read_to_string(sessions_directory.join("chat.json")).unwrap();
+
+        let args: RunArgs = RunArgs::try_parse_from([
+            "run",
+            directory.join("greet.palasm").to_str().unwrap(),
+            "--task", "tests/greet",
+            "--args", "Ada",
+            "--profile", "unreachable",
+            "--profiles-directory", directory.to_str().unwrap(),
+            "--session", "chat",
+            "--sessions-directory", sessions_directory.to_str().unwrap(),
+            "--trace", directory.join("trace.jsonl").to_str().unwrap(),
+            "--dry-run",
+        ]).unwrap();
+        let result: Result<(), CliError> = run_command(&args);
+        let trace: String = fs::read_to_string(directory.join("trace.jsonl")).unwrap();
+        let session_after: String = fs::read_to_string(sessions_directory.join("chat.json")).unwrap();
+        fs::remove_dir_all(&directory).unwrap();
+
+        assert!(result.is_ok(), "{:?}", result);
+        assert!(trace.contains(r#""event":"prompt_rendered""#), "{}", trace);
+        assert!(trace.contains(r#"Greet {parameter \"name\": Ada}."#), "{}", trace);
+        assert!(!trace.contains(r#""provider":"ollama""#), "{}", trace);
+        assert_eq!(session_after, saved_session);
     }
 }
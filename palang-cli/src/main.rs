@@ -1,14 +1,24 @@
+mod cli_error;
 mod dialog_utils;
 mod assembly_path_util;
+mod session_util;
+mod native_util;
 mod commands;
 mod server_proxy;
 
+use std::process::ExitCode;
+
 use clap::{Parser, Subcommand};
+use cli_error::CliError;
 use commands::{
     compile::{
         compile_command,
         CompileArgs
     },
+    debug::{
+        debug_command,
+        DebugArgs
+    },
     connect::{
         connect_command,
         ConnectArgs
@@ -45,7 +55,10 @@ enum Command {
     Compile(CompileArgs),
 
     #[command(about = "Run a compiled program")]
-    Run(RunArgs),
+    Run(Box<RunArgs>),
+
+    #[command(about = "Debug a program step by step")]
+    Debug(DebugArgs),
 
     #[command(about = "Start a Palang server")]
     Serve(ServeArgs),
@@ -76,13 +89,17 @@ struct Args {
     target_file: String,
 }
 
-fn main() {
-    if let Err(e) = execute_command() {
-        eprintln!("{}", e);
+fn main() -> ExitCode {
+    match execute_command() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{}", e);
+            ExitCode::from(e.get_exit_code())
+        },
     }
 }
 
-fn execute_command() -> Result<(), String> {
+fn execute_command() -> Result<(), CliError> {
     match Cli::parse().command {
         Command::Compile(args) => {
             compile_command(&args)
@@ -90,23 +107,26 @@ fn execute_command() -> Result<(), String> {
         Command::Run(args) => {
             run_command(&args)
         },
+        Command::Debug(args) => {
+            debug_command(&args)
+        },
         Command::Serve(args) => {
-            serve_command(&args)
+            serve_command(&args).map_err(CliError::Other)
         },
         Command::Connect(args) => {
-            connect_command(&args)
+            connect_command(&args).map_err(CliError::Other)
         },
         Command::Disconnect => {
-            disconnect_command()
+            disconnect_command().map_err(CliError::Other)
         },
         Command::Status => {
-            status_command()
+            status_command().map_err(CliError::Other)
         },
         Command::Projects(args) => {
-            projects_command(&args)
+            projects_command(&args).map_err(CliError::Other)
         },
         Command::Profiles(args) => {
-            profiles_command(&args)
+            profiles_command(&args).map_err(CliError::Other)
         },
     }
 }
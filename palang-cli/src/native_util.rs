@@ -0,0 +1,97 @@
+use std::collections::HashMap;
+
+use palang_compiler::analyze::{function_info::FunctionInfo, parameter_info::ParameterInfo};
+use palang_virtual_machine::{native::{host_functions::get_host_functions, native_function::NativeFunction}, virtualization::virtual_machine::VirtualMachine};
+
+/// The native functions Palang code run by the CLI can call.
+pub fn get_native_functions() -> Vec<NativeFunction> {
+    get_host_functions()
+}
+
+pub fn register_native_functions(vm: &mut VirtualMachine) {
+    for function in get_native_functions() {
+        vm.register_native_function(&function);
+    }
+}
+
+/// The signatures the compiler checks the calls to native functions against.
+pub fn get_native_signatures(functions: &[NativeFunction]) -> HashMap<String, FunctionInfo> {
+    functions
+        .iter()
+        .map(|function| {
+            let parameters: Vec<ParameterInfo> = function.parameters
+                .iter()
+                .map(|parameter| ParameterInfo {
+                    name: parameter.name.clone(),
+                    full_type: parameter.full_type.clone(),
+                    is_array: false,
+                })
+                .collect();
+
+            (
+                function.name.clone(),
+                FunctionInfo { parameters, return_type: function.return_type.clone() },
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use palang_compiler::compile_file_with_native_functions;
+    use palang_virtual_machine::{assembly::loader::load_assembly, boot_machine, llm::{llm::LargeLanguageModel, mock_llm::{MockFixtures, MockLargeLanguageModel}, model_settings::ModelSettings}};
+
+    use super::*;
+
+    fn compile(source: &str) -> Result<String, String> {
+        compile_file_with_native_functions(source, &get_native_signatures(&get_native_functions()))
+            .map_err(|e| e.to_string())
+    }
+
+    #[test]
+    fn converts_native_signatures() {
+        let signatures: HashMap<String, FunctionInfo> = get_native_signatures(&get_native_functions());
+        let find: &FunctionInfo = &signatures["host/regex/find"];
+
+        assert_eq!(find.return_type, "std/text");
+        assert_eq!(
+            find.parameters.iter().map(|parameter| (parameter.name.as_str(), parameter.full_type.as_str())).collect::<Vec<_>>(),
+            vec![("pattern", "std/text"), ("text", "std/text")],
+        );
+    }
+
+    #[test]
+    fn checks_calls_to_native_functions() {
+        let wrong_arity: String = compile(
+            "module tests\n\nfunction year(text: std::Text) -> std::Text {\n    return host::regex::find(text)\n}\n"
+        ).unwrap_err();
+        assert!(wrong_arity.contains("expects 2 arguments"), "{}", wrong_arity);
+
+        let wrong_type: String = compile(
+            "module tests\n\nfunction name(json: std::Json, path: std::Json) -> std::Json {\n    return host::json::query(json, path)\n}\n"
+        ).unwrap_err();
+        assert!(wrong_type.contains("should be std/text"), "{}", wrong_type);
+    }
+
+    #[test]
+    fn runs_compiled_calls_to_native_functions() {
+        let assembly: String = compile(
+            "module tests\n\nfunction year(pattern: std::Text, text: std::Text) -> std::Text {\n    return host::regex::find(pattern, text)\n}\n"
+        ).unwrap();
+
+        let llm: LargeLanguageModel = LargeLanguageModel::new_mock(MockLargeLanguageModel::new(&MockFixtures::default()).unwrap());
+        let mut vm: VirtualMachine = boot_machine(&llm);
+        register_native_functions(&mut vm);
+        vm.load_assembly(&load_assembly(&assembly).unwrap()).unwrap();
+
+        let result: String = tokio::runtime::Runtime::new().unwrap().block_on(async {
+            vm.execute(
+                &"tests/year".to_string(),
+                &vec![r"\d{4}".to_string(), "Founded in 1889.".to_string()],
+                &ModelSettings { model: "mock".to_string(), temperature: 0.0, max_tokens: 10 },
+            ).await.await
+        }).unwrap();
+
+        assert_eq!(result, "1889");
+    }
+}
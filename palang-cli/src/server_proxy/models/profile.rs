@@ -1,7 +1,8 @@
 use std::{env, fs, path::PathBuf};
 
-use palang_virtual_machine::llm::model_settings::ModelSettings;
+use palang_virtual_machine::llm::{model_settings::ModelSettings, storage_error::StorageError};
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use tabled::Tabled;
 
 #[derive(Debug, Serialize, Deserialize, Tabled)]
@@ -10,6 +11,30 @@ pub struct Profile {
     pub model: String,
     pub temperature: f32,
     pub max_tokens: u32,
+
+    #[serde(default, skip_serializing_if = "Value::is_null")]
+    #[tabled(skip)]
+    pub llm_configuration: Value,
+
+    #[serde(default, skip_serializing_if = "Value::is_null")]
+    #[tabled(skip)]
+    pub response_cache: Value,
+
+    #[serde(default, skip_serializing_if = "Value::is_null")]
+    #[tabled(skip)]
+    pub retry_policy: Value,
+
+    #[serde(default, skip_serializing_if = "Value::is_null")]
+    #[tabled(skip)]
+    pub rate_limits: Value,
+
+    #[serde(default, skip_serializing_if = "Value::is_null")]
+    #[tabled(skip)]
+    pub prices: Value,
+
+    #[serde(default, skip_serializing_if = "Value::is_null")]
+    #[tabled(skip)]
+    pub budget: Value,
 }
 
 impl Profile {
@@ -19,7 +44,7 @@ impl Profile {
         temperature: f32,
         max_tokens: u32,
     ) -> Self {
-        Profile { llm, model, temperature, max_tokens }
+        Profile { llm, model, temperature, max_tokens, llm_configuration: Value::Null, response_cache: Value::Null, retry_policy: Value::Null, rate_limits: Value::Null, prices: Value::Null, budget: Value::Null }
     }
 
     pub fn get_model_settings(&self) -> ModelSettings {
@@ -31,11 +56,11 @@ impl Profile {
     }
 }
 
-pub fn load_profile(file_path: &PathBuf) -> Result<Profile, String> {
+pub fn load_profile(file_path: &PathBuf) -> Result<Profile, StorageError> {
     let raw_profile: String = fs::read_to_string(file_path)
-                                 .map_err(|e| e.to_string())?;
+                                 .map_err(|e| StorageError::Read { file: file_path.clone(), source: e })?;
     let profile: Profile = serde_yaml::from_str(&raw_profile)
-                                      .map_err(|e| e.to_string())?;
+                                      .map_err(|e| StorageError::Yaml { file: file_path.clone(), source: e })?;
 
     Ok(profile)
 }
@@ -49,7 +74,7 @@ pub fn write_profile(file_path: &PathBuf, profile: &Profile) -> Result<(), Strin
     Ok(())
 }
 
-pub fn load_profile_from_directory(name: &String, directory: &Option<PathBuf>) -> Result<Profile, String> {
+pub fn load_profile_from_directory(name: &String, directory: &Option<PathBuf>) -> Result<Profile, StorageError> {
     let file_name_with_extension = format!("{}.yaml", name);
 
     let base_directory = (if let Some(dir) = directory {
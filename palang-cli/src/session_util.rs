@@ -0,0 +1,72 @@
+use std::{env, fs, path::PathBuf};
+
+use palang_virtual_machine::{llm::storage_error::StorageError, virtualization::session::Session};
+
+use crate::cli_error::CliError;
+
+pub fn load_session_from_directory(name: &str, directory: &Option<PathBuf>) -> Result<Session, CliError> {
+    let file_path: PathBuf = get_sessions_directory(directory).join(get_session_file_name(name)?);
+
+    if file_path.exists() {
+        Ok(Session::load(&file_path)?)
+    }
+    else {
+        Ok(Session::new(name))
+    }
+}
+
+pub fn save_session_to_directory(session: &Session, directory: &Option<PathBuf>) -> Result<(), CliError> {
+    let base_directory: PathBuf = get_sessions_directory(directory);
+    fs::create_dir_all(&base_directory)
+        .map_err(|e| StorageError::Write { file: base_directory.clone(), source: e })?;
+
+    Ok(session.save(&base_directory.join(get_session_file_name(&session.name)?))?)
+}
+
+/// Session names become file names, so they are kept to letters, digits,
+/// `-` and `_` to stay inside the sessions directory.
+fn get_session_file_name(name: &str) -> Result<String, CliError> {
+    let is_valid: bool = !name.is_empty()
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+
+    match is_valid {
+        true => Ok(format!("{}.json", name)),
+        false => Err(CliError::SessionName(name.to_string())),
+    }
+}
+
+fn get_sessions_directory(directory: &Option<PathBuf>) -> PathBuf {
+    if let Some(dir) = directory {
+        dir.clone()
+    } else if let Ok(snap_user_data) = env::var("SNAP_USER_DATA") {
+        PathBuf::from(snap_user_data).join("sessions")
+    } else {
+        dirs::home_dir().unwrap_or_else(|| PathBuf::from(".")).join(".palang").join("sessions")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accepts_simple_names() {
+        assert_eq!(get_session_file_name("chat-2_b").unwrap(), "chat-2_b.json");
+    }
+
+    #[test]
+    fn rejects_names_leaving_the_sessions_directory() {
+        for name in ["", "..", "../../x", "a/b", "a\\b", "/etc/passwd", "a.b"] {
+            assert!(get_session_file_name(name).is_err(), "{:?} was accepted", name);
+        }
+    }
+
+    #[test]
+    fn does_not_save_outside_the_sessions_directory() {
+        let session: Session = Session::new("../escaped");
+        let directory: Option<PathBuf> = Some(env::temp_dir().join("palang-sessions-test"));
+
+        assert!(save_session_to_directory(&session, &directory).is_err());
+        assert!(load_session_from_directory("../escaped", &directory).is_err());
+    }
+}
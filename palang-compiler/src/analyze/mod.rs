@@ -2,4 +2,6 @@ pub mod parameter_info;
 pub mod model_info;
 pub mod prompt_info;
 pub mod function_info;
+pub mod standard_library;
 pub mod semantic_analyzer;
+pub mod semantic_error;
@@ -1,5 +1,7 @@
 use crate::parse::ast_node::ASTNode;
 
+use super::semantic_error::SemanticError;
+
 #[derive(Debug, Clone)]
 pub struct ParameterInfo {
     pub name: String,
@@ -8,7 +10,7 @@ pub struct ParameterInfo {
 }
 
 impl ParameterInfo {
-    pub fn new(name: String, full_type: ASTNode, is_array: bool) -> Result<Self, String> {
+    pub fn new(name: String, full_type: ASTNode, is_array: bool) -> Result<Self, SemanticError> {
         match full_type {
             ASTNode::QualifiedIdentifier(parts) => {
                 Ok(
@@ -19,7 +21,7 @@ impl ParameterInfo {
                     }
                 )
             },
-            _ => Err(format!("Unexpected parameter type: {:?}", full_type))
+            _ => Err(SemanticError::InvalidNode { expected: "a parameter type", found: format!("{:?}", full_type) })
         }
     }
 }
@@ -4,4 +4,5 @@ use super::parameter_info::ParameterInfo;
 pub struct PromptInfo {
     pub parameters: Vec<ParameterInfo>,
     pub return_type: String,
+    pub tools: Vec<String>,
 }
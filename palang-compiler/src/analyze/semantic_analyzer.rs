@@ -5,31 +5,39 @@ use super::{
     function_info::FunctionInfo,
     model_info::ModelInfo,
     parameter_info::ParameterInfo,
-    prompt_info::PromptInfo
+    prompt_info::PromptInfo,
+    semantic_error::SemanticError,
+    standard_library::get_standard_library_prompts
 };
 
+const PROMPT_ANNOTATIONS: [&str; 1] = ["nocache"];
+
 struct SemanticAnalysisContext {
     models: HashMap<String, ModelInfo>,
     prompts: HashMap<String, PromptInfo>,
     functions: HashMap<String, FunctionInfo>,
+    native_functions: HashMap<String, FunctionInfo>,
+    standard_library_prompts: HashMap<String, PromptInfo>,
     module_fully_qualified_name: String,
 }
 
 impl SemanticAnalysisContext {
-    pub fn new() -> Self {
+    pub fn new(native_functions: &HashMap<String, FunctionInfo>) -> Self {
         SemanticAnalysisContext {
             models: HashMap::new(),
             prompts: HashMap::new(),
             functions: HashMap::new(),
+            native_functions: native_functions.clone(),
+            standard_library_prompts: get_standard_library_prompts(),
             module_fully_qualified_name: String::new(),
         }
     }
 
-    pub fn register_model(&mut self, name: String) -> Result<(), String> {
+    pub fn register_model(&mut self, name: String) -> Result<(), SemanticError> {
         let model_already_known: bool = self.models.contains_key(&name);
 
         if model_already_known {
-            Err(format!("Duplicate model definition for \"{}\"", name))
+            Err(SemanticError::Duplicate { kind: "model", name })
         }
         else {
             self.models.insert(name, ModelInfo);
@@ -37,48 +45,58 @@ impl SemanticAnalysisContext {
         }
     }
 
-    pub fn register_prompt(&mut self, name: String, parameters: Vec<ParameterInfo>, return_type: String) -> Result<(), String> {
+    pub fn register_prompt(&mut self, name: String, parameters: Vec<ParameterInfo>, return_type: String, tools: Vec<String>) -> Result<(), SemanticError> {
         let prompt_already_known: bool = self.prompts.contains_key(&name);
 
         if prompt_already_known {
-            Err(format!("Duplicate prompt definition for \"{}\"", name))
+            Err(SemanticError::Duplicate { kind: "prompt", name })
         }
         else {
-            self.prompts.insert(name, PromptInfo { parameters, return_type });
+            self.prompts.insert(name, PromptInfo { parameters, return_type, tools });
             Ok(())
         }
     }
 
-    pub fn register_function(&mut self, name: String, parameters: Vec<ParameterInfo>, return_type: String) -> Result<(), String> {
+    pub fn register_function(&mut self, name: String, parameters: Vec<ParameterInfo>, return_type: String) -> Result<(), SemanticError> {
         let function_already_known: bool = self.functions.contains_key(&name);
 
         if function_already_known {
-            Err(format!("Duplicate function definition for \"{}\"", name))
+            Err(SemanticError::Duplicate { kind: "function", name })
         }
         else {
             self.functions.insert(name, FunctionInfo { parameters, return_type });
             Ok(())
         }
     }
+
+    pub fn get_task_signature(&self, name: &String) -> Option<(Vec<ParameterInfo>, String)> {
+        if let Some(prompt) = self.prompts.get(name).or_else(|| self.standard_library_prompts.get(name)) {
+            return Some((prompt.parameters.clone(), prompt.return_type.clone()));
+        }
+
+        self.functions.get(name)
+                      .or_else(|| self.native_functions.get(name))
+                      .map(|function| (function.parameters.clone(), function.return_type.clone()))
+    }
 }
 
-pub fn analyze_semantics(ast: &ASTNode) -> Result<(), String> {
-    let mut ctx: SemanticAnalysisContext = SemanticAnalysisContext::new();
+pub fn analyze_semantics(ast: &ASTNode, native_functions: &HashMap<String, FunctionInfo>) -> Result<(), SemanticError> {
+    let mut ctx: SemanticAnalysisContext = SemanticAnalysisContext::new(native_functions);
 
     match ast {
         ASTNode::Module {
             name,
             definitions
         } => analyze_module(&mut ctx, name, definitions),
-        _ => Err("Expected module at top level".to_string()),
+        _ => Err(SemanticError::InvalidNode { expected: "a module", found: format!("{:?}", ast) }),
     }
 }
 
-fn analyze_module(ctx: &mut SemanticAnalysisContext, name: &ASTNode, definitions: &[ASTNode]) -> Result<(), String> {
+fn analyze_module(ctx: &mut SemanticAnalysisContext, name: &ASTNode, definitions: &[ASTNode]) -> Result<(), SemanticError> {
     if let ASTNode::QualifiedIdentifier(parts) = name {
         ctx.module_fully_qualified_name = parts.join("/").to_lowercase();
     } else {
-        return Err("Invalid module name".to_string());
+        return Err(SemanticError::InvalidNode { expected: "a module name", found: format!("{:?}", name) });
     }
 
     for definition in definitions {
@@ -86,48 +104,166 @@ fn analyze_module(ctx: &mut SemanticAnalysisContext, name: &ASTNode, definitions
             ASTNode::Model { name, text: _ } => {
                 analyze_model(ctx, name)
             },
-            ASTNode::Prompt { name, parameters, return_type, text: _ } => {
-                analyze_prompt(ctx, name, parameters, return_type)
+            ASTNode::Prompt { name, parameters, return_type, tools, annotations, text: _ } => {
+                analyze_prompt(ctx, name, parameters, return_type, tools, annotations)
             },
             ASTNode::Function { name, parameters, return_type, instructions } => {
                 analyze_function(ctx, name, parameters, return_type, instructions)
             },
-            _ => return Err(format!("Unexpected definition in module: {:?}", definition)),
+            _ => return Err(SemanticError::InvalidNode { expected: "a definition", found: format!("{:?}", definition) }),
         }?;
     }
 
-    Ok(())
+    for definition in definitions {
+        if let ASTNode::Function { name, parameters, return_type: _, instructions } = definition {
+            analyze_instructions(ctx, name, parameters, instructions)?;
+        }
+    }
+
+    analyze_tools(ctx)
 }
 
-fn analyze_model(ctx: &mut SemanticAnalysisContext, name: &str) -> Result<(), String> {
+fn analyze_model(ctx: &mut SemanticAnalysisContext, name: &str) -> Result<(), SemanticError> {
     let full_name = get_full_name(ctx, name);
     ctx.register_model(full_name)?;
 
     Ok(())
 }
 
-fn analyze_prompt(ctx: &mut SemanticAnalysisContext, name: &str, parameters: &Vec<(String, ASTNode, bool)>, return_type: &ASTNode) -> Result<(), String> {
+fn analyze_prompt(ctx: &mut SemanticAnalysisContext, name: &str, parameters: &[(String, ASTNode, bool)], return_type: &ASTNode, tools: &[ASTNode], annotations: &[String]) -> Result<(), SemanticError> {
     let full_name: String = get_full_name(ctx, name);
+
+    for annotation in annotations {
+        if !PROMPT_ANNOTATIONS.contains(&annotation.as_str()) {
+            return Err(SemanticError::UnknownAnnotation { annotation: annotation.clone(), prompt: full_name });
+        }
+    }
+
     let parameter_infos: Vec<ParameterInfo> = extract_parameters(parameters)?;
     let full_return_type: String = get_type_name(return_type)?;
+    let full_tool_names: Vec<String> = tools.iter()
+                                            .map(|tool| get_task_name(ctx, tool))
+                                            .collect::<Result<Vec<String>, SemanticError>>()?;
 
-    ctx.register_prompt(full_name, parameter_infos, full_return_type)?;
+    ctx.register_prompt(full_name, parameter_infos, full_return_type, full_tool_names)?;
 
     Ok(())
 }
 
-fn analyze_function(ctx: &mut SemanticAnalysisContext, name: &str, parameters: &Vec<(String, ASTNode, bool)>, return_type: &ASTNode, _instructions: &Vec<ASTNode>) -> Result<(), String> {
+fn analyze_tools(ctx: &SemanticAnalysisContext) -> Result<(), SemanticError> {
+    let module_prefix: String = format!("{}/", ctx.module_fully_qualified_name);
+
+    for (prompt_name, prompt) in &ctx.prompts {
+        for tool in &prompt.tools {
+            let is_local_tool: bool = tool.starts_with(&module_prefix);
+            let tool_is_known: bool = ctx.prompts.contains_key(tool) || ctx.functions.contains_key(tool);
+
+            if is_local_tool && !tool_is_known {
+                return Err(SemanticError::UnknownTool { tool: tool.clone(), prompt: prompt_name.clone() });
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn analyze_function(ctx: &mut SemanticAnalysisContext, name: &str, parameters: &[(String, ASTNode, bool)], return_type: &ASTNode, _instructions: &[ASTNode]) -> Result<(), SemanticError> {
     let full_name: String = get_full_name(ctx, name);
     let parameter_infos: Vec<ParameterInfo> = extract_parameters(parameters)?;
     let full_return_type: String = get_type_name(return_type)?;
 
     ctx.register_function(full_name, parameter_infos.clone(), full_return_type.clone())?;
 
-    // TODO: Analyze the instructions.
+    Ok(())
+}
+
+fn analyze_instructions(ctx: &SemanticAnalysisContext, name: &str, parameters: &[(String, ASTNode, bool)], instructions: &[ASTNode]) -> Result<(), SemanticError> {
+    let full_name: String = get_full_name(ctx, name);
+    let mut variables: HashMap<String, Option<String>> = extract_parameters(parameters)?
+        .into_iter()
+        .map(|parameter| (parameter.name, Some(parameter.full_type)))
+        .collect();
+
+    for instruction in instructions {
+        match instruction {
+            ASTNode::Assignment { lhs, rhs } => {
+                let value_type: Option<String> = analyze_expression(ctx, &full_name, &variables, rhs)?;
+                variables.insert(lhs.clone(), value_type);
+            },
+            ASTNode::ReturnStatement(expression) => {
+                analyze_expression(ctx, &full_name, &variables, expression)?;
+            },
+            _ => {
+                analyze_expression(ctx, &full_name, &variables, instruction)?;
+            },
+        }
+    }
 
     Ok(())
 }
 
+fn analyze_expression(ctx: &SemanticAnalysisContext, function_name: &str, variables: &HashMap<String, Option<String>>, expression: &ASTNode) -> Result<Option<String>, SemanticError> {
+    match expression {
+        ASTNode::FunctionCall { name, arguments } => {
+            analyze_function_call(ctx, function_name, variables, name, arguments)
+        },
+        ASTNode::Identifier(name) => Ok(variables.get(name).cloned().flatten()),
+        _ => Ok(None),
+    }
+}
+
+fn analyze_function_call(ctx: &SemanticAnalysisContext, function_name: &str, variables: &HashMap<String, Option<String>>, name: &str, arguments: &[String]) -> Result<Option<String>, SemanticError> {
+    let is_qualified: bool = name.contains("/");
+    let full_name: String = match is_qualified {
+        true => name.to_lowercase(),
+        false => get_full_name(ctx, name),
+    };
+
+    let (parameters, return_type) = match ctx.get_task_signature(&full_name) {
+        Some(signature) => signature,
+        // Tasks from other modules are only known once assemblies are loaded.
+        None if is_qualified => return Ok(None),
+        None => return Err(SemanticError::UnknownTask { task: name.to_string(), function: function_name.to_string() }),
+    };
+
+    if parameters.len() != arguments.len() {
+        return Err(
+            SemanticError::ArgumentCount {
+                task: full_name,
+                expected: parameters.len(),
+                actual: arguments.len(),
+                function: function_name.to_string(),
+            }
+        );
+    }
+
+    for (parameter, argument) in parameters.iter().zip(arguments.iter()) {
+        let argument_type: Option<String> = match variables.get(argument) {
+            Some(argument_type) => argument_type.clone(),
+            None => return Err(SemanticError::UnknownVariable { variable: argument.clone(), function: function_name.to_string() }),
+        };
+
+        if let Some(argument_type) = argument_type {
+            let expected_type: String = get_full_type_name(ctx, &parameter.full_type);
+            let found_type: String = get_full_type_name(ctx, &argument_type);
+
+            if expected_type != found_type {
+                return Err(
+                    SemanticError::ArgumentType {
+                        parameter: parameter.name.clone(),
+                        task: full_name,
+                        expected: expected_type,
+                        found: found_type,
+                        function: function_name.to_string(),
+                    }
+                );
+            }
+        }
+    }
+
+    Ok(Some(return_type))
+}
+
 fn get_full_name(ctx: &SemanticAnalysisContext, name: &str) -> String {
     let mut full_name: String = ctx.module_fully_qualified_name.clone();
 
@@ -139,15 +275,30 @@ fn get_full_name(ctx: &SemanticAnalysisContext, name: &str) -> String {
     full_name.to_lowercase()
 }
 
-fn get_type_name(type_node: &ASTNode) -> Result<String, String> {
+fn get_type_name(type_node: &ASTNode) -> Result<String, SemanticError> {
     match type_node {
         ASTNode::QualifiedIdentifier(parts) => Ok(parts.join("/").to_lowercase()),
         ASTNode::Identifier(name) => Ok(name.clone()),
-        _ => Err(format!("Invalid type: {:?}", type_node)),
+        _ => Err(SemanticError::InvalidNode { expected: "a type", found: format!("{:?}", type_node) }),
+    }
+}
+
+fn get_full_type_name(ctx: &SemanticAnalysisContext, type_name: &str) -> String {
+    match type_name.contains("/") {
+        true => type_name.to_lowercase(),
+        false => get_full_name(ctx, type_name),
+    }
+}
+
+fn get_task_name(ctx: &SemanticAnalysisContext, task_node: &ASTNode) -> Result<String, SemanticError> {
+    match task_node {
+        ASTNode::QualifiedIdentifier(parts) if parts.len() == 1 => Ok(get_full_name(ctx, &parts[0])),
+        ASTNode::QualifiedIdentifier(parts) => Ok(parts.join("/").to_lowercase()),
+        _ => Err(SemanticError::InvalidNode { expected: "a task", found: format!("{:?}", task_node) }),
     }
 }
 
-fn extract_parameters(raw_parameters: &Vec<(String, ASTNode, bool)>) -> Result<Vec<ParameterInfo>, String> {
+fn extract_parameters(raw_parameters: &[(String, ASTNode, bool)]) -> Result<Vec<ParameterInfo>, SemanticError> {
     raw_parameters.iter()
                   .map(|(name, full_type, is_array)| ParameterInfo::new(
                       name.to_string(),
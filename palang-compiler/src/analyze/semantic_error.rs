@@ -0,0 +1,59 @@
+use thiserror::Error;
+
+/// Why a parsed module is inconsistent. Tasks and types are named by their
+/// full names, like `tutorials/greet`.
+#[derive(Debug, Error, PartialEq)]
+pub enum SemanticError {
+    #[error("Expected {expected}, found {found}")]
+    InvalidNode {
+        expected: &'static str,
+        found: String,
+    },
+
+    #[error("Duplicate {kind} definition for \"{name}\"")]
+    Duplicate {
+        kind: &'static str,
+        name: String,
+    },
+
+    #[error("Unknown annotation \"@{annotation}\" on prompt \"{prompt}\"")]
+    UnknownAnnotation {
+        annotation: String,
+        prompt: String,
+    },
+
+    #[error("Unknown tool \"{tool}\" used by prompt \"{prompt}\"")]
+    UnknownTool {
+        tool: String,
+        prompt: String,
+    },
+
+    #[error("Unknown task \"{task}\" called in \"{function}\"")]
+    UnknownTask {
+        task: String,
+        function: String,
+    },
+
+    #[error("Unknown variable \"{variable}\" in \"{function}\"")]
+    UnknownVariable {
+        variable: String,
+        function: String,
+    },
+
+    #[error("\"{task}\" expects {expected} arguments, found {actual} in \"{function}\"")]
+    ArgumentCount {
+        task: String,
+        expected: usize,
+        actual: usize,
+        function: String,
+    },
+
+    #[error("Argument \"{parameter}\" of \"{task}\" should be {expected}, found {found} in \"{function}\"")]
+    ArgumentType {
+        parameter: String,
+        task: String,
+        expected: String,
+        found: String,
+        function: String,
+    },
+}
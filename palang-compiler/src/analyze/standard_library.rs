@@ -0,0 +1,95 @@
+use std::collections::HashMap;
+
+use crate::{parse::{ast_node::ASTNode, parser::parse}, tokenize::tokenizer::tokenize};
+
+use super::{parameter_info::ParameterInfo, prompt_info::PromptInfo};
+
+/// Sources of the standard library shipped with the virtual machine, copied
+/// so that the compiler builds on its own.
+const STANDARD_LIBRARY_SOURCE: &str = include_str!("../../std/std.palang");
+
+/// Signatures of the prompts in the standard library, read from its sources.
+pub fn get_standard_library_prompts() -> HashMap<String, PromptInfo> {
+    let module: ASTNode = parse(tokenize(STANDARD_LIBRARY_SOURCE))
+        .expect("The standard library sources shipped with the virtual machine are invalid");
+
+    let (module_name, definitions) = match &module {
+        ASTNode::Module { name, definitions } => match name.as_ref() {
+            ASTNode::QualifiedIdentifier(parts) => (parts.join("/").to_lowercase(), definitions),
+            _ => panic!("The standard library sources shipped with the virtual machine have an invalid module name"),
+        },
+        _ => panic!("The standard library sources shipped with the virtual machine are not a module"),
+    };
+
+    let qualify = |type_name: String| match type_name.contains("/") {
+        true => type_name,
+        false => format!("{}/{}", module_name, type_name),
+    };
+
+    definitions
+        .iter()
+        .filter_map(|definition| match definition {
+            ASTNode::Prompt { name, parameters, return_type, .. } => {
+                let return_type: String = match return_type.as_ref() {
+                    ASTNode::QualifiedIdentifier(parts) => parts.join("/").to_lowercase(),
+                    _ => panic!("The standard library sources shipped with the virtual machine have an invalid return type"),
+                };
+                let parameters: Vec<ParameterInfo> = parameters
+                    .iter()
+                    .map(|(name, full_type, is_array)| {
+                        let parameter: ParameterInfo = ParameterInfo::new(name.clone(), full_type.clone(), *is_array)
+                            .expect("The standard library sources shipped with the virtual machine have an invalid parameter");
+                        ParameterInfo { full_type: qualify(parameter.full_type), ..parameter }
+                    })
+                    .collect();
+
+                Some((
+                    format!("{}/{}", module_name, name.to_lowercase()),
+                    PromptInfo {
+                        parameters,
+                        return_type: qualify(return_type),
+                        tools: Vec::new(),
+                    },
+                ))
+            },
+            _ => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_the_standard_prompts() {
+        let prompts: HashMap<String, PromptInfo> = get_standard_library_prompts();
+
+        let mut names: Vec<&String> = prompts.keys().collect();
+        names.sort();
+        assert_eq!(names, vec!["std/classify", "std/summarize", "std/translate"]);
+
+        let classify: &PromptInfo = &prompts["std/classify"];
+        assert_eq!(classify.return_type, "std/text");
+        assert_eq!(
+            classify.parameters.iter().map(|parameter| (parameter.name.as_str(), parameter.full_type.as_str())).collect::<Vec<_>>(),
+            vec![("text", "std/text"), ("categories", "std/json")],
+        );
+    }
+
+    #[test]
+    fn matches_the_sources_of_the_virtual_machine() {
+        let file: String = format!("{}/../palang-virtual-machine/std/std.palang", env!("CARGO_MANIFEST_DIR"));
+        let source: String = std::fs::read_to_string(&file).unwrap();
+
+        assert!(source == STANDARD_LIBRARY_SOURCE, "std/std.palang differs from {}, copy it again", file);
+    }
+
+    #[test]
+    fn checks_calls_to_standard_prompts() {
+        let source: String = "module tests\n\nfunction sort(text: std::Text, categories: std::Text) -> std::Text {\n    return std::classify(text, categories)\n}\n".to_string();
+
+        let error: String = crate::compile_file(&source).unwrap_err().to_string();
+        assert!(error.contains("should be std/json"), "{}", error);
+    }
+}
@@ -0,0 +1,39 @@
+use std::{io, path::PathBuf};
+
+use thiserror::Error;
+
+use crate::{analyze::semantic_error::SemanticError, parse::syntax_error::SyntaxError};
+
+/// Why a source file or a package could not be compiled.
+#[derive(Debug, Error)]
+pub enum CompileError {
+    #[error("Syntax error ({0})")]
+    Syntax(#[from] SyntaxError),
+
+    #[error("Semantic error ({0})")]
+    Semantic(#[from] SemanticError),
+
+    #[error("Code generation failed ({0})")]
+    CodeGeneration(String),
+
+    #[error("Could not read {file:?} ({source})")]
+    Io {
+        file: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+
+    #[error("Invalid package description {file:?} ({source})")]
+    PackageDescription {
+        file: PathBuf,
+        #[source]
+        source: serde_yaml::Error,
+    },
+
+    #[error("Could not compile {file:?} ({source})")]
+    File {
+        file: PathBuf,
+        #[source]
+        source: Box<CompileError>,
+    },
+}
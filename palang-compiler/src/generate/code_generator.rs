@@ -4,6 +4,11 @@ use rand::{distributions::Alphanumeric, Rng};
 
 use crate::parse::ast_node::ASTNode;
 
+/// Version of the assembly format written by the code generator. Bump it
+/// along with the virtual machine's when older machines could not load the
+/// assemblies written.
+pub const ASSEMBLY_FORMAT_VERSION: u32 = 1;
+
 struct CodeGenerationContext {
     generated_assembly: String,
     module_fully_qualified_name: Vec<String>,
@@ -25,6 +30,11 @@ pub fn generate_palassembly(ast: &ASTNode) -> Result<String, String> {
     Ok(ctx.generated_assembly.clone())
 }
 
+/// The header opening every assembly, before any package or module.
+pub fn generate_format_header() -> String {
+    format!("PALASM {}\n", ASSEMBLY_FORMAT_VERSION)
+}
+
 fn generate_node(ctx: &mut CodeGenerationContext, node: &ASTNode) -> Result<(), String> {
     match node {
         ASTNode::Module {
@@ -43,9 +53,11 @@ fn generate_node(ctx: &mut CodeGenerationContext, node: &ASTNode) -> Result<(),
             name,
             parameters,
             return_type,
+            tools,
+            annotations,
             text
         } => {
-            generate_prompt(ctx, name, parameters, return_type, text)
+            generate_prompt(ctx, name, parameters, return_type, tools, annotations, text)
         },
         ASTNode::Function {
             name,
@@ -101,7 +113,10 @@ fn generate_prompt(
     ctx: &mut CodeGenerationContext,
     name: &str,
     parameters: &[(String, ASTNode, bool)],
-    return_type: &ASTNode, text: &str
+    return_type: &ASTNode,
+    tools: &[ASTNode],
+    annotations: &[String],
+    text: &str
 ) -> Result<(), String> {
     let full_name = get_full_name(ctx, name);
     let args = parameters.iter()
@@ -109,13 +124,30 @@ fn generate_prompt(
                                 .collect::<Vec<_>>()
                                 .join(" ");
     let ret_type = get_type_name(ctx, return_type)?;
+    let tool_names = tools.iter()
+                          .map(|tool| get_type_name(ctx, tool))
+                          .collect::<Result<Vec<_>, String>>()?;
 
     ctx.generated_assembly.push_str(
         &format!(
-            "PROMPT {}\nARGUMENTS {}\nRETURNS {}\nSTART\n{}\nEND",
+            "PROMPT {}\nARGUMENTS {}\nRETURNS {}\n",
             full_name,
             args,
             ret_type,
+        )
+    );
+
+    if !tool_names.is_empty() {
+        ctx.generated_assembly.push_str(&format!("TOOLS {}\n", tool_names.join(" ")));
+    }
+
+    if !annotations.is_empty() {
+        ctx.generated_assembly.push_str(&format!("ANNOTATIONS {}\n", annotations.join(" ")));
+    }
+
+    ctx.generated_assembly.push_str(
+        &format!(
+            "START\n{}\nEND",
             remove_indentation(text).trim(),
         )
     );
@@ -176,7 +208,7 @@ fn generate_instruction(
                 &format!(
                     "INVOKE {} {}\n",
                     get_full_type_name(ctx, name)?,
-                    arguments.join(", "),
+                    arguments.join(" "),
                 )
             );
         },
@@ -289,7 +321,7 @@ fn get_full_type_name(
         Ok(get_full_name(ctx, &type_name))
     }
     else {
-        Ok(type_name.to_string())
+        Ok(type_name.to_lowercase())
     }
 }
 
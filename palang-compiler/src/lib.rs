@@ -1,10 +1,12 @@
-use std::{fs, path::{Path, PathBuf}};
+use std::{collections::HashMap, fs, path::{Path, PathBuf}};
+
+use compile_error::CompileError;
 
 use package::{load_package_description, Package};
 use tokenize::{tokenizer::tokenize, tokens::Token};
 use parse::{ast_node::ASTNode, parser::parse};
-use analyze::semantic_analyzer::analyze_semantics;
-use generate::code_generator::generate_palassembly;
+use analyze::{function_info::FunctionInfo, semantic_analyzer::analyze_semantics};
+use generate::code_generator::{generate_format_header, generate_palassembly};
 use walkdir::WalkDir;
 
 pub mod tokenize;
@@ -12,8 +14,16 @@ pub mod parse;
 pub mod analyze;
 pub mod generate;
 pub mod package;
+pub mod compile_error;
+
+pub fn compile_package(root: &Path) -> Result<String, CompileError> {
+    compile_package_with_native_functions(root, &HashMap::new())
+}
 
-pub fn compile_package(root: &Path) -> Result<String, String> {
+pub fn compile_package_with_native_functions(
+    root: &Path,
+    native_functions: &HashMap<String, FunctionInfo>,
+) -> Result<String, CompileError> {
     let package: Package = load_package_description(root)?;
     let source_files: Vec<PathBuf> = WalkDir::new(root)
         .into_iter()
@@ -26,21 +36,70 @@ pub fn compile_package(root: &Path) -> Result<String, String> {
         .map(|entry| entry.path().to_path_buf())
         .collect();
 
-    let mut package_assembly = format!("PACKAGE {}\n", package.name);
+    let mut package_assembly = generate_format_header();
+    package_assembly.push_str(&format!("PACKAGE {}\n", package.name));
     package_assembly.push_str(&format!("DESCRIPTION\nSTART\n{}\nEND\n", package.description));
     package_assembly.push_str(&format!("VERSION {}\n", package.version));
     for source_file in source_files {
-        let source_code: String = fs::read_to_string(source_file).map_err(|e| e.to_string())?;
-        let assembly: String = compile_file(&source_code)?;
+        let source_code: String = fs::read_to_string(&source_file)
+            .map_err(|e| CompileError::Io { file: source_file.clone(), source: e })?;
+        let assembly: String = compile_module(&source_code, native_functions)
+            .map_err(|e| CompileError::File { file: source_file, source: Box::new(e) })?;
         package_assembly.push_str(&assembly);
     }
 
     Ok(package_assembly)
 }
 
-pub fn compile_file(source_code: &String) -> Result<String, String> {
+pub fn compile_file(source_code: &str) -> Result<String, CompileError> {
+    compile_file_with_native_functions(source_code, &HashMap::new())
+}
+
+pub fn compile_file_with_native_functions(
+    source_code: &str,
+    native_functions: &HashMap<String, FunctionInfo>,
+) -> Result<String, CompileError> {
+    Ok(generate_format_header() + &compile_module(source_code, native_functions)?)
+}
+
+/// Compiles a module without the format header, for the assembly of a package.
+fn compile_module(
+    source_code: &str,
+    native_functions: &HashMap<String, FunctionInfo>,
+) -> Result<String, CompileError> {
     let tokens: Vec<Token> = tokenize(source_code);
-    let ast: ASTNode = parse(tokens).map_err(|e| e)?;
-    analyze_semantics(&ast)?;
-    generate_palassembly(&ast)
+    let ast: ASTNode = parse(tokens)?;
+    analyze_semantics(&ast, native_functions)?;
+    generate_palassembly(&ast).map_err(CompileError::CodeGeneration)
+}
+
+#[cfg(test)]
+mod tests {
+    use analyze::semantic_error::SemanticError;
+    use parse::syntax_error::SyntaxError;
+
+    use super::*;
+
+    #[test]
+    fn reports_syntax_errors() {
+        assert!(matches!(
+            compile_file("module tests\n\nprompt greet(name: Text) -> Text\n"),
+            Err(CompileError::Syntax(SyntaxError::UnexpectedEnd))
+        ));
+        assert!(matches!(
+            compile_file("module tests\n\nfunction greet(name: Text) Text {\n    return name\n}\n"),
+            Err(CompileError::Syntax(SyntaxError::ExpectedToken { expected: Token::Arrow, .. }))
+        ));
+    }
+
+    #[test]
+    fn reports_semantic_errors() {
+        let error: CompileError = compile_file("module tests\n\nfunction greet(name: Text) -> Text {\n    return welcome(name)\n}\n").unwrap_err();
+
+        assert!(matches!(
+            &error,
+            CompileError::Semantic(SemanticError::UnknownTask { task, function }) if task == "welcome" && function == "tests/greet"
+        ), "{}", error);
+        assert_eq!(error.to_string(), "Semantic error (Unknown task \"welcome\" called in \"tests/greet\")");
+    }
 }
@@ -2,6 +2,8 @@ use std::{fs, path::{Path, PathBuf}};
 
 use serde::{Deserialize, Serialize};
 
+use crate::compile_error::CompileError;
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Package {
     pub name: String,
@@ -9,12 +11,12 @@ pub struct Package {
     pub version: String,
 }
 
-pub fn load_package_description(root: &Path) -> Result<Package, String> {
+pub fn load_package_description(root: &Path) -> Result<Package, CompileError> {
     let module_path: PathBuf = root.join("package.yaml");
-    let raw_module_description: String = fs::read_to_string(module_path)
-                                            .map_err(|e| e.to_string())?;
+    let raw_module_description: String = fs::read_to_string(&module_path)
+                                            .map_err(|e| CompileError::Io { file: module_path.clone(), source: e })?;
     let module_description: Package = serde_yaml::from_str(&raw_module_description)
-                                                           .map_err(|e| e.to_string())?;
+                                                           .map_err(|e| CompileError::PackageDescription { file: module_path, source: e })?;
 
     Ok(module_description)
 }
@@ -12,6 +12,8 @@ pub enum ASTNode {
         name: String,
         parameters: Vec<(String, ASTNode, bool)>,
         return_type: Box<ASTNode>,
+        tools: Vec<ASTNode>,
+        annotations: Vec<String>,
         text: String,
     },
     Function {
@@ -1,2 +1,3 @@
 pub mod ast_node;
 pub mod parser;
+pub mod syntax_error;
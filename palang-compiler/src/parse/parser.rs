@@ -1,6 +1,6 @@
 use crate::tokenize::tokens::Token;
 
-use super::ast_node::ASTNode;
+use super::{ast_node::ASTNode, syntax_error::SyntaxError};
 
 struct ParserContext {
     tokens: Vec<Token>,
@@ -8,16 +8,16 @@ struct ParserContext {
 }
 
 impl ParserContext {
-    pub fn peek(&self) -> Result<Token, String> {
+    pub fn peek(&self) -> Result<Token, SyntaxError> {
         if self.cursor < self.tokens.len() {
             Ok(self.tokens[self.cursor].clone())
         }
         else {
-            Err("Tried to peek after the end of the tokens list".to_string())
+            Err(SyntaxError::UnexpectedEnd)
         }
     }
 
-    pub fn next(&mut self) -> Result<Token, String> {
+    pub fn next(&mut self) -> Result<Token, SyntaxError> {
         let next_token = self.peek()?;
         self.cursor += 1;
 
@@ -32,7 +32,7 @@ impl ParserContext {
     }
 }
 
-pub fn parse(tokens: Vec<Token>) -> Result<ASTNode, String> {
+pub fn parse(tokens: Vec<Token>) -> Result<ASTNode, SyntaxError> {
     let mut ctx: ParserContext = ParserContext {
         tokens: tokens,
         cursor: 0
@@ -40,7 +40,7 @@ pub fn parse(tokens: Vec<Token>) -> Result<ASTNode, String> {
     parse_module(&mut ctx)
 }
 
-fn parse_module(ctx: &mut ParserContext) -> Result<ASTNode, String> {
+fn parse_module(ctx: &mut ParserContext) -> Result<ASTNode, SyntaxError> {
     expect_token(ctx, &Token::Module)?;
     let name: Box<ASTNode> = Box::new(parse_qualified_identifier(ctx)?);
     let mut definitions: Vec<ASTNode> = Vec::new();
@@ -51,7 +51,11 @@ fn parse_module(ctx: &mut ParserContext) -> Result<ASTNode, String> {
                 definitions.push(parse_model(ctx)?);
             },
             Token::Prompt => {
-                definitions.push(parse_prompt(ctx)?);
+                definitions.push(parse_prompt(ctx, Vec::new())?);
+            },
+            Token::At => {
+                let annotations: Vec<String> = parse_annotations(ctx)?;
+                definitions.push(parse_prompt(ctx, annotations)?);
             },
             Token::Function => {
                 definitions.push(parse_function(ctx)?);
@@ -65,7 +69,7 @@ fn parse_module(ctx: &mut ParserContext) -> Result<ASTNode, String> {
     Ok(ASTNode::Module { name, definitions })
 }
 
-fn parse_model(ctx: &mut ParserContext) -> Result<ASTNode, String> {
+fn parse_model(ctx: &mut ParserContext) -> Result<ASTNode, SyntaxError> {
     expect_token(ctx, &Token::Model)?;
 
     let name: String = parse_definition_name(ctx)?;
@@ -74,22 +78,40 @@ fn parse_model(ctx: &mut ParserContext) -> Result<ASTNode, String> {
     Ok(ASTNode::Model { name, text })
 }
 
-fn parse_prompt(ctx: &mut ParserContext) -> Result<ASTNode, String> {
+fn parse_annotations(ctx: &mut ParserContext) -> Result<Vec<String>, SyntaxError> {
+    let mut annotations: Vec<String> = Vec::new();
+
+    while ctx.peek()? == Token::At {
+        ctx.next()?;
+        annotations.push(parse_identifier(ctx)?.to_lowercase());
+    }
+
+    if ctx.peek()? != Token::Prompt {
+        return Err(SyntaxError::MisplacedAnnotations(ctx.peek()?));
+    }
+
+    Ok(annotations)
+}
+
+fn parse_prompt(ctx: &mut ParserContext, annotations: Vec<String>) -> Result<ASTNode, SyntaxError> {
     expect_token(ctx, &Token::Prompt)?;
 
     let name: String = parse_definition_name(ctx)?;
     let (parameters, return_type) = parse_parameters(ctx)?;
+    let tools: Vec<ASTNode> = parse_tools(ctx)?;
     let text: String = parse_text_body(ctx)?;
 
     Ok(ASTNode::Prompt {
         name,
         parameters,
         return_type: Box::new(return_type),
+        tools,
+        annotations,
         text,
     })
 }
 
-fn parse_function(ctx: &mut ParserContext) -> Result<ASTNode, String> {
+fn parse_function(ctx: &mut ParserContext) -> Result<ASTNode, SyntaxError> {
     expect_token(ctx, &Token::Function)?;
 
     let name: String = parse_definition_name(ctx)?;
@@ -104,7 +126,7 @@ fn parse_function(ctx: &mut ParserContext) -> Result<ASTNode, String> {
     })
 }
 
-fn parse_qualified_identifier(ctx: &mut ParserContext) -> Result<ASTNode, String> {
+fn parse_qualified_identifier(ctx: &mut ParserContext) -> Result<ASTNode, SyntaxError> {
     let mut parts: Vec<String> = Vec::new();
 
     loop {
@@ -121,34 +143,38 @@ fn parse_qualified_identifier(ctx: &mut ParserContext) -> Result<ASTNode, String
     }
 
     if parts.is_empty() {
-        Err("Identifier cannot be empty".to_string())
+        Err(SyntaxError::EmptyIdentifier)
     }
     else {
         Ok(ASTNode::QualifiedIdentifier(parts))
     }
 }
 
-fn parse_identifier(ctx: &mut ParserContext) -> Result<String, String> {
+fn parse_identifier(ctx: &mut ParserContext) -> Result<String, SyntaxError> {
     match ctx.peek()? {
         Token::Identifier(identifier) => {
             ctx.next()?;
             Ok(identifier)
         },
-        _ => Err(format!("Expected identifier, got {:?}", ctx.peek()?))
+        _ => Err(SyntaxError::Unexpected { expected: "an identifier", found: ctx.peek()? })
     }
 }
 
-fn parse_definition_name(ctx: &mut ParserContext) -> Result<String, String> {
+fn parse_definition_name(ctx: &mut ParserContext) -> Result<String, SyntaxError> {
     match ctx.peek()? {
         Token::Identifier(name) => {
             ctx.next()?;
             Ok(name)
         },
-        _ => Err(format!("Expected a name, found {:?}", ctx.peek()?)),
+        _ => Err(SyntaxError::Unexpected { expected: "a name", found: ctx.peek()? }),
     }
 }
 
-fn parse_parameters(ctx: &mut ParserContext) -> Result<(Vec<(String, ASTNode, bool)>, ASTNode), String> {
+/// The parameters of a definition, each with its type and whether it is an
+/// array, and its return type.
+type Signature = (Vec<(String, ASTNode, bool)>, ASTNode);
+
+fn parse_parameters(ctx: &mut ParserContext) -> Result<Signature, SyntaxError> {
     expect_token(ctx, &Token::OpenParenthesis)?;
 
     let mut parameters: Vec<(String, ASTNode, bool)> = Vec::new();
@@ -170,7 +196,7 @@ fn parse_parameters(ctx: &mut ParserContext) -> Result<(Vec<(String, ASTNode, bo
                 parameters.last_mut().unwrap().2 = true;
             }
             _ => {
-                return Err(format!("Unexpected in parameters signature: {:?}", ctx.peek()?));
+                return Err(SyntaxError::Unexpected { expected: "a parameter", found: ctx.peek()? });
             }
         }
     }
@@ -184,17 +210,39 @@ fn parse_parameters(ctx: &mut ParserContext) -> Result<(Vec<(String, ASTNode, bo
     Ok((parameters, return_type))
 }
 
-fn parse_text_body(ctx: &mut ParserContext) -> Result<String, String> {
+fn parse_tools(ctx: &mut ParserContext) -> Result<Vec<ASTNode>, SyntaxError> {
+    let mut tools: Vec<ASTNode> = Vec::new();
+
+    if ctx.peek()? != Token::Uses {
+        return Ok(tools);
+    }
+    ctx.next()?;
+
+    loop {
+        tools.push(parse_qualified_identifier(ctx)?);
+
+        match ctx.peek()? {
+            Token::Comma => {
+                ctx.next()?;
+            },
+            _ => break,
+        }
+    }
+
+    Ok(tools)
+}
+
+fn parse_text_body(ctx: &mut ParserContext) -> Result<String, SyntaxError> {
     match ctx.peek()? {
         Token::StringLiteral(text) => {
             ctx.next()?;
             Ok(text)
         }
-        _ => Err(format!("Expected text, found {:?}", ctx.peek()?))
+        _ => Err(SyntaxError::Unexpected { expected: "a text", found: ctx.peek()? })
     }
 }
 
-fn parse_instructions(ctx: &mut ParserContext) -> Result<Vec<ASTNode>, String> {
+fn parse_instructions(ctx: &mut ParserContext) -> Result<Vec<ASTNode>, SyntaxError> {
     expect_token(ctx, &Token::OpenBrace)?;
 
     let mut instructions: Vec<ASTNode> = Vec::new();
@@ -206,15 +254,15 @@ fn parse_instructions(ctx: &mut ParserContext) -> Result<Vec<ASTNode>, String> {
     Ok(instructions)
 }
 
-fn parse_statement(ctx: &mut ParserContext) -> Result<ASTNode, String> {
+fn parse_statement(ctx: &mut ParserContext) -> Result<ASTNode, SyntaxError> {
     match ctx.peek()? {
         Token::Identifier(_) => parse_assignment_or_function_call(ctx),
         Token::Return        => parse_return_statement(ctx),
-        _ => Err(format!("Unexpected token in statement {:?}", ctx.peek()?)),
+        _ => Err(SyntaxError::Unexpected { expected: "a statement", found: ctx.peek()? }),
     }
 }
 
-fn parse_assignment_or_function_call(ctx: &mut ParserContext) -> Result<ASTNode, String> {
+fn parse_assignment_or_function_call(ctx: &mut ParserContext) -> Result<ASTNode, SyntaxError> {
     let identifier: String = parse_identifier(ctx)?;
 
     match ctx.peek()? {
@@ -228,11 +276,22 @@ fn parse_assignment_or_function_call(ctx: &mut ParserContext) -> Result<ASTNode,
             let arguments: Vec<String> = parse_arguments(ctx)?;
             Ok(ASTNode::FunctionCall { name: identifier, arguments })
         },
+        Token::DoubleColon => {
+            let mut parts: Vec<String> = vec![identifier];
+            while ctx.peek()? == Token::DoubleColon {
+                ctx.next()?;
+                parts.push(parse_identifier(ctx)?);
+            }
+
+            expect_token(ctx, &Token::OpenParenthesis)?;
+            let arguments: Vec<String> = parse_arguments(ctx)?;
+            Ok(ASTNode::FunctionCall { name: parts.join("/"), arguments })
+        },
         _ => Ok(ASTNode::Identifier(identifier)),
     }
 }
 
-fn parse_arguments(ctx: &mut ParserContext) -> Result<Vec<String>, String> {
+fn parse_arguments(ctx: &mut ParserContext) -> Result<Vec<String>, SyntaxError> {
     let mut arguments: Vec<String> = Vec::new();
 
     while ctx.peek()? != Token::CloseParenthesis {
@@ -245,7 +304,7 @@ fn parse_arguments(ctx: &mut ParserContext) -> Result<Vec<String>, String> {
                 ctx.next()?;
             },
             _ => {
-                return Err(format!("Unexpected token in function call arguments {:?}", ctx.peek()?));
+                return Err(SyntaxError::Unexpected { expected: "an argument", found: ctx.peek()? });
             },
         }
     }
@@ -254,17 +313,17 @@ fn parse_arguments(ctx: &mut ParserContext) -> Result<Vec<String>, String> {
     Ok(arguments)
 }
 
-fn parse_return_statement(ctx: &mut ParserContext) -> Result<ASTNode, String> {
+fn parse_return_statement(ctx: &mut ParserContext) -> Result<ASTNode, SyntaxError> {
     expect_token(ctx, &Token::Return)?;
     Ok(ASTNode::ReturnStatement(Box::new(parse_statement(ctx)?)))
 }
 
-fn expect_token(ctx: &mut ParserContext, expected_token: &Token) -> Result<(), String> {
+fn expect_token(ctx: &mut ParserContext, expected_token: &Token) -> Result<(), SyntaxError> {
     if ctx.peek()? == *expected_token {
         ctx.next()?;
         Ok(())
     }
     else {
-        Err(format!("Expected token {:?}, found {:?}", expected_token, ctx.peek()?))
+        Err(SyntaxError::ExpectedToken { expected: expected_token.clone(), found: ctx.peek()? })
     }
 }
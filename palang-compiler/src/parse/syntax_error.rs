@@ -0,0 +1,28 @@
+use thiserror::Error;
+
+use crate::tokenize::tokens::Token;
+
+/// Why the tokens of a source file do not form a module.
+#[derive(Debug, Error, PartialEq)]
+pub enum SyntaxError {
+    #[error("Unexpected end of the source")]
+    UnexpectedEnd,
+
+    #[error("Expected token {expected:?}, found {found:?}")]
+    ExpectedToken {
+        expected: Token,
+        found: Token,
+    },
+
+    #[error("Expected {expected}, found {found:?}")]
+    Unexpected {
+        expected: &'static str,
+        found: Token,
+    },
+
+    #[error("Annotations can only be applied to prompts, got {0:?}")]
+    MisplacedAnnotations(Token),
+
+    #[error("Identifier cannot be empty")]
+    EmptyIdentifier,
+}
@@ -159,6 +159,7 @@ fn tokenize_identifier<'a>(chars: &mut Peekable<Chars<'a>>) -> Token {
         "model"    => Token::Model,
         "prompt"   => Token::Prompt,
         "function" => Token::Function,
+        "uses"     => Token::Uses,
         "return"   => Token::Return,
         "for"      => Token::For,
         "in"       => Token::In,
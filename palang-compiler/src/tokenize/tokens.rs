@@ -4,6 +4,7 @@ pub enum Token {
     Model,
     Prompt,
     Function,
+    Uses,
     Arrow,
     OpenBrace,
     CloseBrace,
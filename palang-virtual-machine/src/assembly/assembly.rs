@@ -1,9 +1,11 @@
 use std::collections::HashMap;
 
-use super::{function::Function, model::Model, prompt::Prompt};
+use super::{function::Function, loader::ASSEMBLY_FORMAT_VERSION, model::Model, package_metadata::PackageMetadata, prompt::Prompt};
 
 #[derive(Debug, Clone)]
 pub struct Assembly {
+    pub format_version: u32,
+    pub package: Option<PackageMetadata>,
     pub name: String,
     pub models: HashMap<String, Model>,
     pub prompts: HashMap<String, Prompt>,
@@ -13,6 +15,8 @@ pub struct Assembly {
 impl Assembly {
     pub fn new() -> Self {
         Assembly {
+            format_version: ASSEMBLY_FORMAT_VERSION,
+            package: None,
             name: String::new(),
             models: HashMap::new(),
             prompts: HashMap::new(),
@@ -1,6 +1,19 @@
+use std::fmt;
+
 #[derive(Debug, Clone)]
 pub enum Instruction {
     Assign(String, String),
     Invoke(String, Vec<String>),
     Return(String),
 }
+
+/// Formats the instruction the way it is written in assembly files.
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Instruction::Assign(to, from) => write!(f, "ASSIGN {} {}", to, from),
+            Instruction::Invoke(task, arguments) => write!(f, "INVOKE {} {}", task, arguments.join(" ")),
+            Instruction::Return(to_return) => write!(f, "RETURN {}", to_return),
+        }
+    }
+}
@@ -0,0 +1,41 @@
+use std::{io, path::PathBuf};
+
+use thiserror::Error;
+
+/// Why an assembly could not be loaded.
+#[derive(Debug, Error)]
+pub enum LoadError {
+    #[error("Could not read {file:?} ({source})")]
+    Io {
+        file: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+
+    #[error("The assembly has no format version, it was compiled by an older version of Palang: recompile it with palang compile")]
+    MissingFormatVersion,
+
+    #[error("The assembly uses format version {version} but this virtual machine only supports up to version {supported}: upgrade Palang to load it")]
+    UnsupportedFormatVersion {
+        version: u32,
+        supported: u32,
+    },
+
+    #[error("Line {line}: {message}")]
+    Syntax {
+        line: usize,
+        message: String,
+    },
+
+    #[error("Model {model} used by {used_by} not found")]
+    UnknownModel {
+        model: String,
+        used_by: String,
+    },
+
+    #[error("Task {task} used by {used_by} not found")]
+    UnknownTask {
+        task: String,
+        used_by: String,
+    },
+}
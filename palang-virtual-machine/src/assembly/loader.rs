@@ -1,199 +1,461 @@
-use super::{assembly::Assembly, function::Function, instruction::Instruction, model::Model, parameter::Parameter, prompt::Prompt};
+use super::{assembly::Assembly, function::Function, instruction::Instruction, load_error::LoadError, model::Model, package_metadata::PackageMetadata, parameter::Parameter, prompt::Prompt};
 
-struct AssemblyReader {
-    lines: Vec<String>,
+/// Version of the assembly format this virtual machine loads, written by the
+/// compiler in the `PALASM` header. See docs/references/assembly-format.md.
+pub const ASSEMBLY_FORMAT_VERSION: u32 = 1;
+
+struct AssemblyReader<'a> {
+    lines: Vec<&'a str>,
     cursor: usize,
 }
 
-impl AssemblyReader {
-    pub fn new(source: &String) -> Self {
+impl<'a> AssemblyReader<'a> {
+    pub fn new(source: &'a str) -> Self {
         AssemblyReader {
-            lines: source.lines().map(str::to_string).collect(),
+            lines: source.lines().collect(),
             cursor: 0,
         }
     }
 
-    pub fn reached_end(&self) -> bool {
-        self.cursor >= self.lines.len()
+    /// An error at the line read last.
+    pub fn error(&self, message: String) -> LoadError {
+        Self::error_at(self.cursor.max(1), message)
     }
 
-    pub fn next_instruction(&mut self) -> Result<(String, Vec<String>), String> {
-        let line = self.peek_instruction()?;
-        self.cursor += 1;
-
-        Ok(line)
+    pub fn error_at(line: usize, message: String) -> LoadError {
+        LoadError::Syntax { line, message }
     }
 
-    pub fn peek_instruction(&self) -> Result<(String, Vec<String>), String> {
-        if self.reached_end() {
-            return Err("Tried to get line after end of assembly was reached".to_string());
-        }
-
-        let line: String = self.lines.get(self.cursor).unwrap().clone();
-        let tokens: Vec<String> = line.split(" ").map(str::to_string).collect();
+    /// The next directive and its operands, skipping blank lines.
+    pub fn next_directive(&mut self) -> Option<(&'a str, Vec<&'a str>)> {
+        while let Some(line) = self.lines.get(self.cursor) {
+            self.cursor += 1;
 
-        if tokens.is_empty() {
-            return Err("Line is empty".to_string());
+            let mut tokens = line.split_whitespace();
+            if let Some(directive) = tokens.next() {
+                return Some((directive, tokens.collect()));
+            }
         }
 
-        Ok((tokens.first().unwrap().to_string(), tokens[1..].into()))
+        None
     }
 
-    pub fn next_line(&mut self) -> Result<String, String> {
-        if self.reached_end() {
-            return Err("Tried to get line after end of assembly was reached".to_string());
-        }
+    pub fn peek_directive(&self) -> Option<&'a str> {
+        self.lines[self.cursor.min(self.lines.len())..]
+            .iter()
+            .find_map(|line| line.split_whitespace().next())
+    }
 
-        let line: String = self.lines.get(self.cursor).unwrap().clone();
-        self.cursor += 1;
+    pub fn expect(&mut self, directive: &str) -> Result<Vec<&'a str>, LoadError> {
+        match self.next_directive() {
+            Some((next_directive, operands)) if next_directive == directive => Ok(operands),
+            Some((next_directive, _)) => Err(self.error(format!("Expected {}, found {}", directive, next_directive))),
+            None => Err(self.error(format!("Expected {}, reached the end of the assembly", directive))),
+        }
+    }
 
-        Ok(line)
+    /// The only operand of a directive.
+    pub fn get_operand(&self, directive: &str, operands: &[&str]) -> Result<String, LoadError> {
+        match operands {
+            [operand] => Ok(operand.to_string()),
+            _ => Err(self.error(format!("{} expects 1 operand, found {}", directive, operands.len()))),
+        }
     }
 
-    pub fn next(&mut self) {
-        self.cursor += 1;
+    pub fn expect_operand(&mut self, directive: &str) -> Result<String, LoadError> {
+        let operands: Vec<&str> = self.expect(directive)?;
+        self.get_operand(directive, &operands)
     }
 
-    pub fn expect(&mut self, instruction: &str) -> Result<Vec<String>, String> {
-        let (next_instruction, parameters) = self.next_instruction()?;
+    /// The lines between `START` and `END`, exactly as written.
+    pub fn read_text(&mut self) -> Result<String, LoadError> {
+        self.expect("START")?;
+        let start: usize = self.cursor;
 
-        if next_instruction.as_str() == instruction {
-            Ok(parameters)
-        }
-        else {
-            Err(format!("Expected {}, found {}", instruction, next_instruction))
+        while let Some(line) = self.lines.get(self.cursor) {
+            self.cursor += 1;
+
+            if *line == "END" {
+                return Ok(self.lines[start..self.cursor - 1].join("\n"));
+            }
         }
+
+        Err(Self::error_at(start, "START is never closed by END".to_string()))
     }
 }
 
-pub fn load_assembly(source: &String) -> Result<Assembly, String> {
+pub fn load_assembly(source: &str) -> Result<Assembly, LoadError> {
     let mut assembly: Assembly = Assembly::new();
     let mut reader: AssemblyReader = AssemblyReader::new(source);
+    assembly.format_version = read_format_version(&mut reader)?;
+
+    while let Some((directive, operands)) = reader.next_directive() {
+        let line: usize = reader.cursor;
+
+        match directive {
+            "MODULE" => {
+                assembly.name = reader.get_operand(directive, &operands)?;
+            },
+            "PACKAGE" => {
+                let name: String = reader.get_operand(directive, &operands)?;
+                reader.expect("DESCRIPTION")?;
+                let description: String = reader.read_text()?;
+                let version: String = reader.expect_operand("VERSION")?;
 
-    while !reader.reached_end() {
-        match reader.next_instruction() {
-            Ok((instruction, parameters)) => {
-                match instruction.as_str() {
-                    "MODULE" => {
-                        assembly.name = parameters.get(0).unwrap().clone();
-                    },
-                    "MODEL" => {
-                        let name: String = parameters.get(0).unwrap().clone();
-                        let mut text: String = String::new();
-
-                        reader.expect("START")?;
-                        loop {
-                            let (line_instruction, _) = reader.peek_instruction()?;
-                            if line_instruction == "END" {
-                                break;
-                            }
-
-                            text += reader.next_line()?.as_str();
-                        }
-                        reader.next();
-
-                        assembly.models.insert(
-                            name.clone(),
-                            Model { name, text }
-                        );
-                    },
-                    "PROMPT" => {
-                        let name: String = parameters.get(0).unwrap().clone();
-                        let mut text: String = String::new();
-
-                        let arguments: Vec<Parameter> = reader
-                            .expect("ARGUMENTS")?[0..]
-                            .iter()
-                            .map(|argument| Parameter { name: argument.clone() })
-                            .collect();
-                        let returns: String = reader.expect("RETURNS")?.get(0).unwrap().clone();
-
-                        reader.expect("START")?;
-                        loop {
-                            let (line_instruction, _) = reader.peek_instruction()?;
-                            if line_instruction == "END" {
-                                break;
-                            }
-
-                            text += reader.next_line()?.as_str();
-                        }
-                        reader.next();
-
-                        assembly.prompts.insert(
-                            name.clone(),
-                            Prompt {
-                                name,
-                                parameters: arguments,
-                                return_type: returns,
-                                text,
-                            }
-                        );
-                    },
-                    "FUNCTION" => {
-                        let name: String = parameters.get(0).unwrap().clone();
-                        let mut instructions: Vec<Instruction> = Vec::new();
-
-                        let arguments: Vec<Parameter> = reader
-                            .expect("ARGUMENTS")?[0..]
-                            .iter()
-                            .map(|argument| Parameter { name: argument.clone() })
-                            .collect();
-                        let returns: String = reader.expect("RETURNS")?.get(0).unwrap().clone();
-
-                        reader.expect("START")?;
-                        loop {
-                            let (line_instruction, line_parameters) = reader.peek_instruction()?;
-                            match line_instruction.as_str() {
-                                "END" => {
-                                    break;
-                                },
-                                "ASSIGN" => {
-                                    instructions.push(
-                                        Instruction::Assign(
-                                            line_parameters.get(0).unwrap().to_string(),
-                                            line_parameters.get(1).unwrap().to_string(),
-                                        )
-                                    );
-                                },
-                                "INVOKE" => {
-                                    instructions.push(
-                                        Instruction::Invoke(
-                                            line_parameters.get(0).unwrap().clone(),
-                                            line_parameters[1..].into(),
-                                        )
-                                    );
-                                },
-                                "RETURN" => {
-                                    instructions.push(
-                                        Instruction::Return(
-                                            line_parameters.get(0).unwrap().clone(),
-                                        )
-                                    )
-                                },
-                                _ => {},
-                            }
-                            reader.next();
-                        }
-                        reader.next();
-
-                        assembly.functions.insert(
-                            name.clone(),
-                            Function {
-                                name,
-                                parameters: arguments,
-                                return_type: returns,
-                                instructions,
-                            }
-                        );
-                    },
-                    _ => {},
+                if assembly.package.is_some() {
+                    return Err(AssemblyReader::error_at(line, "PACKAGE is defined twice".to_string()));
                 }
+                assembly.package = Some(PackageMetadata { name, description, version });
+            },
+            "MODEL" => {
+                let name: String = reader.get_operand(directive, &operands)?;
+                let text: String = reader.read_text()?;
+
+                if assembly.models.contains_key(&name) {
+                    return Err(AssemblyReader::error_at(line, format!("Model {} is defined twice", name)));
+                }
+                assembly.models.insert(
+                    name.clone(),
+                    Model { name, text }
+                );
+            },
+            "PROMPT" => {
+                let name: String = reader.get_operand(directive, &operands)?;
+
+                let arguments: Vec<Parameter> = reader
+                    .expect("ARGUMENTS")?
+                    .iter()
+                    .map(|argument| Parameter { name: argument.to_string() })
+                    .collect();
+                let returns: String = reader.expect_operand("RETURNS")?;
+
+                let mut tools: Vec<String> = Vec::new();
+                if reader.peek_directive() == Some("TOOLS") {
+                    tools = reader.expect("TOOLS")?.iter().map(|tool| tool.to_string()).collect();
+                }
+
+                let mut annotations: Vec<String> = Vec::new();
+                if reader.peek_directive() == Some("ANNOTATIONS") {
+                    annotations = reader.expect("ANNOTATIONS")?.iter().map(|annotation| annotation.to_string()).collect();
+                }
+
+                let text: String = reader.read_text()?;
+
+                if assembly.prompts.contains_key(&name) || assembly.functions.contains_key(&name) {
+                    return Err(AssemblyReader::error_at(line, format!("Task {} is defined twice", name)));
+                }
+                assembly.prompts.insert(
+                    name.clone(),
+                    Prompt {
+                        name,
+                        parameters: arguments,
+                        return_type: returns,
+                        tools,
+                        annotations,
+                        text,
+                    }
+                );
+            },
+            "FUNCTION" => {
+                let name: String = reader.get_operand(directive, &operands)?;
+
+                let arguments: Vec<Parameter> = reader
+                    .expect("ARGUMENTS")?
+                    .iter()
+                    .map(|argument| Parameter { name: argument.to_string() })
+                    .collect();
+                let returns: String = reader.expect_operand("RETURNS")?;
+                let instructions: Vec<Instruction> = read_instructions(&mut reader, &name)?;
+
+                if assembly.prompts.contains_key(&name) || assembly.functions.contains_key(&name) {
+                    return Err(AssemblyReader::error_at(line, format!("Task {} is defined twice", name)));
+                }
+                assembly.functions.insert(
+                    name.clone(),
+                    Function {
+                        name,
+                        parameters: arguments,
+                        return_type: returns,
+                        instructions,
+                    }
+                );
+            },
+            _ => {
+                return Err(reader.error(format!("Unknown directive {}", directive)));
             },
-            Err(_) => {
-                continue;
-            }
         }
     }
 
     Ok(assembly)
 }
+
+fn read_format_version(reader: &mut AssemblyReader) -> Result<u32, LoadError> {
+    let version: String = match reader.peek_directive() {
+        Some("PALASM") => reader.expect_operand("PALASM")?,
+        _ => return Err(LoadError::MissingFormatVersion),
+    };
+
+    match version.parse::<u32>() {
+        Ok(version) if version > ASSEMBLY_FORMAT_VERSION => Err(
+            LoadError::UnsupportedFormatVersion {
+                version,
+                supported: ASSEMBLY_FORMAT_VERSION,
+            }
+        ),
+        Ok(version) if version > 0 => Ok(version),
+        _ => Err(reader.error(format!("Invalid format version {}", version))),
+    }
+}
+
+fn read_instructions(reader: &mut AssemblyReader, function: &String) -> Result<Vec<Instruction>, LoadError> {
+    let mut instructions: Vec<Instruction> = Vec::new();
+
+    reader.expect("START")?;
+    loop {
+        let instruction: Instruction = match reader.next_directive() {
+            Some(("END", _)) => {
+                return Ok(instructions);
+            },
+            Some(("ASSIGN", operands)) => match operands[..] {
+                [to, from] => Instruction::Assign(to.to_string(), from.to_string()),
+                _ => {
+                    return Err(reader.error(format!("ASSIGN expects 2 operands, found {}", operands.len())));
+                },
+            },
+            Some(("INVOKE", operands)) => match operands.split_first() {
+                Some((task, arguments)) => Instruction::Invoke(
+                    task.to_string(),
+                    arguments.iter().map(|argument| argument.to_string()).collect(),
+                ),
+                None => {
+                    return Err(reader.error("INVOKE expects a task".to_string()));
+                },
+            },
+            Some(("RETURN", operands)) => Instruction::Return(reader.get_operand("RETURN", &operands)?),
+            Some((instruction, _)) => {
+                return Err(reader.error(format!("Unknown instruction {} in function {}", instruction, function)));
+            },
+            None => {
+                return Err(reader.error(format!("Function {} is never closed by END", function)));
+            },
+        };
+
+        instructions.push(instruction);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::{prelude::*, sample::Index};
+
+    use super::*;
+
+    const ASSEMBLY: &str = "PALASM 1
+MODULE tests
+PACKAGE tests
+DESCRIPTION
+START
+Tests of the loader.
+END
+VERSION 0.1.0
+MODEL tests/city
+START
+name: std/text
+END
+PROMPT tests/ask
+ARGUMENTS question
+RETURNS std/text
+TOOLS tests/twice
+ANNOTATIONS nocache
+START
+Answer @{question}.
+END
+FUNCTION tests/twice
+ARGUMENTS question
+RETURNS std/text
+START
+INVOKE tests/ask question
+ASSIGN first @invocation_registry
+INVOKE tests/ask first
+ASSIGN second @invocation_registry
+RETURN second
+END";
+
+    fn get_syntax_error(source: &str) -> (usize, String) {
+        match load_assembly(source) {
+            Err(LoadError::Syntax { line, message }) => (line, message),
+            result => panic!("Expected a syntax error, got {:?}", result.map(|assembly| assembly.name)),
+        }
+    }
+
+    #[test]
+    fn loads_every_definition() {
+        let assembly: Assembly = load_assembly(ASSEMBLY).unwrap();
+
+        assert_eq!(assembly.name, "tests");
+        assert_eq!(assembly.package.unwrap().version, "0.1.0");
+        assert_eq!(assembly.models["tests/city"].text, "name: std/text");
+        assert_eq!(assembly.prompts["tests/ask"].tools, vec!["tests/twice"]);
+        assert_eq!(assembly.prompts["tests/ask"].annotations, vec!["nocache"]);
+        assert_eq!(assembly.functions["tests/twice"].instructions.len(), 5);
+    }
+
+    #[test]
+    fn reports_the_line_of_syntax_errors() {
+        assert_eq!(get_syntax_error("PALASM 1\n\nMODULE tests extra").0, 3);
+        assert_eq!(get_syntax_error("PALASM 1\nMODULE tests\n\n\nMODEL tests/city\nEND").0, 6);
+        assert_eq!(
+            get_syntax_error(&ASSEMBLY.replace("RETURN second", "RETURN")),
+            (29, "RETURN expects 1 operand, found 0".to_string()),
+        );
+    }
+
+    #[test]
+    fn reports_duplicates_at_their_definition() {
+        let source: String = format!("{}\nMODEL tests/city\nSTART\nEND", ASSEMBLY);
+
+        assert_eq!(get_syntax_error(&source), (31, "Model tests/city is defined twice".to_string()));
+    }
+
+    #[test]
+    fn rejects_unknown_directives() {
+        assert_eq!(
+            get_syntax_error("PALASM 1\nMODULE tests\nIMPORT std"),
+            (3, "Unknown directive IMPORT".to_string()),
+        );
+        assert_eq!(
+            get_syntax_error(&ASSEMBLY.replace("RETURN second", "YIELD second")),
+            (29, "Unknown instruction YIELD in function tests/twice".to_string()),
+        );
+    }
+
+    #[test]
+    fn reports_an_unterminated_start_at_its_line() {
+        assert_eq!(
+            get_syntax_error("PALASM 1\nMODULE tests\nMODEL tests/city\nSTART\nname: std/text\n END"),
+            (4, "START is never closed by END".to_string()),
+        );
+        assert_eq!(
+            get_syntax_error("PALASM 1\nFUNCTION tests/empty\nARGUMENTS\nRETURNS std/none\nSTART"),
+            (5, "Function tests/empty is never closed by END".to_string()),
+        );
+    }
+
+    #[test]
+    fn requires_a_supported_format_version() {
+        assert!(matches!(load_assembly("MODULE tests"), Err(LoadError::MissingFormatVersion)));
+        assert!(matches!(
+            load_assembly("PALASM 2\nMODULE tests"),
+            Err(LoadError::UnsupportedFormatVersion { version: 2, supported: 1 })
+        ));
+        assert_eq!(get_syntax_error("PALASM 0"), (1, "Invalid format version 0".to_string()));
+    }
+
+    #[test]
+    fn preserves_text_bodies_exactly() {
+        let text: &str = "  Indented line\n\nARGUMENTS look like directives\n END\nEND \ttrailing whitespace  \n";
+        let source: String = format!("PALASM 1\nMODEL tests/city\nSTART\n{}\nEND", text);
+
+        assert_eq!(load_assembly(&source).unwrap().models["tests/city"].text, text);
+    }
+
+    /// Lines of a text body, which only end at a line that is exactly `END`.
+    fn text_line() -> impl Strategy<Value = String> {
+        "[^\r\n]*".prop_filter("END closes the text", |line| line != "END")
+    }
+
+    /// Structured mutations of the lines of an assembly.
+    #[derive(Debug, Clone)]
+    enum Mutation {
+        Remove(Index),
+        Duplicate(Index),
+        Swap(Index, Index),
+        Replace(Index, String),
+        Insert(Index, String),
+        Truncate(Index),
+    }
+
+    fn mutation() -> impl Strategy<Value = Mutation> {
+        let line = prop_oneof![
+            Just("START".to_string()),
+            Just("END".to_string()),
+            Just("ARGUMENTS".to_string()),
+            Just("RETURN".to_string()),
+            Just("INVOKE".to_string()),
+            Just("ASSIGN x".to_string()),
+            Just("PALASM 1".to_string()),
+            Just("PACKAGE tests".to_string()),
+            "[A-Z]{1,10}( [a-z/@{}]{0,8}){0,3}",
+            "\\PC{0,20}",
+        ];
+
+        prop_oneof![
+            any::<Index>().prop_map(Mutation::Remove),
+            any::<Index>().prop_map(Mutation::Duplicate),
+            (any::<Index>(), any::<Index>()).prop_map(|(first, second)| Mutation::Swap(first, second)),
+            (any::<Index>(), line.clone()).prop_map(|(index, line)| Mutation::Replace(index, line)),
+            (any::<Index>(), line).prop_map(|(index, line)| Mutation::Insert(index, line)),
+            any::<Index>().prop_map(Mutation::Truncate),
+        ]
+    }
+
+    fn mutate(lines: &mut Vec<String>, mutation: &Mutation) {
+        if lines.is_empty() {
+            return;
+        }
+
+        match mutation {
+            Mutation::Remove(index) => {
+                lines.remove(index.index(lines.len()));
+            },
+            Mutation::Duplicate(index) => {
+                let index: usize = index.index(lines.len());
+                lines.insert(index, lines[index].clone());
+            },
+            Mutation::Swap(first, second) => {
+                let length: usize = lines.len();
+                lines.swap(first.index(length), second.index(length));
+            },
+            Mutation::Replace(index, line) => {
+                let index: usize = index.index(lines.len());
+                lines[index] = line.clone();
+            },
+            Mutation::Insert(index, line) => {
+                lines.insert(index.index(lines.len() + 1), line.clone());
+            },
+            Mutation::Truncate(index) => {
+                lines.truncate(index.index(lines.len()));
+            },
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn arbitrary_input_does_not_panic(source in "(PALASM 1\n)?\\PC*(\n\\PC*){0,20}") {
+            let _ = load_assembly(&source);
+        }
+
+        #[test]
+        fn mutated_assemblies_do_not_panic(mutations in prop::collection::vec(mutation(), 1..6)) {
+            let mut lines: Vec<String> = ASSEMBLY.lines().map(str::to_string).collect();
+            for mutation in &mutations {
+                mutate(&mut lines, mutation);
+            }
+
+            match load_assembly(&lines.join("\n")) {
+                Ok(_) | Err(LoadError::MissingFormatVersion) | Err(LoadError::UnsupportedFormatVersion { .. }) => {},
+                Err(LoadError::Syntax { line, .. }) => prop_assert!(line >= 1 && line <= lines.len().max(1)),
+                Err(error) => prop_assert!(false, "Unexpected error {}", error),
+            }
+        }
+
+        #[test]
+        fn text_bodies_round_trip(text_lines in prop::collection::vec(text_line(), 0..8)) {
+            let text: String = text_lines.join("\n");
+            let source: String = format!("PALASM 1\nMODEL tests/city\nSTART\n{}\nEND", text);
+
+            let assembly: Assembly = load_assembly(&source).unwrap();
+            prop_assert_eq!(&assembly.models["tests/city"].text, &text);
+        }
+    }
+}
@@ -5,5 +5,7 @@ pub mod prompt;
 pub mod function;
 pub mod task;
 pub mod assembly;
+pub mod package_metadata;
 pub mod assemblies_cache;
 pub mod loader;
+pub mod load_error;
@@ -0,0 +1,7 @@
+/// What `compile_package` writes about the package an assembly was compiled from.
+#[derive(Debug, Clone)]
+pub struct PackageMetadata {
+    pub name: String,
+    pub description: String,
+    pub version: String,
+}
@@ -5,5 +5,13 @@ pub struct Prompt {
     pub name: String,
     pub parameters: Vec<Parameter>,
     pub return_type: String,
+    pub tools: Vec<String>,
+    pub annotations: Vec<String>,
     pub text: String,
 }
+
+impl Prompt {
+    pub fn is_cacheable(&self) -> bool {
+        !self.annotations.iter().any(|annotation| annotation == "nocache")
+    }
+}
@@ -1,40 +1,80 @@
-use std::{fs, path::PathBuf};
+use std::{fs, path::PathBuf, sync::{OnceLock, RwLock}};
 
-use assembly::{assembly::Assembly, loader::load_assembly};
-use llm::llm::LargeLanguageModel;
+use assembly::{assembly::Assembly, load_error::LoadError, loader::load_assembly};
+use llm::{llm::LargeLanguageModel, llm_error::LlmError, llm_registry::LargeLanguageModelRegistry};
+use serde_json::Value;
+use standard_library::load_standard_library;
 use virtualization::virtual_machine::VirtualMachine;
 
 pub mod assembly;
 pub mod virtualization;
 pub mod llm;
+pub mod native;
+pub mod standard_library;
 
-pub fn load_assembly_file(file: &PathBuf) -> Result<Assembly, String> {
+pub fn load_assembly_file(file: &PathBuf) -> Result<Assembly, LoadError> {
     let assembly_code: String = fs::read_to_string(file)
-                                   .map_err(|e| e.to_string())?;
+                                   .map_err(|e| LoadError::Io { file: file.clone(), source: e })?;
 
     load_assembly(&assembly_code)
 }
 
-pub fn choose_llm(llm: &String) -> Result<LargeLanguageModel, String> {
-    match llm.to_lowercase().as_str() {
-        "groq" => {
-            let authorization_token: String = std::env::var("GROQ_AUTHORIZATION_TOKEN")
-                                                       .map_err(|e| e.to_string())?;
-            Ok(LargeLanguageModel::new_groq(&authorization_token))
-        },
-        "ollama" => {
-            let base_url: String = std::env::var("OLLAMA_BASE_URL")
-                                            .map_err(|e| e.to_string())?;
-            Ok(LargeLanguageModel::new_ollama(&base_url))
-        },
-        _ => Err(format!("Large Language Model \"{}\" not found", llm.to_lowercase())),
-    }
+static LLM_REGISTRY: OnceLock<RwLock<LargeLanguageModelRegistry>> = OnceLock::new();
+
+fn get_llm_registry() -> &'static RwLock<LargeLanguageModelRegistry> {
+    LLM_REGISTRY.get_or_init(|| RwLock::new(LargeLanguageModelRegistry::with_builtin_providers()))
+}
+
+/// Makes a large language model provider available to `choose_llm` under `name`.
+pub fn register_llm<F>(name: &str, factory: F)
+    where F: Fn(&Value) -> Result<LargeLanguageModel, LlmError> + Send + Sync + 'static
+{
+    get_llm_registry().write().unwrap().register(name, factory);
+}
+
+pub fn choose_llm(llm: &str) -> Result<LargeLanguageModel, LlmError> {
+    choose_llm_with_configuration(llm, &Value::Null)
+}
+
+pub fn choose_llm_with_configuration(llm: &str, configuration: &Value) -> Result<LargeLanguageModel, LlmError> {
+    get_llm_registry().read().unwrap().create(llm, configuration)
 }
 
 pub fn boot_machine(llm: &LargeLanguageModel) -> VirtualMachine {
-    let vm: VirtualMachine = VirtualMachine::new(llm);
+    let mut vm: VirtualMachine = VirtualMachine::new(llm);
 
-    // Todo: load std assemblies into vm.
+    let standard_library: Assembly = load_standard_library()
+        .expect("The standard library assembly shipped with the virtual machine is invalid");
+    vm.load_assembly(&standard_library)
+        .expect("The standard library assembly shipped with the virtual machine is inconsistent");
 
     vm
 }
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use llm::{message::Message, model_settings::ModelSettings, mock_llm::MockLargeLanguageModel};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn chooses_registered_providers() {
+        register_llm("tests-provider", |configuration| {
+            let llm = MockLargeLanguageModel::from_configuration(configuration)?;
+            Ok(LargeLanguageModel::new_mock(llm))
+        });
+
+        let llm: LargeLanguageModel = choose_llm_with_configuration("Tests-Provider", &json!({ "default": "configured" })).unwrap();
+        let response = llm.invoke(
+            &"tests/ask".to_string(),
+            &vec![Message::user("Configured?")],
+            &Vec::new(),
+            &ModelSettings { model: "mock".to_string(), temperature: 0.0, max_tokens: 16 },
+        ).await.unwrap();
+
+        assert_eq!(response.get_content(), "configured");
+        assert!(matches!(choose_llm("tests-missing"), Err(LlmError::NotFound(name)) if name == "tests-missing"));
+    }
+}
@@ -0,0 +1,298 @@
+use std::{fs, path::PathBuf, str::FromStr, sync::{Arc, Mutex}};
+
+use serde::{Deserialize, Serialize};
+
+use super::{invokable_llm::DeltaHandler, llm::LargeLanguageModel, llm_error::LlmError, llm_response::LargeLanguageModelResponse, message::Message, model_settings::ModelSettings, retry_policy::RetryPolicy, storage_error::StorageError, tool::Tool};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CassetteMode {
+    /// Calls the large language model and saves every interaction.
+    Record,
+    /// Answers from the saved interactions without calling the large language model.
+    Replay,
+    /// Calls the large language model without touching the cassette.
+    Passthrough,
+}
+
+impl FromStr for CassetteMode {
+    type Err = String;
+
+    fn from_str(mode: &str) -> Result<Self, Self::Err> {
+        match mode.to_lowercase().as_str() {
+            "record" => Ok(CassetteMode::Record),
+            "replay" => Ok(CassetteMode::Replay),
+            "passthrough" => Ok(CassetteMode::Passthrough),
+            _ => Err(format!("Unknown cassette mode \"{}\", expected record, replay or passthrough", mode)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InteractionKey {
+    pub provider: String,
+    pub settings: ModelSettings,
+    /// The whole conversation sent, session history and tool calls included.
+    pub messages: Vec<Message>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Interaction {
+    pub key: InteractionKey,
+    pub response: LargeLanguageModelResponse,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct CassetteFile {
+    interactions: Vec<Interaction>,
+}
+
+#[derive(Clone)]
+pub struct Cassette {
+    file: PathBuf,
+    mode: CassetteMode,
+    interactions: Arc<Mutex<Vec<Interaction>>>,
+}
+
+impl Cassette {
+    pub fn open(file: &PathBuf, mode: CassetteMode) -> Result<Self, StorageError> {
+        let interactions: Vec<Interaction> = if file.exists() {
+            let raw_cassette: String = fs::read_to_string(file)
+                                          .map_err(|e| StorageError::Read { file: file.clone(), source: e })?;
+            serde_json::from_str::<CassetteFile>(&raw_cassette)
+                .map_err(|e| StorageError::Json { file: file.clone(), source: e })?
+                .interactions
+        }
+        else if mode == CassetteMode::Replay {
+            return Err(StorageError::CassetteNotFound(file.clone()));
+        }
+        else {
+            Vec::new()
+        };
+
+        Ok(Cassette {
+            file: file.clone(),
+            mode,
+            interactions: Arc::new(Mutex::new(interactions)),
+        })
+    }
+
+    fn find(&self, key: &InteractionKey) -> Option<LargeLanguageModelResponse> {
+        self.interactions
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|interaction| &interaction.key == key)
+            .map(|interaction| interaction.response.clone())
+    }
+
+    fn record(&self, key: InteractionKey, response: &LargeLanguageModelResponse) -> Result<(), StorageError> {
+        let mut interactions = self.interactions.lock().unwrap();
+
+        interactions.retain(|interaction| interaction.key != key);
+        interactions.push(Interaction { key, response: response.clone() });
+
+        let raw_cassette: String = serde_json::to_string_pretty(
+            &CassetteFile { interactions: interactions.clone() }
+        ).map_err(|e| StorageError::Json { file: self.file.clone(), source: e })?;
+
+        fs::write(&self.file, raw_cassette).map_err(|e| StorageError::Write { file: self.file.clone(), source: e })
+    }
+}
+
+/// Records or replays the interactions of the wrapped large language model.
+#[derive(Clone)]
+pub struct CassetteLargeLanguageModel {
+    /// Missing when only replaying, so no provider has to be configured.
+    llm: Option<Box<LargeLanguageModel>>,
+    provider: String,
+    cassette: Cassette,
+}
+
+impl CassetteLargeLanguageModel {
+    pub fn new(llm: &LargeLanguageModel, cassette: &Cassette) -> Self {
+        CassetteLargeLanguageModel {
+            llm: Some(Box::new(llm.clone())),
+            provider: llm.get_provider_name(),
+            cassette: cassette.clone(),
+        }
+    }
+
+    /// Answers from the interactions recorded with `provider`, without any
+    /// model to call.
+    pub fn new_replay(provider: &str, cassette: &Cassette) -> Self {
+        CassetteLargeLanguageModel {
+            llm: None,
+            provider: provider.to_string(),
+            cassette: cassette.clone(),
+        }
+    }
+
+    pub fn set_retry_policy(&mut self, retry_policy: &RetryPolicy) {
+        if let Some(llm) = &mut self.llm {
+            **llm = llm.with_retry_policy(retry_policy);
+        }
+    }
+
+    pub fn get_provider_name(&self) -> String {
+        self.provider.clone()
+    }
+
+    pub fn get_base_url(&self) -> Option<String> {
+        self.llm.as_ref().and_then(|llm| llm.get_base_url())
+    }
+
+    pub async fn invoke(
+        &self,
+        prompt: &String,
+        messages: &Vec<Message>,
+        tools: &Vec<Tool>,
+        settings: &ModelSettings,
+        on_delta: Option<DeltaHandler<'_>>,
+    ) -> Result<LargeLanguageModelResponse, LlmError> {
+        let llm = || self.llm.as_ref().ok_or_else(|| LlmError::ReplayOnly(self.cassette.file.clone()));
+
+        if self.cassette.mode == CassetteMode::Passthrough {
+            return Box::pin(llm()?.invoke_with_handler(prompt, messages, tools, settings, on_delta)).await;
+        }
+
+        let key: InteractionKey = InteractionKey {
+            provider: self.get_provider_name(),
+            settings: settings.clone(),
+            messages: messages.clone(),
+        };
+
+        match self.cassette.mode {
+            CassetteMode::Replay => {
+                let response: LargeLanguageModelResponse = self.cassette.find(&key).ok_or_else(|| {
+                    LlmError::RecordingNotFound {
+                        prompt: prompt.clone(),
+                        model: settings.model.clone(),
+                        cassette: self.cassette.file.clone(),
+                    }
+                })?;
+
+                // Recordings keep the whole response, so it is replayed in one piece
+                if let Some(on_delta) = on_delta {
+                    on_delta(&response.message.content);
+                }
+
+                Ok(response)
+            },
+            _ => {
+                let response: LargeLanguageModelResponse = Box::pin(
+                    llm()?.invoke_with_handler(prompt, messages, tools, settings, on_delta)
+                ).await?;
+                self.cassette.record(key, &response).map_err(LlmError::Recording)?;
+                Ok(response)
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::llm::mock_llm::{MockFixtures, MockLargeLanguageModel};
+
+    use super::*;
+
+    fn get_cassette_file(name: &str) -> PathBuf {
+        let file: PathBuf = std::env::temp_dir().join(format!("palang-cassette-{}-{}.json", name, std::process::id()));
+        let _ = fs::remove_file(&file);
+        file
+    }
+
+    fn get_mock_llm(response: &str) -> LargeLanguageModel {
+        LargeLanguageModel::new_mock(MockLargeLanguageModel::new(&MockFixtures {
+            responses: Vec::new(),
+            default: Some(response.to_string()),
+        }).unwrap())
+    }
+
+    fn get_settings() -> ModelSettings {
+        ModelSettings { model: "llama3".to_string(), temperature: 0.0, max_tokens: 16 }
+    }
+
+    fn get_conversation(history: &str) -> Vec<Message> {
+        vec![
+            Message::system("Answer briefly."),
+            Message::user(history),
+            Message::assistant("Noted."),
+            Message::user("What did I say?"),
+        ]
+    }
+
+    async fn invoke(llm: &LargeLanguageModel, messages: &Vec<Message>) -> Result<String, LlmError> {
+        llm.invoke(&"tests/recall".to_string(), messages, &Vec::new(), &get_settings())
+           .await
+           .map(|response| response.message.content)
+    }
+
+    #[tokio::test]
+    async fn replays_without_a_model() {
+        let file: PathBuf = get_cassette_file("replay");
+        let recording: Cassette = Cassette::open(&file, CassetteMode::Record).unwrap();
+        let conversation: Vec<Message> = get_conversation("My name is Ada.");
+        invoke(&get_mock_llm("Ada").with_cassette(&recording), &conversation).await.unwrap();
+
+        let replay: LargeLanguageModel = LargeLanguageModel::new_replay(
+            "mock",
+            &Cassette::open(&file, CassetteMode::Replay).unwrap(),
+        );
+        let result: Result<String, LlmError> = invoke(&replay, &conversation).await;
+        fs::remove_file(&file).unwrap();
+
+        assert_eq!(result.unwrap(), "Ada");
+    }
+
+    #[tokio::test]
+    async fn tells_conversations_apart_by_their_history() {
+        let file: PathBuf = get_cassette_file("history");
+        let recording: Cassette = Cassette::open(&file, CassetteMode::Record).unwrap();
+        invoke(&get_mock_llm("Ada").with_cassette(&recording), &get_conversation("My name is Ada.")).await.unwrap();
+        invoke(&get_mock_llm("Alan").with_cassette(&recording), &get_conversation("My name is Alan.")).await.unwrap();
+
+        let replay: LargeLanguageModel = LargeLanguageModel::new_replay(
+            "mock",
+            &Cassette::open(&file, CassetteMode::Replay).unwrap(),
+        );
+        let ada: Result<String, LlmError> = invoke(&replay, &get_conversation("My name is Ada.")).await;
+        let alan: Result<String, LlmError> = invoke(&replay, &get_conversation("My name is Alan.")).await;
+        let unknown: Result<String, LlmError> = invoke(&replay, &get_conversation("My name is Grace.")).await;
+        fs::remove_file(&file).unwrap();
+
+        assert_eq!(ada.unwrap(), "Ada");
+        assert_eq!(alan.unwrap(), "Alan");
+        assert!(matches!(unknown, Err(LlmError::RecordingNotFound { .. })));
+    }
+
+    #[tokio::test]
+    async fn replays_only_the_recorded_provider() {
+        let file: PathBuf = get_cassette_file("provider");
+        let recording: Cassette = Cassette::open(&file, CassetteMode::Record).unwrap();
+        let conversation: Vec<Message> = get_conversation("My name is Ada.");
+        invoke(&get_mock_llm("Ada").with_cassette(&recording), &conversation).await.unwrap();
+
+        let replay: LargeLanguageModel = LargeLanguageModel::new_replay(
+            "groq",
+            &Cassette::open(&file, CassetteMode::Replay).unwrap(),
+        );
+        let result: Result<String, LlmError> = invoke(&replay, &conversation).await;
+        fs::remove_file(&file).unwrap();
+
+        assert!(matches!(result, Err(LlmError::RecordingNotFound { .. })));
+    }
+
+    #[tokio::test]
+    async fn cannot_record_without_a_model() {
+        let file: PathBuf = get_cassette_file("record");
+        let replay: LargeLanguageModel = LargeLanguageModel::new_replay(
+            "mock",
+            &Cassette::open(&file, CassetteMode::Record).unwrap(),
+        );
+
+        let result: Result<String, LlmError> = invoke(&replay, &get_conversation("My name is Ada.")).await;
+
+        assert!(matches!(result, Err(LlmError::ReplayOnly(_))));
+        assert!(!file.exists());
+    }
+}
@@ -0,0 +1,62 @@
+use std::env::VarError;
+
+use reqwest::header::{InvalidHeaderName, InvalidHeaderValue};
+use thiserror::Error;
+
+use super::storage_error::StorageError;
+
+/// Why a setting of a profile is invalid. Settings are named by their path
+/// in the profile, like `rate_limits.requests_per_minute`.
+#[derive(Debug, Error)]
+pub enum ConfigurationError {
+    #[error("Missing {0}")]
+    Missing(String),
+
+    #[error("Invalid {setting} ({source})")]
+    Invalid {
+        setting: String,
+        #[source]
+        source: serde_json::Error,
+    },
+
+    #[error("Invalid {0}, expected a positive integer")]
+    NotPositive(String),
+
+    #[error("Unknown {setting} \"{value}\", expected {expected}")]
+    Unknown {
+        setting: String,
+        value: String,
+        expected: String,
+    },
+
+    #[error("Could not read environment variable {variable} ({source})")]
+    Environment {
+        variable: String,
+        #[source]
+        source: VarError,
+    },
+
+    #[error("Invalid header name {name} ({source})")]
+    HeaderName {
+        name: String,
+        #[source]
+        source: InvalidHeaderName,
+    },
+
+    #[error("Invalid value for header {name} ({source})")]
+    HeaderValue {
+        name: String,
+        #[source]
+        source: InvalidHeaderValue,
+    },
+
+    #[error("Invalid pattern {pattern} ({source})")]
+    Pattern {
+        pattern: String,
+        #[source]
+        source: regex::Error,
+    },
+
+    #[error(transparent)]
+    Storage(#[from] StorageError),
+}
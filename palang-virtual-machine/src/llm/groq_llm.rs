@@ -1,76 +1,50 @@
-use reqwest::{header::{HeaderMap, HeaderValue, AUTHORIZATION, CONTENT_TYPE}, Client};
-use serde_json::{json, Value};
+use std::collections::HashMap;
 
-use super::{invokable_llm::InvokableLargeLanguageModel, model_settings::ModelSettings};
+use super::{invokable_llm::{DeltaHandler, InvocationFuture, InvokableLargeLanguageModel}, message::Message, model_settings::ModelSettings, openai_compatible_llm::OpenAiCompatibleLargeLanguageModel, retry_policy::RetryPolicy, tool::Tool};
 
 #[derive(Clone)]
 pub struct GroqLargeLanguageModel {
-    client: Client,
-    authorization_token: String,
+    llm: OpenAiCompatibleLargeLanguageModel,
 }
 
 impl InvokableLargeLanguageModel for GroqLargeLanguageModel {
-    async fn invoke(
-        &self,
-        system: &String,
-        prompt: &String,
-        settings: &ModelSettings,
-    ) -> Result<String, String> {
-        let body = json!({
-            "messages": [
-                {
-                    "role": "system",
-                    "content": system,
-                },
-                {
-                    "role": "user",
-                    "content": prompt,
-                }
-            ],
-            "model": settings.model,
-            "temperature": settings.temperature,
-            "max_tokens": settings.max_tokens,
-            "top_p": 1,
-            "stream": false,
-            "stop": null,
-        });
-
-        let mut headers = HeaderMap::new();
-        headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
-        headers.insert(AUTHORIZATION,
-            HeaderValue::from_str(
-                &format!("Bearer {}", self.authorization_token).as_str()
-            ).map_err(|e| e.to_string())?
-        );
-
-        let response: Value = self.client
-            .post("https://api.groq.com/openai/v1/chat/completions")
-            .headers(headers)
-            .json(&body)
-            .send()
-            .await
-            .map_err(|e| e.to_string())?
-            .json()
-            .await
-            .map_err(|e| e.to_string())?;
+    fn invoke<'a>(
+        &'a self,
+        messages: &'a [Message],
+        tools: &'a [Tool],
+        settings: &'a ModelSettings,
+    ) -> InvocationFuture<'a> {
+        self.llm.invoke(messages, tools, settings)
+    }
 
-        // Extract the content from the first choice's message
-        response
-            .get("choices")
-            .and_then(|choices| choices.get(0))
-            .and_then(|choice| choice.get("message"))
-            .and_then(|message| message.get("content"))
-            .and_then(|content| content.as_str())
-            .map(String::from)
-            .ok_or_else(|| "Failed to extract content from response".to_string())
+    fn invoke_streaming<'a>(
+        &'a self,
+        messages: &'a [Message],
+        tools: &'a [Tool],
+        settings: &'a ModelSettings,
+        on_delta: DeltaHandler<'a>,
+    ) -> InvocationFuture<'a> {
+        self.llm.invoke_streaming(messages, tools, settings, on_delta)
     }
 }
 
 impl GroqLargeLanguageModel {
     pub fn new(authorization_token: &String) -> Self {
         GroqLargeLanguageModel {
-            client: Client::new(),
-            authorization_token: authorization_token.clone(),
+            llm: OpenAiCompatibleLargeLanguageModel::new(
+                "https://api.groq.com/openai/v1",
+                &Some(authorization_token.clone()),
+                &HashMap::new(),
+                &HashMap::new(),
+            ),
         }
     }
+
+    pub fn set_retry_policy(&mut self, retry_policy: &RetryPolicy) {
+        self.llm.set_retry_policy(retry_policy);
+    }
+
+    pub fn get_retry_policy(&self) -> &RetryPolicy {
+        self.llm.get_retry_policy()
+    }
 }
@@ -0,0 +1,80 @@
+use std::time::Duration;
+
+use reqwest::{Client, Response, StatusCode};
+use serde_json::Value;
+
+use super::llm_error::LlmError;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const READ_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Builds the HTTP client used by providers. Requests time out when the
+/// provider stops answering instead of hanging forever, but long streams are
+/// not cut off: the invocation timeout of the machine bounds their duration.
+pub fn new_http_client() -> Client {
+    Client::builder()
+        .connect_timeout(CONNECT_TIMEOUT)
+        .read_timeout(READ_TIMEOUT)
+        .build()
+        .unwrap_or_else(|_| Client::new())
+}
+
+/// Reads a provider's JSON response, turning HTTP errors into readable messages.
+pub async fn read_json_response(response: Response, provider: &str) -> Result<Value, LlmError> {
+    let status: StatusCode = response.status();
+    if !status.is_success() {
+        return Err(read_error_response(response, provider).await);
+    }
+
+    let body: String = response.text().await.map_err(|e| LlmError::from_request_error(provider, e))?;
+
+    serde_json::from_str(&body)
+        .map_err(|e| LlmError::invalid_response(provider, format!("invalid JSON, {}: {}", e, body.trim())))
+}
+
+pub async fn read_error_response(response: Response, provider: &str) -> LlmError {
+    let status: StatusCode = response.status();
+
+    LlmError::Status {
+        provider: provider.to_string(),
+        status,
+        body: match response.text().await {
+            Ok(body) => body.trim().to_string(),
+            Err(e) => format!("({})", e),
+        },
+    }
+}
+
+/// Calls `on_line` with each non-empty line of a streamed response as it arrives.
+pub async fn read_response_lines<F>(mut response: Response, provider: &str, mut on_line: F) -> Result<(), LlmError>
+    where F: FnMut(&str) -> Result<(), LlmError>
+{
+    let mut buffer: Vec<u8> = Vec::new();
+
+    loop {
+        let chunk = response
+            .chunk()
+            .await
+            .map_err(|e| LlmError::from_request_error(provider, e))?;
+
+        match chunk {
+            Some(chunk) => buffer.extend_from_slice(&chunk),
+            None => break,
+        }
+
+        while let Some(end_of_line) = buffer.iter().position(|byte| *byte == b'\n') {
+            let line: Vec<u8> = buffer.drain(..=end_of_line).collect();
+            let line: String = String::from_utf8_lossy(&line).trim().to_string();
+            if !line.is_empty() {
+                on_line(&line)?;
+            }
+        }
+    }
+
+    let line: String = String::from_utf8_lossy(&buffer).trim().to_string();
+    if !line.is_empty() {
+        on_line(&line)?;
+    }
+
+    Ok(())
+}
@@ -1,10 +1,33 @@
-use super::model_settings::ModelSettings;
-
-pub trait InvokableLargeLanguageModel {
-    fn invoke(
-        &self,
-        system: &String,
-        prompt: &String,
-        settings: &ModelSettings,
-    ) -> impl std::future::Future<Output = Result<String, String>> + Send;
+use std::{future::Future, pin::Pin};
+
+use super::{llm_error::LlmError, llm_response::LargeLanguageModelResponse, message::Message, model_settings::ModelSettings, tool::Tool};
+
+pub type InvocationFuture<'a> = Pin<Box<dyn Future<Output = Result<LargeLanguageModelResponse, LlmError>> + Send + 'a>>;
+
+/// Receives each piece of content as it is generated.
+pub type DeltaHandler<'a> = &'a (dyn Fn(&str) + Send + Sync);
+
+pub trait InvokableLargeLanguageModel: Send + Sync {
+    fn invoke<'a>(
+        &'a self,
+        messages: &'a [Message],
+        tools: &'a [Tool],
+        settings: &'a ModelSettings,
+    ) -> InvocationFuture<'a>;
+
+    /// Streams the content through `on_delta` while generating it. Models that
+    /// cannot stream send the whole content at once.
+    fn invoke_streaming<'a>(
+        &'a self,
+        messages: &'a [Message],
+        tools: &'a [Tool],
+        settings: &'a ModelSettings,
+        on_delta: DeltaHandler<'a>,
+    ) -> InvocationFuture<'a> {
+        Box::pin(async move {
+            let response: LargeLanguageModelResponse = self.invoke(messages, tools, settings).await?;
+            on_delta(response.get_content());
+            Ok(response)
+        })
+    }
 }
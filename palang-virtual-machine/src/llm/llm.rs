@@ -1,9 +1,16 @@
-use super::{groq_llm::GroqLargeLanguageModel, invokable_llm::InvokableLargeLanguageModel, model_settings::ModelSettings, ollama_llm::OllamaLargeLanguageModel};
+use std::{sync::Arc, time::Instant};
+
+use super::{cassette::{Cassette, CassetteLargeLanguageModel}, groq_llm::GroqLargeLanguageModel, invokable_llm::{DeltaHandler, InvokableLargeLanguageModel}, llm_error::LlmError, llm_response::LargeLanguageModelResponse, message::Message, mock_llm::MockLargeLanguageModel, model_settings::ModelSettings, ollama_llm::OllamaLargeLanguageModel, openai_compatible_llm::OpenAiCompatibleLargeLanguageModel, rate_limiter::{RateLimitedLargeLanguageModel, RateLimiter}, retry_policy::RetryPolicy, tool::Tool};
 
 #[derive(Clone)]
 pub enum LargeLanguageModel {
     Groq(GroqLargeLanguageModel),
     Ollama(OllamaLargeLanguageModel),
+    OpenAiCompatible(OpenAiCompatibleLargeLanguageModel),
+    Mock(MockLargeLanguageModel),
+    Cassette(CassetteLargeLanguageModel),
+    RateLimited(RateLimitedLargeLanguageModel),
+    Custom(Arc<dyn InvokableLargeLanguageModel + Send + Sync>),
 }
 
 impl LargeLanguageModel {
@@ -15,15 +22,132 @@ impl LargeLanguageModel {
         LargeLanguageModel::Ollama(OllamaLargeLanguageModel::new(base_url))
     }
 
+    pub fn new_ollama_openai_compatible(base_url: &str) -> Self {
+        LargeLanguageModel::Ollama(OllamaLargeLanguageModel::new_openai_compatible(base_url))
+    }
+
+    pub fn new_openai_compatible(llm: OpenAiCompatibleLargeLanguageModel) -> Self {
+        LargeLanguageModel::OpenAiCompatible(llm)
+    }
+
+    pub fn new_mock(llm: MockLargeLanguageModel) -> Self {
+        LargeLanguageModel::Mock(llm)
+    }
+
+    pub fn with_cassette(&self, cassette: &Cassette) -> Self {
+        LargeLanguageModel::Cassette(CassetteLargeLanguageModel::new(self, cassette))
+    }
+
+    /// Answers from the cassette alone, as if recorded with `provider`.
+    pub fn new_replay(provider: &str, cassette: &Cassette) -> Self {
+        LargeLanguageModel::Cassette(CassetteLargeLanguageModel::new_replay(provider, cassette))
+    }
+
+    pub fn with_rate_limiter(&self, rate_limiter: &RateLimiter) -> Self {
+        LargeLanguageModel::RateLimited(RateLimitedLargeLanguageModel::new(self, rate_limiter))
+    }
+
+    pub fn new_custom<T>(llm: T) -> Self
+        where T: InvokableLargeLanguageModel + Send + Sync + 'static
+    {
+        LargeLanguageModel::Custom(Arc::new(llm))
+    }
+
+    pub fn with_retry_policy(&self, retry_policy: &RetryPolicy) -> Self {
+        let mut llm: LargeLanguageModel = self.clone();
+
+        match &mut llm {
+            LargeLanguageModel::Groq(llm) => llm.set_retry_policy(retry_policy),
+            LargeLanguageModel::Ollama(llm) => llm.set_retry_policy(retry_policy),
+            LargeLanguageModel::OpenAiCompatible(llm) => llm.set_retry_policy(retry_policy),
+            LargeLanguageModel::Cassette(llm) => llm.set_retry_policy(retry_policy),
+            LargeLanguageModel::RateLimited(llm) => llm.set_retry_policy(retry_policy),
+            LargeLanguageModel::Mock(_) | LargeLanguageModel::Custom(_) => {},
+        }
+
+        llm
+    }
+
+    pub fn get_provider_name(&self) -> String {
+        match self {
+            LargeLanguageModel::Groq(_) => "groq".to_string(),
+            LargeLanguageModel::Ollama(_) => "ollama".to_string(),
+            LargeLanguageModel::OpenAiCompatible(_) => "openai-compatible".to_string(),
+            LargeLanguageModel::Mock(_) => "mock".to_string(),
+            LargeLanguageModel::Cassette(llm) => llm.get_provider_name(),
+            LargeLanguageModel::RateLimited(llm) => llm.get_provider_name(),
+            LargeLanguageModel::Custom(_) => "custom".to_string(),
+        }
+    }
+
+    /// The server called, for the providers that can be self-hosted.
+    pub fn get_base_url(&self) -> Option<String> {
+        match self {
+            LargeLanguageModel::Ollama(llm) => Some(llm.get_base_url().clone()),
+            LargeLanguageModel::OpenAiCompatible(llm) => Some(llm.get_base_url().clone()),
+            LargeLanguageModel::Cassette(llm) => llm.get_base_url(),
+            LargeLanguageModel::RateLimited(llm) => llm.get_base_url(),
+            LargeLanguageModel::Groq(_) | LargeLanguageModel::Mock(_) | LargeLanguageModel::Custom(_) => None,
+        }
+    }
+
     pub async fn invoke(
         &self,
-        system: &String,
         prompt: &String,
+        messages: &Vec<Message>,
+        tools: &Vec<Tool>,
         settings: &ModelSettings,
-    ) -> Result<String, String> {
-        match self {
-            LargeLanguageModel::Groq(llm) => llm.invoke(system, prompt, settings).await,
-            LargeLanguageModel::Ollama(llm) => llm.invoke(system, prompt, settings).await,
-        }
+    ) -> Result<LargeLanguageModelResponse, LlmError> {
+        self.invoke_with_handler(prompt, messages, tools, settings, None).await
+    }
+
+    /// Invokes the model, passing each piece of the response to `on_delta` as
+    /// soon as the provider sends it.
+    pub async fn invoke_streaming(
+        &self,
+        prompt: &String,
+        messages: &Vec<Message>,
+        tools: &Vec<Tool>,
+        settings: &ModelSettings,
+        on_delta: DeltaHandler<'_>,
+    ) -> Result<LargeLanguageModelResponse, LlmError> {
+        self.invoke_with_handler(prompt, messages, tools, settings, Some(on_delta)).await
+    }
+
+    pub(crate) async fn invoke_with_handler(
+        &self,
+        prompt: &String,
+        messages: &Vec<Message>,
+        tools: &Vec<Tool>,
+        settings: &ModelSettings,
+        on_delta: Option<DeltaHandler<'_>>,
+    ) -> Result<LargeLanguageModelResponse, LlmError> {
+        let started_at: Instant = Instant::now();
+
+        let mut response: LargeLanguageModelResponse = match (self, on_delta) {
+            (LargeLanguageModel::Groq(llm), None) => llm.invoke(messages, tools, settings).await,
+            (LargeLanguageModel::Groq(llm), Some(on_delta)) => llm.invoke_streaming(messages, tools, settings, on_delta).await,
+            (LargeLanguageModel::Ollama(llm), None) => llm.invoke(messages, tools, settings).await,
+            (LargeLanguageModel::Ollama(llm), Some(on_delta)) => llm.invoke_streaming(messages, tools, settings, on_delta).await,
+            (LargeLanguageModel::OpenAiCompatible(llm), None) => llm.invoke(messages, tools, settings).await,
+            (LargeLanguageModel::OpenAiCompatible(llm), Some(on_delta)) => llm.invoke_streaming(messages, tools, settings, on_delta).await,
+            (LargeLanguageModel::Mock(llm), on_delta) => llm.invoke(prompt, messages, settings).inspect(|response| {
+                if let Some(on_delta) = on_delta {
+                    on_delta(&response.message.content);
+                }
+            }),
+            (LargeLanguageModel::Custom(llm), None) => llm.invoke(messages, tools, settings).await,
+            (LargeLanguageModel::Custom(llm), Some(on_delta)) => llm.invoke_streaming(messages, tools, settings, on_delta).await,
+            // Wrappers report the latency measured by the model they wrap
+            (LargeLanguageModel::Cassette(llm), on_delta) => {
+                return llm.invoke(prompt, messages, tools, settings, on_delta).await;
+            },
+            (LargeLanguageModel::RateLimited(llm), on_delta) => {
+                return llm.invoke(prompt, messages, tools, settings, on_delta).await;
+            },
+        }?;
+
+        response.latency = started_at.elapsed();
+        Ok(response)
     }
 }
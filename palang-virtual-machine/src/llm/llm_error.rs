@@ -0,0 +1,98 @@
+use std::path::PathBuf;
+
+use reqwest::StatusCode;
+use thiserror::Error;
+use tokio::sync::AcquireError;
+
+use super::{configuration_error::ConfigurationError, storage_error::StorageError};
+
+/// Why a large language model could not be created or invoked.
+#[derive(Debug, Error)]
+pub enum LlmError {
+    #[error("Large Language Model \"{0}\" not found")]
+    NotFound(String),
+
+    #[error(transparent)]
+    Configuration(#[from] ConfigurationError),
+
+    #[error("Could not reach {provider} ({source})")]
+    Connection {
+        provider: String,
+        #[source]
+        source: reqwest::Error,
+    },
+
+    #[error("{provider} did not respond in time ({source})")]
+    Timeout {
+        provider: String,
+        #[source]
+        source: reqwest::Error,
+    },
+
+    #[error("{provider} returned HTTP {status}: {body}")]
+    Status {
+        provider: String,
+        status: StatusCode,
+        body: String,
+    },
+
+    #[error("{provider} returned an invalid response ({message})")]
+    InvalidResponse {
+        provider: String,
+        message: String,
+    },
+
+    #[error("{provider} failed ({message})")]
+    Provider {
+        provider: String,
+        message: String,
+    },
+
+    #[error("No mock response matches prompt {0}")]
+    NoMockResponse(String),
+
+    #[error("No recording of prompt {prompt} with model {model} in cassette {cassette:?}, record it with the record mode")]
+    RecordingNotFound {
+        prompt: String,
+        model: String,
+        cassette: PathBuf,
+    },
+
+    #[error("Could not record the interaction ({0})")]
+    Recording(#[source] StorageError),
+
+    #[error("Cassette {0:?} can only be replayed without a large language model")]
+    ReplayOnly(PathBuf),
+
+    #[error("{source}, after {retries} retries")]
+    Retried {
+        retries: u64,
+        #[source]
+        source: Box<LlmError>,
+    },
+
+    #[error("The rate limiter was closed")]
+    RateLimiterClosed(#[from] AcquireError),
+}
+
+impl LlmError {
+    /// Sorts out the timeouts from the other failures to send a request.
+    pub fn from_request_error(provider: &str, error: reqwest::Error) -> Self {
+        match error.is_timeout() {
+            true => LlmError::Timeout { provider: provider.to_string(), source: error },
+            false => LlmError::Connection { provider: provider.to_string(), source: error },
+        }
+    }
+
+    /// Number of times the failed request was retried.
+    pub fn get_retries(&self) -> u64 {
+        match self {
+            LlmError::Retried { retries, .. } => *retries,
+            _ => 0,
+        }
+    }
+
+    pub fn invalid_response(provider: &str, message: String) -> Self {
+        LlmError::InvalidResponse { provider: provider.to_string(), message }
+    }
+}
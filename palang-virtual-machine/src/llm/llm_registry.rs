@@ -0,0 +1,157 @@
+use std::{collections::HashMap, env, sync::Arc};
+
+use serde_json::Value;
+
+use super::{configuration_error::ConfigurationError, llm::LargeLanguageModel, llm_error::LlmError, mock_llm::MockLargeLanguageModel, openai_compatible_llm::OpenAiCompatibleLargeLanguageModel};
+
+/// Builds a large language model from the `llm_configuration` of a profile.
+pub type LargeLanguageModelFactory = Arc<dyn Fn(&Value) -> Result<LargeLanguageModel, LlmError> + Send + Sync>;
+
+#[derive(Default)]
+pub struct LargeLanguageModelRegistry {
+    factories: HashMap<String, LargeLanguageModelFactory>,
+}
+
+impl LargeLanguageModelRegistry {
+    pub fn new() -> Self {
+        LargeLanguageModelRegistry {
+            factories: HashMap::new(),
+        }
+    }
+
+    pub fn with_builtin_providers() -> Self {
+        let mut registry: LargeLanguageModelRegistry = LargeLanguageModelRegistry::new();
+
+        registry.register("groq", |configuration| {
+            let authorization_token: String = get_setting(configuration, "authorization_token", "GROQ_AUTHORIZATION_TOKEN")?;
+            Ok(LargeLanguageModel::new_groq(&authorization_token))
+        });
+        registry.register("ollama", |configuration| {
+            let base_url: String = get_setting(configuration, "base_url", "OLLAMA_BASE_URL")?;
+            match configuration.get("api").and_then(|api| api.as_str()) {
+                Some("openai") => Ok(LargeLanguageModel::new_ollama_openai_compatible(&base_url)),
+                Some("native") | None => Ok(LargeLanguageModel::new_ollama(&base_url)),
+                Some(api) => Err(LlmError::Configuration(
+                    ConfigurationError::Unknown {
+                        setting: "llm_configuration.api".to_string(),
+                        value: api.to_string(),
+                        expected: "native or openai".to_string(),
+                    }
+                )),
+            }
+        });
+        registry.register("openai-compatible", |configuration| {
+            let llm = OpenAiCompatibleLargeLanguageModel::from_configuration(configuration)?;
+            Ok(LargeLanguageModel::new_openai_compatible(llm))
+        });
+        registry.register("mock", |configuration| {
+            let llm = MockLargeLanguageModel::from_configuration(configuration)?;
+            Ok(LargeLanguageModel::new_mock(llm))
+        });
+
+        registry
+    }
+
+    pub fn register<F>(&mut self, name: &str, factory: F)
+        where F: Fn(&Value) -> Result<LargeLanguageModel, LlmError> + Send + Sync + 'static
+    {
+        self.factories.insert(name.to_lowercase(), Arc::new(factory));
+    }
+
+    pub fn create(&self, name: &str, configuration: &Value) -> Result<LargeLanguageModel, LlmError> {
+        match self.factories.get(&name.to_lowercase()) {
+            Some(factory) => factory(configuration),
+            None => Err(LlmError::NotFound(name.to_lowercase())),
+        }
+    }
+
+    pub fn get_names(&self) -> Vec<String> {
+        self.factories.keys().cloned().collect()
+    }
+}
+
+/// Reads a provider setting from the configuration, falling back to an environment variable.
+pub fn get_setting(configuration: &Value, key: &str, environment_variable: &str) -> Result<String, LlmError> {
+    match configuration.get(key).and_then(|value| value.as_str()) {
+        Some(value) => Ok(value.to_string()),
+        None => env::var(environment_variable)
+                    .map_err(|e| ConfigurationError::Environment { variable: environment_variable.to_string(), source: e }.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use wiremock::{matchers::{method, path}, Mock, MockServer, ResponseTemplate};
+
+    use crate::llm::{message::Message, model_settings::ModelSettings};
+
+    use super::*;
+
+    /// Which of the APIs of the server the Ollama model configured with `api` calls.
+    async fn get_ollama_api(api: Value) -> Result<String, LlmError> {
+        let server: MockServer = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/api/chat"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "message": { "role": "assistant", "content": "native" },
+                "done": true,
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/v1/chat/completions"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "choices": [{ "message": { "role": "assistant", "content": "openai" } }],
+            })))
+            .mount(&server)
+            .await;
+
+        let llm: LargeLanguageModel = LargeLanguageModelRegistry::with_builtin_providers()
+            .create("ollama", &json!({ "base_url": server.uri(), "api": api }))?;
+        let response = llm.invoke(
+            &"tests/ask".to_string(),
+            &vec![Message::user("Which API?")],
+            &Vec::new(),
+            &ModelSettings { model: "llama3".to_string(), temperature: 0.0, max_tokens: 16 },
+        ).await?;
+
+        Ok(response.get_content().clone())
+    }
+
+    #[tokio::test]
+    async fn selects_the_ollama_api() {
+        assert_eq!(get_ollama_api(Value::Null).await.unwrap(), "native");
+        assert_eq!(get_ollama_api(json!("native")).await.unwrap(), "native");
+        assert_eq!(get_ollama_api(json!("openai")).await.unwrap(), "openai");
+        assert!(matches!(
+            get_ollama_api(json!("grpc")).await,
+            Err(LlmError::Configuration(ConfigurationError::Unknown { setting, value, .. })) if setting == "llm_configuration.api" && value == "grpc"
+        ));
+    }
+
+    #[test]
+    fn creates_registered_providers_by_case_insensitive_name() {
+        let mut registry: LargeLanguageModelRegistry = LargeLanguageModelRegistry::new();
+        registry.register("Tests", |configuration| {
+            let llm = MockLargeLanguageModel::from_configuration(configuration)?;
+            Ok(LargeLanguageModel::new_mock(llm))
+        });
+
+        assert_eq!(registry.get_names(), vec!["tests"]);
+        assert_eq!(registry.create("TESTS", &Value::Null).unwrap().get_provider_name(), "mock");
+        assert!(matches!(registry.create("other", &Value::Null), Err(LlmError::NotFound(name)) if name == "other"));
+    }
+
+    #[test]
+    fn falls_back_to_environment_variables() {
+        std::env::set_var("PALANG_TESTS_SETTING", "from environment");
+
+        assert_eq!(get_setting(&json!({ "setting": "from profile" }), "setting", "PALANG_TESTS_SETTING").unwrap(), "from profile");
+        assert_eq!(get_setting(&Value::Null, "setting", "PALANG_TESTS_SETTING").unwrap(), "from environment");
+        assert!(matches!(
+            get_setting(&Value::Null, "setting", "PALANG_TESTS_MISSING_SETTING"),
+            Err(LlmError::Configuration(ConfigurationError::Environment { variable, .. })) if variable == "PALANG_TESTS_MISSING_SETTING"
+        ));
+    }
+}
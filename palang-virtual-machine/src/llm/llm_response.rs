@@ -0,0 +1,47 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use super::message::Message;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct TokenUsage {
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+}
+
+impl TokenUsage {
+    pub fn get_total_tokens(&self) -> u64 {
+        self.prompt_tokens + self.completion_tokens
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LargeLanguageModelResponse {
+    pub message: Message,
+    #[serde(default)]
+    pub usage: TokenUsage,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub finish_reason: Option<String>,
+    #[serde(default)]
+    pub latency: Duration,
+    /// Number of times the request was retried before this response.
+    #[serde(skip)]
+    pub retries: u64,
+}
+
+impl LargeLanguageModelResponse {
+    pub fn new(message: &Message) -> Self {
+        LargeLanguageModelResponse {
+            message: message.clone(),
+            usage: TokenUsage::default(),
+            finish_reason: None,
+            latency: Duration::ZERO,
+            retries: 0,
+        }
+    }
+
+    pub fn get_content(&self) -> &String {
+        &self.message.content
+    }
+}
@@ -0,0 +1,72 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::tool::ToolCall;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    System,
+    User,
+    Assistant,
+    Tool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Message {
+    pub role: Role,
+    pub content: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tool_calls: Vec<ToolCall>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_call_id: Option<String>,
+}
+
+impl Message {
+    pub fn system(content: &str) -> Self {
+        Message::new(Role::System, content)
+    }
+
+    pub fn user(content: &str) -> Self {
+        Message::new(Role::User, content)
+    }
+
+    pub fn assistant(content: &str) -> Self {
+        Message::new(Role::Assistant, content)
+    }
+
+    pub fn tool(tool_call_id: &str, content: &str) -> Self {
+        Message {
+            tool_call_id: Some(tool_call_id.to_string()),
+            ..Message::new(Role::Tool, content)
+        }
+    }
+
+    /// Reads an assistant message in the OpenAI chat completions format.
+    pub fn from_json(message: &Value) -> Result<Self, serde_json::Error> {
+        let content: String = message
+            .get("content")
+            .and_then(|content| content.as_str())
+            .unwrap_or_default()
+            .to_string();
+
+        let tool_calls: Vec<ToolCall> = match message.get("tool_calls") {
+            Some(Value::Null) | None => Vec::new(),
+            Some(tool_calls) => serde_json::from_value(tool_calls.clone())?,
+        };
+
+        Ok(Message {
+            tool_calls,
+            ..Message::new(Role::Assistant, &content)
+        })
+    }
+
+    fn new(role: Role, content: &str) -> Self {
+        Message {
+            role,
+            content: content.to_string(),
+            tool_calls: Vec::new(),
+            tool_call_id: None,
+        }
+    }
+}
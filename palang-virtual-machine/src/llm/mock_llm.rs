@@ -0,0 +1,264 @@
+use std::{fs::{self, OpenOptions}, io::Write, path::PathBuf, sync::{Arc, Mutex}};
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::{configuration_error::ConfigurationError, llm_error::LlmError, llm_response::LargeLanguageModelResponse, message::{Message, Role}, model_settings::ModelSettings, storage_error::StorageError};
+
+/// A scripted response, chosen when the prompt name or the pattern matches.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MockResponse {
+    #[serde(default)]
+    pub prompt: Option<String>,
+    #[serde(default)]
+    pub pattern: Option<String>,
+    pub response: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MockFixtures {
+    #[serde(default)]
+    pub responses: Vec<MockResponse>,
+    #[serde(default)]
+    pub default: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MockCall {
+    pub prompt: String,
+    pub messages: Vec<Message>,
+    pub settings: ModelSettings,
+    pub response: Option<String>,
+}
+
+/// Answers prompts from fixtures instead of calling a provider and records every call.
+#[derive(Clone)]
+pub struct MockLargeLanguageModel {
+    responses: Vec<(MockResponse, Option<Regex>)>,
+    default: Option<String>,
+    calls: Arc<Mutex<Vec<MockCall>>>,
+    calls_file: Option<PathBuf>,
+}
+
+impl MockLargeLanguageModel {
+    pub fn new(fixtures: &MockFixtures) -> Result<Self, ConfigurationError> {
+        let responses = fixtures.responses
+            .iter()
+            .map(|response| {
+                let pattern: Option<Regex> = match &response.pattern {
+                    Some(pattern) => Some(
+                        Regex::new(pattern)
+                              .map_err(|e| ConfigurationError::Pattern { pattern: pattern.clone(), source: e })?
+                    ),
+                    None => None,
+                };
+                Ok((response.clone(), pattern))
+            })
+            .collect::<Result<Vec<(MockResponse, Option<Regex>)>, ConfigurationError>>()?;
+
+        Ok(MockLargeLanguageModel {
+            responses,
+            default: fixtures.default.clone(),
+            calls: Arc::new(Mutex::new(Vec::new())),
+            calls_file: None,
+        })
+    }
+
+    /// Builds the mock from a profile's `llm_configuration`. Fixtures are
+    /// read from the YAML or JSON file in `fixtures`, or given inline:
+    ///
+    /// ```yaml
+    /// llm_configuration:
+    ///   calls_file: calls.jsonl
+    ///   responses:
+    ///     - prompt: tutorials/greet
+    ///       response: Hello world!
+    ///     - pattern: (?i)japan
+    ///       response: Visit Kyoto.
+    ///   default: unknown
+    /// ```
+    pub fn from_configuration(configuration: &Value) -> Result<Self, ConfigurationError> {
+        let fixtures: MockFixtures = match configuration.get("fixtures").and_then(|file| file.as_str()) {
+            Some(file) => load_fixtures(&PathBuf::from(file))?,
+            None if configuration.is_null() => MockFixtures::default(),
+            None => serde_json::from_value(configuration.clone())
+                               .map_err(|e| ConfigurationError::Invalid { setting: "llm_configuration".to_string(), source: e })?,
+        };
+
+        let mut llm: MockLargeLanguageModel = MockLargeLanguageModel::new(&fixtures)?;
+        llm.calls_file = configuration
+            .get("calls_file")
+            .and_then(|file| file.as_str())
+            .map(PathBuf::from);
+
+        Ok(llm)
+    }
+
+    pub fn get_calls(&self) -> Vec<MockCall> {
+        self.calls.lock().unwrap().clone()
+    }
+
+    pub fn invoke(
+        &self,
+        prompt: &String,
+        messages: &[Message],
+        settings: &ModelSettings,
+    ) -> Result<LargeLanguageModelResponse, LlmError> {
+        let instructions: String = messages
+            .iter()
+            .rev()
+            .find(|message| message.role == Role::User)
+            .map(|message| message.content.clone())
+            .unwrap_or_default();
+
+        let response: Option<String> = self.responses
+            .iter()
+            .find(|(response, pattern)| {
+                let prompt_matches: bool = response.prompt.as_ref().is_none_or(|name| name == prompt);
+                let pattern_matches: bool = pattern.as_ref().is_none_or(|pattern| pattern.is_match(&instructions));
+                prompt_matches && pattern_matches
+            })
+            .map(|(response, _)| response.response.clone())
+            .or_else(|| self.default.clone());
+
+        self.record(MockCall {
+            prompt: prompt.clone(),
+            messages: messages.to_vec(),
+            settings: settings.clone(),
+            response: response.clone(),
+        }).map_err(LlmError::Recording)?;
+
+        match response {
+            Some(response) => Ok(LargeLanguageModelResponse {
+                finish_reason: Some("stop".to_string()),
+                ..LargeLanguageModelResponse::new(&Message::assistant(&response))
+            }),
+            None => Err(LlmError::NoMockResponse(prompt.clone())),
+        }
+    }
+
+    fn record(&self, call: MockCall) -> Result<(), StorageError> {
+        if let Some(calls_file) = &self.calls_file {
+            let write_error = |e| StorageError::Write { file: calls_file.clone(), source: e };
+
+            let mut file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(calls_file)
+                .map_err(write_error)?;
+            let line: String = serde_json::to_string(&call)
+                                          .map_err(|e| StorageError::Json { file: calls_file.clone(), source: e })?;
+            writeln!(file, "{}", line).map_err(write_error)?;
+        }
+
+        self.calls.lock().unwrap().push(call);
+
+        Ok(())
+    }
+}
+
+pub fn load_fixtures(file: &PathBuf) -> Result<MockFixtures, StorageError> {
+    let raw_fixtures: String = fs::read_to_string(file)
+                                  .map_err(|e| StorageError::Read { file: file.clone(), source: e })?;
+
+    match file.extension().and_then(|extension| extension.to_str()) {
+        Some("json") => serde_json::from_str(&raw_fixtures).map_err(|e| StorageError::Json { file: file.clone(), source: e }),
+        _ => serde_yaml::from_str(&raw_fixtures).map_err(|e| StorageError::Yaml { file: file.clone(), source: e }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn get_mock_llm(configuration: Value) -> MockLargeLanguageModel {
+        MockLargeLanguageModel::from_configuration(&configuration).unwrap()
+    }
+
+    fn get_settings() -> ModelSettings {
+        ModelSettings { model: "mock".to_string(), temperature: 0.0, max_tokens: 16 }
+    }
+
+    fn ask(llm: &MockLargeLanguageModel, prompt: &str, question: &str) -> Result<String, LlmError> {
+        let messages: Vec<Message> = vec![Message::user("Answer in japanese."), Message::assistant("Hai."), Message::user(question)];
+
+        llm.invoke(&prompt.to_string(), &messages, &get_settings())
+           .map(|response| response.get_content().clone())
+    }
+
+    #[test]
+    fn answers_the_first_matching_response() {
+        let llm: MockLargeLanguageModel = get_mock_llm(json!({
+            "responses": [
+                { "prompt": "tests/greet", "response": "Hello world!" },
+                { "pattern": "(?i)japan", "response": "Visit Kyoto." },
+                { "prompt": "tests/travel", "pattern": "France", "response": "Visit Lyon." },
+                { "prompt": "tests/travel", "response": "Stay home." },
+            ],
+        }));
+
+        assert_eq!(ask(&llm, "tests/greet", "Greet Japan.").unwrap(), "Hello world!");
+        assert_eq!(ask(&llm, "tests/travel", "Where in JAPAN?").unwrap(), "Visit Kyoto.");
+        assert_eq!(ask(&llm, "tests/travel", "Where in France?").unwrap(), "Visit Lyon.");
+        assert_eq!(ask(&llm, "tests/travel", "Where in Peru?").unwrap(), "Stay home.");
+        // Patterns match the last user message only
+        assert!(matches!(
+            ask(&llm, "tests/other", "Answer briefly."),
+            Err(LlmError::NoMockResponse(prompt)) if prompt == "tests/other"
+        ));
+    }
+
+    #[test]
+    fn falls_back_to_the_default_response() {
+        let llm: MockLargeLanguageModel = get_mock_llm(json!({
+            "responses": [{ "prompt": "tests/greet", "response": "Hello world!" }],
+            "default": "unknown",
+        }));
+
+        assert_eq!(ask(&llm, "tests/other", "Anything?").unwrap(), "unknown");
+    }
+
+    #[test]
+    fn rejects_invalid_patterns() {
+        let result = MockLargeLanguageModel::from_configuration(&json!({ "responses": [{ "pattern": "(", "response": "never" }] }));
+
+        assert!(matches!(result, Err(ConfigurationError::Pattern { pattern, .. }) if pattern == "("));
+    }
+
+    #[test]
+    fn records_every_call() {
+        let calls_file: PathBuf = std::env::temp_dir().join(format!("palang-mock-calls-{}.jsonl", std::process::id()));
+        let _ = fs::remove_file(&calls_file);
+        let llm: MockLargeLanguageModel = get_mock_llm(json!({
+            "calls_file": calls_file,
+            "responses": [{ "prompt": "tests/greet", "response": "Hello world!" }],
+        }));
+
+        ask(&llm, "tests/greet", "Greet the world.").unwrap();
+        ask(&llm, "tests/other", "Anything?").unwrap_err();
+
+        // Clones share their calls
+        let calls: Vec<MockCall> = llm.clone().get_calls();
+        assert_eq!(
+            calls.iter().map(|call| (call.prompt.as_str(), call.response.as_deref())).collect::<Vec<_>>(),
+            vec![("tests/greet", Some("Hello world!")), ("tests/other", None)],
+        );
+        assert_eq!(calls[0].messages.last().unwrap().content, "Greet the world.");
+        assert_eq!(calls[0].settings.model, "mock");
+
+        let lines: Vec<Value> = fs::read_to_string(&calls_file)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        fs::remove_file(&calls_file).unwrap();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["prompt"], "tests/greet");
+        assert_eq!(lines[0]["response"], "Hello world!");
+        assert_eq!(lines[0]["messages"][2]["content"], "Greet the world.");
+        assert_eq!(lines[1]["response"], Value::Null);
+    }
+}
@@ -1,5 +1,21 @@
 pub mod model_settings;
+pub mod message;
+pub mod tool;
+pub mod tool_call_error;
+pub mod llm_response;
+pub mod llm_error;
+pub mod configuration_error;
+pub mod storage_error;
 pub mod invokable_llm;
+pub mod http_response;
+pub mod retry_policy;
 pub mod llm;
+pub mod llm_registry;
+pub mod openai_compatible_llm;
 pub mod groq_llm;
 pub mod ollama_llm;
+pub mod mock_llm;
+pub mod cassette;
+pub mod response_cache;
+pub mod rate_limiter;
+pub mod price_table;
@@ -1,67 +1,439 @@
-use reqwest::{Client, header::{HeaderMap, HeaderValue, CONTENT_TYPE}};
+use std::{collections::HashMap, time::Duration};
+
+use reqwest::{Client, RequestBuilder, Response, header::{HeaderMap, HeaderValue, CONTENT_TYPE}};
 use serde_json::{json, Value};
 
-use super::{invokable_llm::InvokableLargeLanguageModel, model_settings::ModelSettings};
+use super::{
+    http_response::{new_http_client, read_response_lines},
+    invokable_llm::{DeltaHandler, InvocationFuture, InvokableLargeLanguageModel},
+    llm_error::LlmError,
+    llm_response::{LargeLanguageModelResponse, TokenUsage},
+    message::{Message, Role},
+    model_settings::ModelSettings,
+    openai_compatible_llm::OpenAiCompatibleLargeLanguageModel,
+    retry_policy::RetryPolicy,
+    tool::{Tool, ToolCall, ToolCallFunction}
+};
+
+#[derive(Clone)]
+pub enum OllamaApi {
+    /// Ollama's own `/api/chat` endpoint.
+    Native,
+    /// The OpenAI compatible `/v1/chat/completions` endpoint.
+    OpenAiCompatible(OpenAiCompatibleLargeLanguageModel),
+}
 
 #[derive(Clone)]
 pub struct OllamaLargeLanguageModel {
     client: Client,
     base_url: String,
+    api: OllamaApi,
+    retry_policy: RetryPolicy,
 }
 
 impl InvokableLargeLanguageModel for OllamaLargeLanguageModel {
-    async fn invoke(
+    fn invoke<'a>(
+        &'a self,
+        messages: &'a [Message],
+        tools: &'a [Tool],
+        settings: &'a ModelSettings,
+    ) -> InvocationFuture<'a> {
+        match &self.api {
+            OllamaApi::Native => Box::pin(self.invoke_native(messages, tools, settings)),
+            OllamaApi::OpenAiCompatible(llm) => llm.invoke(messages, tools, settings),
+        }
+    }
+
+    fn invoke_streaming<'a>(
+        &'a self,
+        messages: &'a [Message],
+        tools: &'a [Tool],
+        settings: &'a ModelSettings,
+        on_delta: DeltaHandler<'a>,
+    ) -> InvocationFuture<'a> {
+        match &self.api {
+            OllamaApi::Native => Box::pin(self.invoke_native_streaming(messages, tools, settings, on_delta)),
+            OllamaApi::OpenAiCompatible(llm) => llm.invoke_streaming(messages, tools, settings, on_delta),
+        }
+    }
+}
+
+impl OllamaLargeLanguageModel {
+    pub fn new(base_url: &String) -> Self {
+        OllamaLargeLanguageModel {
+            client: new_http_client(),
+            base_url: base_url.trim_end_matches('/').to_string(),
+            api: OllamaApi::Native,
+            retry_policy: RetryPolicy::default(),
+        }
+    }
+
+    pub fn new_openai_compatible(base_url: &str) -> Self {
+        let base_url: String = base_url.trim_end_matches('/').to_string();
+
+        OllamaLargeLanguageModel {
+            client: new_http_client(),
+            base_url: base_url.clone(),
+            api: OllamaApi::OpenAiCompatible(
+                OpenAiCompatibleLargeLanguageModel::new(
+                    &format!("{}/v1", base_url),
+                    &None,
+                    &HashMap::new(),
+                    &HashMap::new(),
+                )
+            ),
+            retry_policy: RetryPolicy::default(),
+        }
+    }
+
+    pub fn get_base_url(&self) -> &String {
+        &self.base_url
+    }
+
+    pub fn set_retry_policy(&mut self, retry_policy: &RetryPolicy) {
+        self.retry_policy = retry_policy.clone();
+        if let OllamaApi::OpenAiCompatible(llm) = &mut self.api {
+            llm.set_retry_policy(retry_policy);
+        }
+    }
+
+    pub fn get_retry_policy(&self) -> &RetryPolicy {
+        &self.retry_policy
+    }
+
+    async fn invoke_native(
         &self,
-        system: &String,
-        prompt: &String,
+        messages: &[Message],
+        tools: &[Tool],
         settings: &ModelSettings,
-    ) -> Result<String, String> {
-        let body = json!({
-            "messages": [
-                {
-                    "role": "system",
-                    "content": system,
-                },
-                {
-                    "role": "user",
-                    "content": prompt,
-                }
-            ],
+    ) -> Result<LargeLanguageModelResponse, LlmError> {
+        let request = self.get_native_request(messages, tools, settings, false);
+        let (response, retries) = self.retry_policy.send(request, "Ollama").await?;
+
+        // Extract the message
+        let message: Message = response
+            .get("message")
+            .map(from_native_message)
+            .ok_or_else(|| LlmError::invalid_response("Ollama", format!("no message: {}", response)))?;
+
+        Ok(LargeLanguageModelResponse {
+            message,
+            usage: get_native_usage(&response),
+            finish_reason: response["done_reason"].as_str().map(str::to_string),
+            latency: Duration::ZERO,
+            retries,
+        })
+    }
+
+    /// The native API streams one JSON object per line, the last one carrying
+    /// the token counts.
+    async fn invoke_native_streaming(
+        &self,
+        messages: &[Message],
+        tools: &[Tool],
+        settings: &ModelSettings,
+        on_delta: DeltaHandler<'_>,
+    ) -> Result<LargeLanguageModelResponse, LlmError> {
+        let request = self.get_native_request(messages, tools, settings, true);
+        let (response, retries): (Response, u64) = self.retry_policy.send_request(request, "Ollama").await?;
+
+        let mut message: Message = Message::assistant("");
+        let mut usage: TokenUsage = TokenUsage::default();
+        let mut finish_reason: Option<String> = None;
+
+        read_response_lines(response, "Ollama", |line| {
+            if line.trim().is_empty() {
+                return Ok(());
+            }
+
+            let chunk: Value = serde_json::from_str(line)
+                .map_err(|e| LlmError::invalid_response("Ollama", format!("invalid chunk, {}: {}", e, line)))?;
+            if let Some(error) = chunk["error"].as_str() {
+                return Err(LlmError::Provider { provider: "Ollama".to_string(), message: error.to_string() });
+            }
+
+            let delta: Message = from_native_message(&chunk["message"]);
+            if !delta.content.is_empty() {
+                message.content.push_str(&delta.content);
+                on_delta(&delta.content);
+            }
+            message.tool_calls.extend(delta.tool_calls);
+
+            if chunk["done"].as_bool().unwrap_or_default() {
+                usage = get_native_usage(&chunk);
+                finish_reason = chunk["done_reason"].as_str().map(str::to_string);
+            }
+
+            Ok(())
+        }).await?;
+
+        // Tool calls may arrive over several chunks, each numbered from zero
+        for (index, tool_call) in message.tool_calls.iter_mut().enumerate() {
+            tool_call.id = format!("call_{}", index);
+        }
+
+        Ok(LargeLanguageModelResponse {
+            message,
+            usage,
+            finish_reason,
+            latency: Duration::ZERO,
+            retries,
+        })
+    }
+
+    fn get_native_request(
+        &self,
+        messages: &[Message],
+        tools: &[Tool],
+        settings: &ModelSettings,
+        stream: bool,
+    ) -> RequestBuilder {
+        let mut body = json!({
+            "messages": messages.iter().map(to_native_message).collect::<Vec<Value>>(),
             "model": settings.model,
-            "temperature": settings.temperature,
-            "max_tokens": settings.max_tokens,
-            "stream": false,
+            "options": {
+                "temperature": settings.temperature,
+                "num_predict": settings.max_tokens,
+            },
+            "stream": stream,
         });
 
+        if !tools.is_empty() {
+            body["tools"] = Value::Array(tools.iter().map(Tool::to_json).collect());
+        }
+
         let mut headers = HeaderMap::new();
         headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
 
-        let response: Value = self.client
-            .post(format!("{}/v1/chat/completions", self.base_url))
+        self.client
+            .post(format!("{}/api/chat", self.base_url))
             .headers(headers)
             .json(&body)
-            .send()
-            .await
-            .map_err(|e| e.to_string())?
-            .json()
-            .await
-            .map_err(|e| e.to_string())?;
+    }
+}
 
-        // Extract the content from the message
-        response
-            .get("message")
-            .and_then(|message| message.get("content"))
-            .and_then(|content| content.as_str())
-            .map(String::from)
-            .ok_or_else(|| "Failed to extract content from response".to_string())
+fn get_native_usage(response: &Value) -> TokenUsage {
+    TokenUsage {
+        prompt_tokens: response["prompt_eval_count"].as_u64().unwrap_or_default(),
+        completion_tokens: response["eval_count"].as_u64().unwrap_or_default(),
     }
 }
 
-impl OllamaLargeLanguageModel {
-    pub fn new(base_url: &String) -> Self {
-        OllamaLargeLanguageModel {
-            client: Client::new(),
-            base_url: base_url.clone(),
+/// The native API takes tool call arguments as objects instead of JSON strings.
+fn to_native_message(message: &Message) -> Value {
+    let mut native_message: Value = json!({
+        "role": message.role,
+        "content": message.content,
+    });
+
+    if !message.tool_calls.is_empty() {
+        native_message["tool_calls"] = message.tool_calls
+            .iter()
+            .map(|tool_call| json!({
+                "function": {
+                    "name": tool_call.function.name,
+                    "arguments": serde_json::from_str::<Value>(&tool_call.function.arguments)
+                                            .unwrap_or(Value::Null),
+                },
+            }))
+            .collect();
+    }
+
+    native_message
+}
+
+/// The native API does not give ids to tool calls, so they are numbered in order.
+fn from_native_message(message: &Value) -> Message {
+    let content: String = message
+        .get("content")
+        .and_then(|content| content.as_str())
+        .unwrap_or_default()
+        .to_string();
+
+    let tool_calls: Vec<ToolCall> = message
+        .get("tool_calls")
+        .and_then(|tool_calls| tool_calls.as_array())
+        .map(|tool_calls| tool_calls.iter().enumerate().map(|(index, tool_call)| {
+            ToolCall {
+                id: format!("call_{}", index),
+                kind: "function".to_string(),
+                function: ToolCallFunction {
+                    name: tool_call["function"]["name"].as_str().unwrap_or_default().to_string(),
+                    arguments: tool_call["function"]["arguments"].to_string(),
+                },
+            }
+        }).collect())
+        .unwrap_or_default();
+
+    Message {
+        role: Role::Assistant,
+        content,
+        tool_calls,
+        tool_call_id: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use wiremock::{matchers::{body_partial_json, method, path}, Mock, MockServer, ResponseTemplate};
+
+    use super::*;
+
+    fn get_settings() -> ModelSettings {
+        ModelSettings { model: "llama3".to_string(), temperature: 0.5, max_tokens: 64 }
+    }
+
+    fn get_tool_call() -> ToolCall {
+        ToolCall {
+            id: "call_0".to_string(),
+            kind: "function".to_string(),
+            function: ToolCallFunction { name: "tests-lookup".to_string(), arguments: "{\"city\":\"Paris\"}".to_string() },
         }
     }
+
+    #[tokio::test]
+    async fn invokes_the_native_api() {
+        let server: MockServer = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/api/chat"))
+            .and(body_partial_json(json!({
+                "model": "llama3",
+                "options": { "temperature": 0.5, "num_predict": 64 },
+                "stream": false,
+                "messages": [
+                    { "role": "user", "content": "Weather in Paris?" },
+                    {
+                        "role": "assistant",
+                        "content": "",
+                        "tool_calls": [{ "function": { "name": "tests-lookup", "arguments": { "city": "Paris" } } }],
+                    },
+                ],
+                "tools": [{ "type": "function", "function": { "name": "tests-lookup" } }],
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "message": { "role": "assistant", "content": "Sunny" },
+                "done": true,
+                "done_reason": "stop",
+                "prompt_eval_count": 30,
+                "eval_count": 2,
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let mut tool_call_message: Message = Message::assistant("");
+        tool_call_message.tool_calls.push(get_tool_call());
+        let tools: Vec<Tool> = vec![Tool::new("tests/lookup", "Looks a city up.", &["city".to_string()])];
+
+        let response = OllamaLargeLanguageModel::new(&format!("{}/", server.uri()))
+            .invoke(&[Message::user("Weather in Paris?"), tool_call_message], &tools, &get_settings())
+            .await
+            .unwrap();
+
+        assert_eq!(response.message, Message::assistant("Sunny"));
+        assert_eq!(response.usage, TokenUsage { prompt_tokens: 30, completion_tokens: 2 });
+        assert_eq!(response.finish_reason, Some("stop".to_string()));
+    }
+
+    #[tokio::test]
+    async fn reads_native_tool_calls() {
+        let server: MockServer = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/api/chat"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "message": {
+                    "role": "assistant",
+                    "content": "",
+                    "tool_calls": [{ "function": { "name": "tests-lookup", "arguments": { "city": "Paris" } } }],
+                },
+                "done": true,
+            })))
+            .mount(&server)
+            .await;
+
+        let response = OllamaLargeLanguageModel::new(&server.uri())
+            .invoke(&[Message::user("Weather in Paris?")], &[], &get_settings())
+            .await
+            .unwrap();
+
+        assert_eq!(response.message.tool_calls, vec![get_tool_call()]);
+    }
+
+    #[tokio::test]
+    async fn streams_the_native_api() {
+        let chunks: &str = concat!(
+            "{\"message\":{\"role\":\"assistant\",\"content\":\"Su\"},\"done\":false}\n",
+            "{\"message\":{\"role\":\"assistant\",\"content\":\"nny\"},\"done\":false}\n",
+            "{\"message\":{\"role\":\"assistant\",\"content\":\"\",\"tool_calls\":[{\"function\":{\"name\":\"tests-lookup\",\"arguments\":{\"city\":\"Paris\"}}}]},\"done\":false}\n",
+            "{\"message\":{\"role\":\"assistant\",\"content\":\"\"},\"done\":true,\"done_reason\":\"stop\",\"prompt_eval_count\":30,\"eval_count\":2}\n",
+        );
+
+        let server: MockServer = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/api/chat"))
+            .and(body_partial_json(json!({ "stream": true })))
+            .respond_with(ResponseTemplate::new(200).set_body_raw(chunks, "application/x-ndjson"))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let deltas: Mutex<Vec<String>> = Mutex::new(Vec::new());
+        let on_delta = |delta: &str| deltas.lock().unwrap().push(delta.to_string());
+        let response = OllamaLargeLanguageModel::new(&server.uri())
+            .invoke_streaming(&[Message::user("Weather in Paris?")], &[], &get_settings(), &on_delta)
+            .await
+            .unwrap();
+
+        assert_eq!(deltas.into_inner().unwrap(), vec!["Su", "nny"]);
+        assert_eq!(response.message.content, "Sunny");
+        assert_eq!(response.message.tool_calls, vec![get_tool_call()]);
+        assert_eq!(response.usage, TokenUsage { prompt_tokens: 30, completion_tokens: 2 });
+        assert_eq!(response.finish_reason, Some("stop".to_string()));
+    }
+
+    #[tokio::test]
+    async fn fails_on_a_streamed_error() {
+        let server: MockServer = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(200).set_body_raw("{\"error\":\"model not found\"}\n", "application/x-ndjson"))
+            .mount(&server)
+            .await;
+
+        let error: LlmError = OllamaLargeLanguageModel::new(&server.uri())
+            .invoke_streaming(&[Message::user("Hello")], &[], &get_settings(), &|_| {})
+            .await
+            .unwrap_err();
+
+        assert!(error.to_string().contains("model not found"), "{}", error);
+    }
+
+    #[tokio::test]
+    async fn invokes_the_openai_compatible_api() {
+        let server: MockServer = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v1/chat/completions"))
+            .and(body_partial_json(json!({
+                "model": "llama3",
+                "temperature": 0.5,
+                "max_tokens": 64,
+                "messages": [{ "role": "user", "content": "Capital of France?" }],
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "choices": [{ "message": { "role": "assistant", "content": "Paris" }, "finish_reason": "stop" }],
+                "usage": { "prompt_tokens": 12, "completion_tokens": 1 },
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let response = OllamaLargeLanguageModel::new_openai_compatible(&format!("{}/", server.uri()))
+            .invoke(&[Message::user("Capital of France?")], &[], &get_settings())
+            .await
+            .unwrap();
+
+        assert_eq!(response.message, Message::assistant("Paris"));
+        assert_eq!(response.usage, TokenUsage { prompt_tokens: 12, completion_tokens: 1 });
+    }
 }
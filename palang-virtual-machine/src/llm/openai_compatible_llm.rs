@@ -0,0 +1,503 @@
+use std::{collections::HashMap, time::Duration};
+
+use reqwest::{header::{HeaderMap, HeaderName, HeaderValue, AUTHORIZATION, CONTENT_TYPE}, Client, RequestBuilder, Response};
+use serde_json::{json, Value};
+
+use super::{
+    configuration_error::ConfigurationError,
+    http_response::{new_http_client, read_response_lines},
+    invokable_llm::{DeltaHandler, InvocationFuture, InvokableLargeLanguageModel},
+    llm_error::LlmError,
+    llm_response::{LargeLanguageModelResponse, TokenUsage},
+    message::{Message, Role},
+    model_settings::ModelSettings,
+    retry_policy::RetryPolicy,
+    tool::{Tool, ToolCall, ToolCallFunction}
+};
+
+/// Any server implementing the OpenAI chat completions API (vLLM, llama.cpp, LiteLLM, etc.).
+#[derive(Clone)]
+pub struct OpenAiCompatibleLargeLanguageModel {
+    client: Client,
+    base_url: String,
+    api_key: Option<String>,
+    headers: HashMap<String, String>,
+    models: HashMap<String, String>,
+    retry_policy: RetryPolicy,
+}
+
+impl InvokableLargeLanguageModel for OpenAiCompatibleLargeLanguageModel {
+    fn invoke<'a>(
+        &'a self,
+        messages: &'a [Message],
+        tools: &'a [Tool],
+        settings: &'a ModelSettings,
+    ) -> InvocationFuture<'a> {
+        Box::pin(async move {
+            let request = self.get_request(messages, tools, settings, false)?;
+            let (response, retries) = self.retry_policy.send(request, &self.base_url).await?;
+
+            // Extract the first choice's message
+            let choice: &Value = response
+                .get("choices")
+                .and_then(|choices| choices.get(0))
+                .ok_or_else(|| LlmError::invalid_response(&self.base_url, "no choice".to_string()))?;
+            let message: Message = choice
+                .get("message")
+                .ok_or_else(|| LlmError::invalid_response(&self.base_url, "no message".to_string()))
+                .and_then(|message| {
+                    Message::from_json(message)
+                        .map_err(|e| LlmError::invalid_response(&self.base_url, e.to_string()))
+                })?;
+
+            Ok(LargeLanguageModelResponse {
+                message,
+                usage: TokenUsage {
+                    prompt_tokens: response["usage"]["prompt_tokens"].as_u64().unwrap_or_default(),
+                    completion_tokens: response["usage"]["completion_tokens"].as_u64().unwrap_or_default(),
+                },
+                finish_reason: choice["finish_reason"].as_str().map(str::to_string),
+                latency: Duration::ZERO,
+                retries,
+            })
+        })
+    }
+
+    /// Reads the server-sent events of a streamed completion, assembling tool
+    /// calls from their fragments.
+    fn invoke_streaming<'a>(
+        &'a self,
+        messages: &'a [Message],
+        tools: &'a [Tool],
+        settings: &'a ModelSettings,
+        on_delta: DeltaHandler<'a>,
+    ) -> InvocationFuture<'a> {
+        Box::pin(async move {
+            let request = self.get_request(messages, tools, settings, true)?;
+            let (response, retries): (Response, u64) = self.retry_policy.send_request(request, &self.base_url).await?;
+
+            let mut content: String = String::new();
+            let mut tool_calls: Vec<ToolCall> = Vec::new();
+            let mut usage: TokenUsage = TokenUsage::default();
+            let mut finish_reason: Option<String> = None;
+
+            read_response_lines(response, &self.base_url, |line| {
+                let data: &str = match line.strip_prefix("data:") {
+                    Some(data) => data.trim(),
+                    None => return Ok(()),
+                };
+                if data == "[DONE]" {
+                    return Ok(());
+                }
+
+                let chunk: Value = serde_json::from_str(data)
+                    .map_err(|e| LlmError::invalid_response(&self.base_url, format!("invalid event, {}: {}", e, data)))?;
+
+                // Groq reports usage in its own x_groq field
+                let chunk_usage: &Value = match &chunk["usage"] {
+                    Value::Null => &chunk["x_groq"]["usage"],
+                    chunk_usage => chunk_usage,
+                };
+                if let (Some(prompt_tokens), Some(completion_tokens)) = (
+                    chunk_usage["prompt_tokens"].as_u64(),
+                    chunk_usage["completion_tokens"].as_u64(),
+                ) {
+                    usage = TokenUsage { prompt_tokens, completion_tokens };
+                }
+
+                let choice: &Value = &chunk["choices"][0];
+                if let Some(reason) = choice["finish_reason"].as_str() {
+                    finish_reason = Some(reason.to_string());
+                }
+
+                if let Some(delta) = choice["delta"]["content"].as_str() {
+                    content.push_str(delta);
+                    on_delta(delta);
+                }
+
+                for fragment in choice["delta"]["tool_calls"].as_array().into_iter().flatten() {
+                    let index: usize = fragment["index"].as_u64().unwrap_or_default() as usize;
+                    while tool_calls.len() <= index {
+                        tool_calls.push(ToolCall {
+                            id: String::new(),
+                            kind: "function".to_string(),
+                            function: ToolCallFunction { name: String::new(), arguments: String::new() },
+                        });
+                    }
+
+                    let tool_call: &mut ToolCall = &mut tool_calls[index];
+                    if let Some(id) = fragment["id"].as_str() {
+                        tool_call.id = id.to_string();
+                    }
+                    if let Some(name) = fragment["function"]["name"].as_str() {
+                        tool_call.function.name.push_str(name);
+                    }
+                    if let Some(arguments) = fragment["function"]["arguments"].as_str() {
+                        tool_call.function.arguments.push_str(arguments);
+                    }
+                }
+
+                Ok(())
+            }).await?;
+
+            Ok(LargeLanguageModelResponse {
+                message: Message {
+                    role: Role::Assistant,
+                    content,
+                    tool_calls,
+                    tool_call_id: None,
+                },
+                usage,
+                finish_reason,
+                latency: Duration::ZERO,
+                retries,
+            })
+        })
+    }
+}
+
+impl OpenAiCompatibleLargeLanguageModel {
+    pub fn new(
+        base_url: &str,
+        api_key: &Option<String>,
+        headers: &HashMap<String, String>,
+        models: &HashMap<String, String>,
+    ) -> Self {
+        OpenAiCompatibleLargeLanguageModel {
+            client: new_http_client(),
+            base_url: base_url.trim_end_matches('/').to_string(),
+            api_key: api_key.clone(),
+            headers: headers.clone(),
+            models: models.clone(),
+            retry_policy: RetryPolicy::default(),
+        }
+    }
+
+    pub fn get_base_url(&self) -> &String {
+        &self.base_url
+    }
+
+    pub fn set_retry_policy(&mut self, retry_policy: &RetryPolicy) {
+        self.retry_policy = retry_policy.clone();
+    }
+
+    pub fn get_retry_policy(&self) -> &RetryPolicy {
+        &self.retry_policy
+    }
+
+    /// Builds the model from a profile's `llm_configuration`:
+    ///
+    /// ```yaml
+    /// llm_configuration:
+    ///   base_url: http://localhost:8000/v1
+    ///   api_key_env: VLLM_API_KEY
+    ///   headers:
+    ///     X-Team: research
+    ///   models:
+    ///     llama3: meta-llama/Meta-Llama-3-8B-Instruct
+    /// ```
+    pub fn from_configuration(configuration: &Value) -> Result<Self, ConfigurationError> {
+        let base_url: String = configuration
+            .get("base_url")
+            .and_then(|base_url| base_url.as_str())
+            .map(str::to_string)
+            .ok_or_else(|| ConfigurationError::Missing("llm_configuration.base_url".to_string()))?;
+
+        let api_key: Option<String> = match configuration.get("api_key_env").and_then(|env| env.as_str()) {
+            Some(environment_variable) => Some(
+                std::env::var(environment_variable)
+                         .map_err(|e| ConfigurationError::Environment { variable: environment_variable.to_string(), source: e })?
+            ),
+            None => None,
+        };
+
+        Ok(
+            OpenAiCompatibleLargeLanguageModel::new(
+                &base_url,
+                &api_key,
+                &get_string_map(configuration, "headers")?,
+                &get_string_map(configuration, "models")?,
+            )
+        )
+    }
+
+    fn get_request(
+        &self,
+        messages: &[Message],
+        tools: &[Tool],
+        settings: &ModelSettings,
+        stream: bool,
+    ) -> Result<RequestBuilder, LlmError> {
+        let mut body = json!({
+            "messages": messages,
+            "model": self.get_model_name(&settings.model),
+            "temperature": settings.temperature,
+            "max_tokens": settings.max_tokens,
+            "top_p": 1,
+            "stream": stream,
+            "stop": null,
+        });
+
+        if stream {
+            body["stream_options"] = json!({ "include_usage": true });
+        }
+
+        if !tools.is_empty() {
+            body["tools"] = Value::Array(tools.iter().map(Tool::to_json).collect());
+        }
+
+        Ok(
+            self.client
+                .post(format!("{}/chat/completions", self.base_url))
+                .headers(self.get_headers()?)
+                .json(&body)
+        )
+    }
+
+    fn get_model_name(&self, model: &String) -> String {
+        self.models.get(model).unwrap_or(model).clone()
+    }
+
+    fn get_headers(&self) -> Result<HeaderMap, ConfigurationError> {
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+
+        if let Some(api_key) = &self.api_key {
+            headers.insert(AUTHORIZATION,
+                HeaderValue::from_str(
+                    format!("Bearer {}", api_key).as_str()
+                ).map_err(|e| ConfigurationError::HeaderValue { name: AUTHORIZATION.to_string(), source: e })?
+            );
+        }
+
+        for (name, value) in &self.headers {
+            headers.insert(
+                HeaderName::from_bytes(name.as_bytes())
+                           .map_err(|e| ConfigurationError::HeaderName { name: name.clone(), source: e })?,
+                HeaderValue::from_str(value)
+                            .map_err(|e| ConfigurationError::HeaderValue { name: name.clone(), source: e })?,
+            );
+        }
+
+        Ok(headers)
+    }
+}
+
+fn get_string_map(configuration: &Value, key: &str) -> Result<HashMap<String, String>, ConfigurationError> {
+    match configuration.get(key) {
+        Some(Value::Null) | None => Ok(HashMap::new()),
+        Some(value) => serde_json::from_value(value.clone())
+                                  .map_err(|e| ConfigurationError::Invalid { setting: format!("llm_configuration.{}", key), source: e }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use wiremock::{matchers::{body_partial_json, header, method, path}, Mock, MockServer, ResponseTemplate};
+
+    use super::*;
+
+    fn get_settings() -> ModelSettings {
+        ModelSettings { model: "llama3".to_string(), temperature: 0.5, max_tokens: 64 }
+    }
+
+    fn get_model(server: &MockServer) -> OpenAiCompatibleLargeLanguageModel {
+        OpenAiCompatibleLargeLanguageModel::from_configuration(&json!({
+            "base_url": format!("{}/v1/", server.uri()),
+            "headers": { "X-Team": "research" },
+            "models": { "llama3": "meta-llama/Meta-Llama-3-8B-Instruct" },
+        })).unwrap()
+    }
+
+    #[test]
+    fn reads_the_configuration() {
+        std::env::set_var("PALANG_TEST_OPENAI_COMPATIBLE_KEY", "secret");
+        let llm = OpenAiCompatibleLargeLanguageModel::from_configuration(&json!({
+            "base_url": "http://localhost:8000/v1/",
+            "api_key_env": "PALANG_TEST_OPENAI_COMPATIBLE_KEY",
+            "models": { "llama3": "meta-llama/Meta-Llama-3-8B-Instruct" },
+        })).unwrap();
+
+        assert_eq!(llm.base_url, "http://localhost:8000/v1");
+        assert_eq!(llm.api_key, Some("secret".to_string()));
+        assert_eq!(llm.get_model_name(&"llama3".to_string()), "meta-llama/Meta-Llama-3-8B-Instruct");
+        assert_eq!(llm.get_model_name(&"mistral".to_string()), "mistral");
+    }
+
+    #[test]
+    fn requires_a_base_url() {
+        let result = OpenAiCompatibleLargeLanguageModel::from_configuration(&json!({ "api_key_env": "KEY" }));
+
+        assert!(matches!(result, Err(ConfigurationError::Missing(setting)) if setting == "llm_configuration.base_url"));
+    }
+
+    #[test]
+    fn rejects_invalid_maps() {
+        let result = OpenAiCompatibleLargeLanguageModel::from_configuration(&json!({
+            "base_url": "http://localhost:8000/v1",
+            "headers": ["X-Team"],
+        }));
+
+        assert!(matches!(result, Err(ConfigurationError::Invalid { setting, .. }) if setting == "llm_configuration.headers"));
+    }
+
+    #[tokio::test]
+    async fn invokes_a_completion() {
+        let server: MockServer = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v1/chat/completions"))
+            .and(header("X-Team", "research"))
+            .and(body_partial_json(json!({
+                "model": "meta-llama/Meta-Llama-3-8B-Instruct",
+                "temperature": 0.5,
+                "max_tokens": 64,
+                "stream": false,
+                "messages": [{ "role": "user", "content": "Capital of France?" }],
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "choices": [{
+                    "message": { "role": "assistant", "content": "Paris" },
+                    "finish_reason": "stop",
+                }],
+                "usage": { "prompt_tokens": 12, "completion_tokens": 3 },
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let response = get_model(&server)
+            .invoke(&[Message::user("Capital of France?")], &[], &get_settings())
+            .await
+            .unwrap();
+
+        assert_eq!(response.message, Message::assistant("Paris"));
+        assert_eq!(response.usage, TokenUsage { prompt_tokens: 12, completion_tokens: 3 });
+        assert_eq!(response.finish_reason, Some("stop".to_string()));
+    }
+
+    #[tokio::test]
+    async fn sends_the_tools() {
+        let server: MockServer = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(body_partial_json(json!({
+                "tools": [{ "type": "function", "function": { "name": "tests-lookup" } }],
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "choices": [{
+                    "message": {
+                        "role": "assistant",
+                        "content": "",
+                        "tool_calls": [{
+                            "id": "call_1",
+                            "type": "function",
+                            "function": { "name": "tests-lookup", "arguments": "{\"city\":\"Paris\"}" },
+                        }],
+                    },
+                    "finish_reason": "tool_calls",
+                }],
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let tools: Vec<Tool> = vec![Tool::new("tests/lookup", "Looks a city up.", &["city".to_string()])];
+        let response = get_model(&server)
+            .invoke(&[Message::user("Weather in Paris?")], &tools, &get_settings())
+            .await
+            .unwrap();
+
+        assert_eq!(response.message.tool_calls.len(), 1);
+        assert_eq!(response.message.tool_calls[0].function.arguments, "{\"city\":\"Paris\"}");
+        assert_eq!(response.usage, TokenUsage::default());
+    }
+
+    #[tokio::test]
+    async fn fails_without_a_choice() {
+        let server: MockServer = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "choices": [] })))
+            .mount(&server)
+            .await;
+
+        let result = get_model(&server)
+            .invoke(&[Message::user("Hello")], &[], &get_settings())
+            .await;
+
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn streams_a_completion() {
+        let events: &str = concat!(
+            "data: {\"choices\":[{\"delta\":{\"role\":\"assistant\",\"content\":\"Pa\"}}]}\n\n",
+            "data: {\"choices\":[{\"delta\":{\"content\":\"ris\"},\"finish_reason\":\"stop\"}]}\n\n",
+            "data: {\"choices\":[],\"usage\":{\"prompt_tokens\":12,\"completion_tokens\":2}}\n\n",
+            "data: [DONE]\n\n",
+        );
+
+        let server: MockServer = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v1/chat/completions"))
+            .and(body_partial_json(json!({ "stream": true, "stream_options": { "include_usage": true } })))
+            .respond_with(ResponseTemplate::new(200).set_body_raw(events, "text/event-stream"))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let deltas: Mutex<Vec<String>> = Mutex::new(Vec::new());
+        let on_delta = |delta: &str| deltas.lock().unwrap().push(delta.to_string());
+        let response = get_model(&server)
+            .invoke_streaming(&[Message::user("Capital of France?")], &[], &get_settings(), &on_delta)
+            .await
+            .unwrap();
+
+        assert_eq!(deltas.into_inner().unwrap(), vec!["Pa", "ris"]);
+        assert_eq!(response.message, Message::assistant("Paris"));
+        assert_eq!(response.usage, TokenUsage { prompt_tokens: 12, completion_tokens: 2 });
+        assert_eq!(response.finish_reason, Some("stop".to_string()));
+    }
+
+    #[tokio::test]
+    async fn assembles_streamed_tool_calls() {
+        let events: &str = concat!(
+            "data: {\"choices\":[{\"delta\":{\"tool_calls\":[{\"index\":0,\"id\":\"call_1\",\"type\":\"function\",\"function\":{\"name\":\"tests-\",\"arguments\":\"\"}}]}}]}\n\n",
+            "data: {\"choices\":[{\"delta\":{\"tool_calls\":[{\"index\":0,\"function\":{\"name\":\"lookup\",\"arguments\":\"{\\\"city\\\":\"}}]}}]}\n\n",
+            "data: {\"choices\":[{\"delta\":{\"tool_calls\":[{\"index\":1,\"id\":\"call_2\",\"function\":{\"name\":\"tests-time\",\"arguments\":\"{}\"}}]}}]}\n\n",
+            "data: {\"choices\":[{\"delta\":{\"tool_calls\":[{\"index\":0,\"function\":{\"arguments\":\"\\\"Paris\\\"}\"}}]},\"finish_reason\":\"tool_calls\"}]}\n\n",
+            "data: {\"choices\":[],\"x_groq\":{\"usage\":{\"prompt_tokens\":20,\"completion_tokens\":9}}}\n\n",
+            "data: [DONE]\n\n",
+        );
+
+        let server: MockServer = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(200).set_body_raw(events, "text/event-stream"))
+            .mount(&server)
+            .await;
+
+        let response = get_model(&server)
+            .invoke_streaming(&[Message::user("Weather in Paris?")], &[], &get_settings(), &|_| {})
+            .await
+            .unwrap();
+
+        assert_eq!(response.message.content, "");
+        assert_eq!(
+            response.message.tool_calls,
+            vec![
+                ToolCall {
+                    id: "call_1".to_string(),
+                    kind: "function".to_string(),
+                    function: ToolCallFunction { name: "tests-lookup".to_string(), arguments: "{\"city\":\"Paris\"}".to_string() },
+                },
+                ToolCall {
+                    id: "call_2".to_string(),
+                    kind: "function".to_string(),
+                    function: ToolCallFunction { name: "tests-time".to_string(), arguments: "{}".to_string() },
+                },
+            ],
+        );
+        assert_eq!(response.usage, TokenUsage { prompt_tokens: 20, completion_tokens: 9 });
+        assert_eq!(response.finish_reason, Some("tool_calls".to_string()));
+    }
+}
@@ -0,0 +1,91 @@
+use std::{collections::HashMap, fs, path::PathBuf};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::{configuration_error::ConfigurationError, llm_response::TokenUsage, storage_error::StorageError};
+
+/// Prices in any currency per million tokens.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModelPrice {
+    pub prompt: f64,
+    pub completion: f64,
+}
+
+/// Prices per model, e.g.:
+///
+/// ```yaml
+/// llama3-70b-8192:
+///   prompt: 0.59
+///   completion: 0.79
+/// ```
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct PriceTable {
+    prices: HashMap<String, ModelPrice>,
+}
+
+impl PriceTable {
+    pub fn new(prices: &HashMap<String, ModelPrice>) -> Self {
+        PriceTable { prices: prices.clone() }
+    }
+
+    pub fn load(file: &PathBuf) -> Result<Self, StorageError> {
+        let raw_prices: String = fs::read_to_string(file)
+                                    .map_err(|e| StorageError::Read { file: file.clone(), source: e })?;
+
+        match file.extension().and_then(|extension| extension.to_str()) {
+            Some("json") => serde_json::from_str(&raw_prices).map_err(|e| StorageError::Json { file: file.clone(), source: e }),
+            _ => serde_yaml::from_str(&raw_prices).map_err(|e| StorageError::Yaml { file: file.clone(), source: e }),
+        }
+    }
+
+    pub fn from_configuration(configuration: &Value) -> Result<Self, ConfigurationError> {
+        if configuration.is_null() {
+            return Ok(PriceTable::default());
+        }
+
+        serde_json::from_value(configuration.clone())
+            .map_err(|e| ConfigurationError::Invalid { setting: "prices".to_string(), source: e })
+    }
+
+    pub fn get_price(&self, model: &String) -> Option<&ModelPrice> {
+        self.prices.get(model)
+    }
+
+    pub fn estimate_cost(&self, model: &String, usage: &TokenUsage) -> Option<f64> {
+        self.get_price(model).map(|price| {
+            (usage.prompt_tokens as f64 * price.prompt + usage.completion_tokens as f64 * price.completion) / 1_000_000.0
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn estimates_costs_per_million_tokens() {
+        let price_table: PriceTable = PriceTable::from_configuration(&json!({
+            "llama3-70b-8192": { "prompt": 0.59, "completion": 0.79 },
+        })).unwrap();
+
+        let cost: f64 = price_table
+            .estimate_cost(&"llama3-70b-8192".to_string(), &TokenUsage { prompt_tokens: 2_000_000, completion_tokens: 500_000 })
+            .unwrap();
+
+        assert!((cost - 1.575).abs() < 1e-9, "{}", cost);
+        assert_eq!(price_table.estimate_cost(&"unknown".to_string(), &TokenUsage::default()), None);
+    }
+
+    #[test]
+    fn rejects_invalid_prices() {
+        assert!(PriceTable::from_configuration(&Value::Null).unwrap().get_price(&"any".to_string()).is_none());
+        assert!(matches!(
+            PriceTable::from_configuration(&json!({ "model": { "prompt": "free" } })),
+            Err(ConfigurationError::Invalid { setting, .. }) if setting == "prices"
+        ));
+    }
+}
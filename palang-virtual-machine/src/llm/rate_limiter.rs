@@ -0,0 +1,334 @@
+use std::{sync::{Arc, Mutex}, time::Duration};
+
+use serde_json::Value;
+use tokio::{sync::{OwnedSemaphorePermit, Semaphore}, time::Instant};
+
+use super::{configuration_error::ConfigurationError, invokable_llm::DeltaHandler, llm::LargeLanguageModel, llm_error::LlmError, llm_response::LargeLanguageModelResponse, message::Message, model_settings::ModelSettings, retry_policy::RetryPolicy, tool::Tool};
+
+/// Rough number of characters per token, used to estimate a request's tokens before sending it.
+const CHARACTERS_PER_TOKEN: usize = 4;
+
+struct TokenBucket {
+    capacity: f64,
+    available: f64,
+    refill_per_second: f64,
+    refilled_at: Instant,
+}
+
+impl TokenBucket {
+    fn per_minute(capacity: u64) -> Self {
+        TokenBucket {
+            capacity: capacity as f64,
+            available: capacity as f64,
+            refill_per_second: capacity as f64 / 60.0,
+            refilled_at: Instant::now(),
+        }
+    }
+
+    fn refill(&mut self) {
+        let now: Instant = Instant::now();
+        let elapsed: f64 = now.duration_since(self.refilled_at).as_secs_f64();
+
+        self.available = (self.available + elapsed * self.refill_per_second).min(self.capacity);
+        self.refilled_at = now;
+    }
+
+    /// Takes `amount` tokens if available, otherwise returns how long to wait for them.
+    /// Requests larger than the bucket go through once it is full.
+    fn try_take(&mut self, amount: f64) -> Result<(), Duration> {
+        self.refill();
+
+        let amount: f64 = self.clamp(amount);
+        if self.available >= amount {
+            self.available -= amount;
+            Ok(())
+        }
+        else {
+            Err(Duration::from_secs_f64((amount - self.available) / self.refill_per_second))
+        }
+    }
+
+    /// Gives back what was taken for `taken` tokens beyond `used`, or takes
+    /// what was used beyond it, possibly going into debt.
+    fn settle(&mut self, taken: f64, used: f64) {
+        self.refill();
+        self.available = (self.available + self.clamp(taken) - used).min(self.capacity);
+    }
+
+    fn clamp(&self, amount: f64) -> f64 {
+        amount.min(self.capacity)
+    }
+}
+
+/// Limits requests per minute, tokens per minute and concurrent requests.
+/// Clones share their limits.
+#[derive(Clone, Default)]
+pub struct RateLimiter {
+    requests: Option<Arc<Mutex<TokenBucket>>>,
+    tokens: Option<Arc<Mutex<TokenBucket>>>,
+    concurrency: Option<Arc<Semaphore>>,
+}
+
+impl RateLimiter {
+    pub fn new(
+        requests_per_minute: Option<u64>,
+        tokens_per_minute: Option<u64>,
+        max_concurrent_requests: Option<usize>,
+    ) -> Self {
+        RateLimiter {
+            requests: requests_per_minute.map(|limit| Arc::new(Mutex::new(TokenBucket::per_minute(limit)))),
+            tokens: tokens_per_minute.map(|limit| Arc::new(Mutex::new(TokenBucket::per_minute(limit)))),
+            concurrency: max_concurrent_requests.map(|limit| Arc::new(Semaphore::new(limit))),
+        }
+    }
+
+    /// Builds the limiter from a profile's `rate_limits`:
+    ///
+    /// ```yaml
+    /// rate_limits:
+    ///   requests_per_minute: 30
+    ///   tokens_per_minute: 6000
+    ///   max_concurrent_requests: 4
+    /// ```
+    pub fn from_configuration(configuration: &Value) -> Result<Self, ConfigurationError> {
+        let get_limit = |key: &str| -> Result<Option<u64>, ConfigurationError> {
+            match configuration.get(key) {
+                Some(Value::Null) | None => Ok(None),
+                Some(limit) => match limit.as_u64() {
+                    Some(limit) if limit > 0 => Ok(Some(limit)),
+                    _ => Err(ConfigurationError::NotPositive(format!("rate_limits.{}", key))),
+                },
+            }
+        };
+
+        Ok(
+            RateLimiter::new(
+                get_limit("requests_per_minute")?,
+                get_limit("tokens_per_minute")?,
+                get_limit("max_concurrent_requests")?.map(|limit| limit as usize),
+            )
+        )
+    }
+
+    /// Waits until a request of `tokens` tokens is allowed. The returned permit
+    /// must be held until the request completes.
+    pub async fn acquire(&self, tokens: u64) -> Result<Option<OwnedSemaphorePermit>, LlmError> {
+        let permit: Option<OwnedSemaphorePermit> = match &self.concurrency {
+            Some(concurrency) => Some(
+                concurrency.clone()
+                           .acquire_owned()
+                           .await?
+            ),
+            None => None,
+        };
+
+        if let Some(requests) = &self.requests {
+            take(requests, 1.0).await;
+        }
+        if let Some(token_bucket) = &self.tokens {
+            take(token_bucket, tokens as f64).await;
+        }
+
+        Ok(permit)
+    }
+
+    /// Replaces the estimate a request was acquired with by the tokens it
+    /// actually used, as reported by the provider.
+    pub fn record_usage(&self, estimated_tokens: u64, used_tokens: u64) {
+        if let Some(token_bucket) = &self.tokens {
+            token_bucket.lock().unwrap().settle(estimated_tokens as f64, used_tokens as f64);
+        }
+    }
+}
+
+async fn take(bucket: &Mutex<TokenBucket>, amount: f64) {
+    loop {
+        let wait: Duration = match bucket.lock().unwrap().try_take(amount) {
+            Ok(()) => return,
+            Err(wait) => wait,
+        };
+
+        tokio::time::sleep(wait).await;
+    }
+}
+
+/// Applies a rate limiter to every invocation of the wrapped large language model.
+#[derive(Clone)]
+pub struct RateLimitedLargeLanguageModel {
+    llm: Box<LargeLanguageModel>,
+    rate_limiter: RateLimiter,
+}
+
+impl RateLimitedLargeLanguageModel {
+    pub fn new(llm: &LargeLanguageModel, rate_limiter: &RateLimiter) -> Self {
+        RateLimitedLargeLanguageModel {
+            llm: Box::new(llm.clone()),
+            rate_limiter: rate_limiter.clone(),
+        }
+    }
+
+    pub fn set_retry_policy(&mut self, retry_policy: &RetryPolicy) {
+        *self.llm = self.llm.with_retry_policy(retry_policy);
+    }
+
+    pub fn get_provider_name(&self) -> String {
+        self.llm.get_provider_name()
+    }
+
+    pub fn get_base_url(&self) -> Option<String> {
+        self.llm.get_base_url()
+    }
+
+    pub async fn invoke(
+        &self,
+        prompt: &String,
+        messages: &Vec<Message>,
+        tools: &Vec<Tool>,
+        settings: &ModelSettings,
+        on_delta: Option<DeltaHandler<'_>>,
+    ) -> Result<LargeLanguageModelResponse, LlmError> {
+        let estimated_tokens: u64 = estimate_tokens(messages, settings);
+        let _permit: Option<OwnedSemaphorePermit> = self.rate_limiter
+            .acquire(estimated_tokens)
+            .await?;
+
+        let response: LargeLanguageModelResponse = Box::pin(
+            self.llm.invoke_with_handler(prompt, messages, tools, settings, on_delta)
+        ).await?;
+
+        // Some providers do not report usage, their requests keep counting as estimated
+        let used_tokens: u64 = response.usage.get_total_tokens();
+        if used_tokens > 0 {
+            self.rate_limiter.record_usage(estimated_tokens, used_tokens);
+        }
+
+        Ok(response)
+    }
+}
+
+/// Providers count the prompt and the maximum completion against token quotas.
+fn estimate_tokens(messages: &[Message], settings: &ModelSettings) -> u64 {
+    let characters: usize = messages.iter().map(|message| message.content.len()).sum();
+
+    (characters / CHARACTERS_PER_TOKEN) as u64 + settings.max_tokens as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::llm::{
+        invokable_llm::{InvocationFuture, InvokableLargeLanguageModel},
+        llm_response::TokenUsage
+    };
+
+    use super::*;
+
+    /// Answers at once, using 10 prompt and 5 completion tokens.
+    struct MeteredLargeLanguageModel;
+
+    impl InvokableLargeLanguageModel for MeteredLargeLanguageModel {
+        fn invoke<'a>(
+            &'a self,
+            _messages: &'a [Message],
+            _tools: &'a [Tool],
+            _settings: &'a ModelSettings,
+        ) -> InvocationFuture<'a> {
+            Box::pin(async move {
+                Ok(LargeLanguageModelResponse {
+                    usage: TokenUsage { prompt_tokens: 10, completion_tokens: 5 },
+                    ..LargeLanguageModelResponse::new(&Message::assistant("answer"))
+                })
+            })
+        }
+
+        fn invoke_streaming<'a>(
+            &'a self,
+            messages: &'a [Message],
+            tools: &'a [Tool],
+            settings: &'a ModelSettings,
+            _on_delta: DeltaHandler<'a>,
+        ) -> InvocationFuture<'a> {
+            self.invoke(messages, tools, settings)
+        }
+    }
+
+    /// How long the acquisitions wait, one after another.
+    async fn get_waits(rate_limiter: &RateLimiter, tokens: &[u64]) -> Vec<Duration> {
+        let mut waits: Vec<Duration> = Vec::new();
+        for tokens in tokens {
+            let started_at: Instant = Instant::now();
+            rate_limiter.acquire(*tokens).await.unwrap();
+            waits.push(started_at.elapsed());
+        }
+
+        waits
+    }
+
+    fn assert_waits(waits: &[Duration], seconds: &[u64]) {
+        assert_eq!(waits.len(), seconds.len());
+        for (wait, seconds) in waits.iter().zip(seconds) {
+            // Buckets refill continuously, rounding may cost a few more milliseconds
+            let expected: Duration = Duration::from_secs(*seconds);
+            assert!(*wait >= expected && *wait < expected + Duration::from_millis(10), "{:?} instead of {:?}", waits, seconds);
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn limits_requests_per_minute() {
+        let rate_limiter: RateLimiter = RateLimiter::new(Some(2), None, None);
+
+        let waits: Vec<Duration> = get_waits(&rate_limiter, &[0, 0, 0, 0]).await;
+
+        assert_waits(&waits, &[0, 0, 30, 30]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn limits_tokens_per_minute() {
+        let rate_limiter: RateLimiter = RateLimiter::new(None, Some(600), None);
+
+        // Requests larger than the bucket wait for it to be full
+        let waits: Vec<Duration> = get_waits(&rate_limiter, &[400, 300, 1000, 60]).await;
+
+        assert_waits(&waits, &[0, 10, 60, 6]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn limits_concurrent_requests() {
+        let rate_limiter: RateLimiter = RateLimiter::new(None, None, Some(2));
+
+        let first: Option<OwnedSemaphorePermit> = rate_limiter.acquire(0).await.unwrap();
+        let _second: Option<OwnedSemaphorePermit> = rate_limiter.acquire(0).await.unwrap();
+        assert!(tokio::time::timeout(Duration::from_secs(3600), rate_limiter.acquire(0)).await.is_err());
+
+        drop(first);
+        assert!(tokio::time::timeout(Duration::from_secs(1), rate_limiter.acquire(0)).await.is_ok());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn settles_estimates_with_the_actual_usage() {
+        let rate_limiter: RateLimiter = RateLimiter::new(None, Some(600), None);
+
+        // Overestimated requests give back what they did not use
+        rate_limiter.acquire(600).await.unwrap();
+        rate_limiter.record_usage(600, 300);
+        assert_waits(&get_waits(&rate_limiter, &[300]).await, &[0]);
+
+        // Underestimated ones take the difference
+        rate_limiter.record_usage(0, 60);
+        assert_waits(&get_waits(&rate_limiter, &[60]).await, &[12]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn counts_the_tokens_used_by_invocations() {
+        let llm: LargeLanguageModel = LargeLanguageModel::new_custom(MeteredLargeLanguageModel)
+            .with_rate_limiter(&RateLimiter::new(None, Some(200), None));
+        let settings: ModelSettings = ModelSettings { model: "metered".to_string(), temperature: 0.0, max_tokens: 90 };
+
+        // Each invocation is estimated at 90 tokens but uses 15, so none waits
+        let started_at: Instant = Instant::now();
+        for _ in 0..3 {
+            llm.invoke(&"tests/ask".to_string(), &vec![Message::user("Hi")], &Vec::new(), &settings).await.unwrap();
+        }
+
+        assert_eq!(started_at.elapsed(), Duration::ZERO);
+    }
+}
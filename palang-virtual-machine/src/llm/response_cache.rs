@@ -0,0 +1,216 @@
+use std::{collections::HashMap, fs, path::PathBuf, sync::{Arc, Mutex}, time::{Duration, SystemTime, UNIX_EPOCH}};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+
+use super::{llm::LargeLanguageModel, message::Message, model_settings::ModelSettings, storage_error::StorageError, tool::Tool};
+
+const DEFAULT_MAX_ENTRIES: usize = 1024;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CacheEntry {
+    response: Message,
+    created_at: u64,
+    last_used_at: u128,
+}
+
+/// Caches responses of a large language model, either in memory or in a JSON file.
+/// The least recently used entries are evicted once `max_entries` is reached.
+#[derive(Clone)]
+pub struct ResponseCache {
+    file: Option<PathBuf>,
+    max_entries: usize,
+    ttl: Option<Duration>,
+    entries: Arc<Mutex<HashMap<String, CacheEntry>>>,
+}
+
+impl ResponseCache {
+    pub fn new_in_memory(max_entries: usize, ttl: Option<Duration>) -> Self {
+        ResponseCache {
+            file: None,
+            max_entries,
+            ttl,
+            entries: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    pub fn new_on_disk(file: &PathBuf, max_entries: usize, ttl: Option<Duration>) -> Result<Self, StorageError> {
+        let entries: HashMap<String, CacheEntry> = if file.exists() {
+            let raw_entries: String = fs::read_to_string(file)
+                                         .map_err(|e| StorageError::Read { file: file.clone(), source: e })?;
+            serde_json::from_str(&raw_entries)
+                .map_err(|e| StorageError::Json { file: file.clone(), source: e })?
+        }
+        else {
+            HashMap::new()
+        };
+
+        Ok(ResponseCache {
+            file: Some(file.clone()),
+            max_entries,
+            ttl,
+            entries: Arc::new(Mutex::new(entries)),
+        })
+    }
+
+    pub fn from_configuration(configuration: &Value) -> Result<Self, StorageError> {
+        let max_entries: usize = configuration["max_entries"]
+            .as_u64()
+            .map(|max_entries| max_entries as usize)
+            .unwrap_or(DEFAULT_MAX_ENTRIES);
+        let ttl: Option<Duration> = configuration["ttl_seconds"]
+            .as_u64()
+            .map(Duration::from_secs);
+
+        match configuration["file"].as_str() {
+            Some(file) => Self::new_on_disk(&PathBuf::from(file), max_entries, ttl),
+            None => Ok(Self::new_in_memory(max_entries, ttl)),
+        }
+    }
+
+    /// Keys a request by everything deciding its response, including which
+    /// provider and server answer it.
+    pub fn get_key(llm: &LargeLanguageModel, messages: &[Message], tools: &[Tool], settings: &ModelSettings) -> String {
+        let tool_names: Vec<&String> = tools.iter().map(|tool| &tool.name).collect();
+        let request: Value = json!({
+            "provider": llm.get_provider_name(),
+            "base_url": llm.get_base_url(),
+            "messages": messages,
+            "tools": tool_names,
+            "settings": settings,
+        });
+
+        Sha256::digest(request.to_string().as_bytes())
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+
+    pub fn get(&self, key: &str) -> Option<Message> {
+        let mut entries = self.entries.lock().unwrap();
+
+        let is_expired: bool = match (entries.get(key), self.ttl) {
+            (Some(entry), Some(ttl)) => get_timestamp().as_secs() >= entry.created_at + ttl.as_secs(),
+            _ => false,
+        };
+        if is_expired {
+            entries.remove(key);
+            return None;
+        }
+
+        entries.get_mut(key).map(|entry| {
+            entry.last_used_at = get_timestamp().as_nanos();
+            entry.response.clone()
+        })
+    }
+
+    pub fn put(&self, key: &str, response: &Message) -> Result<(), StorageError> {
+        let mut entries = self.entries.lock().unwrap();
+
+        let now: Duration = get_timestamp();
+        entries.insert(
+            key.to_string(),
+            CacheEntry {
+                response: response.clone(),
+                created_at: now.as_secs(),
+                last_used_at: now.as_nanos(),
+            }
+        );
+
+        while entries.len() > self.max_entries {
+            let least_recently_used: Option<String> = entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used_at)
+                .map(|(key, _)| key.clone());
+
+            match least_recently_used {
+                Some(key) => entries.remove(&key),
+                None => break,
+            };
+        }
+
+        match &self.file {
+            Some(file) => {
+                let raw_entries: String = serde_json::to_string(&*entries)
+                                                     .map_err(|e| StorageError::Json { file: file.clone(), source: e })?;
+                fs::write(file, raw_entries).map_err(|e| StorageError::Write { file: file.clone(), source: e })
+            },
+            None => Ok(()),
+        }
+    }
+}
+
+fn get_timestamp() -> Duration {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::llm::{mock_llm::{MockFixtures, MockLargeLanguageModel}, openai_compatible_llm::OpenAiCompatibleLargeLanguageModel};
+
+    use super::*;
+
+    fn get_key(llm: &LargeLanguageModel) -> String {
+        ResponseCache::get_key(
+            llm,
+            &[Message::user("Capital of France?")],
+            &[],
+            &ModelSettings { model: "llama3".to_string(), temperature: 0.0, max_tokens: 16 },
+        )
+    }
+
+    fn get_openai_compatible_llm(base_url: &str) -> LargeLanguageModel {
+        LargeLanguageModel::new_openai_compatible(
+            OpenAiCompatibleLargeLanguageModel::new(base_url, &None, &HashMap::new(), &HashMap::new())
+        )
+    }
+
+    #[test]
+    fn keys_requests_by_provider_and_server() {
+        let mock: LargeLanguageModel = LargeLanguageModel::new_mock(MockLargeLanguageModel::new(&MockFixtures::default()).unwrap());
+        let local: LargeLanguageModel = get_openai_compatible_llm("http://localhost:8000/v1");
+        let remote: LargeLanguageModel = get_openai_compatible_llm("http://inference.example.com/v1");
+
+        assert_eq!(get_key(&local), get_key(&get_openai_compatible_llm("http://localhost:8000/v1/")));
+        assert_ne!(get_key(&local), get_key(&remote));
+        assert_ne!(get_key(&local), get_key(&LargeLanguageModel::new_ollama(&"http://localhost:8000/v1".to_string())));
+        assert_ne!(get_key(&mock), get_key(&LargeLanguageModel::new_groq(&"token".to_string())));
+    }
+
+    #[test]
+    fn evicts_the_least_recently_used_entries() {
+        let cache: ResponseCache = ResponseCache::new_in_memory(2, None);
+        cache.put("first", &Message::assistant("1")).unwrap();
+        cache.put("second", &Message::assistant("2")).unwrap();
+        std::thread::sleep(Duration::from_millis(1));
+        cache.get("first").unwrap();
+        cache.put("third", &Message::assistant("3")).unwrap();
+
+        assert!(cache.get("first").is_some());
+        assert!(cache.get("second").is_none());
+        assert!(cache.get("third").is_some());
+    }
+
+    #[test]
+    fn expires_entries_after_their_ttl() {
+        let file: PathBuf = std::env::temp_dir().join(format!("palang-response-cache-{}.json", std::process::id()));
+        let entry: CacheEntry = CacheEntry { response: Message::assistant("Paris"), created_at: get_timestamp().as_secs() - 120, last_used_at: 0 };
+        fs::write(&file, serde_json::to_string(&HashMap::from([("capital", entry)])).unwrap()).unwrap();
+
+        let fresh: ResponseCache = ResponseCache::new_on_disk(&file, 16, Some(Duration::from_secs(3600))).unwrap();
+        let expired: ResponseCache = ResponseCache::new_on_disk(&file, 16, Some(Duration::from_secs(60))).unwrap();
+        let unlimited: ResponseCache = ResponseCache::new_on_disk(&file, 16, None).unwrap();
+        fs::remove_file(&file).unwrap();
+
+        assert_eq!(fresh.get("capital").unwrap().content, "Paris");
+        assert!(expired.get("capital").is_none());
+        assert!(unlimited.get("capital").is_some());
+        // Reading an expired entry drops it
+        assert!(!expired.entries.lock().unwrap().contains_key("capital"));
+    }
+}
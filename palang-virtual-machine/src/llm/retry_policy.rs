@@ -0,0 +1,249 @@
+use std::time::Duration;
+
+use reqwest::{header::RETRY_AFTER, RequestBuilder, Response, StatusCode};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::{configuration_error::ConfigurationError, http_response::{read_error_response, read_json_response}, llm_error::LlmError};
+
+/// Retries requests failing with HTTP 429, 5xx or a timeout, waiting with an
+/// exponential backoff and jitter, or as long as the `Retry-After` header asks
+/// up to `max_backoff_ms`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub initial_backoff_ms: u64,
+    pub max_backoff_ms: u64,
+    pub multiplier: f64,
+    /// Fraction of the backoff randomly added or removed.
+    pub jitter: f64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 3,
+            initial_backoff_ms: 500,
+            max_backoff_ms: 30_000,
+            multiplier: 2.0,
+            jitter: 0.2,
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn none() -> Self {
+        RetryPolicy {
+            max_attempts: 1,
+            ..RetryPolicy::default()
+        }
+    }
+
+    /// Builds the policy from a profile's `retry_policy`:
+    ///
+    /// ```yaml
+    /// retry_policy:
+    ///   max_attempts: 5
+    ///   initial_backoff_ms: 1000
+    ///   max_backoff_ms: 60000
+    /// ```
+    pub fn from_configuration(configuration: &Value) -> Result<Self, ConfigurationError> {
+        serde_json::from_value(configuration.clone())
+            .map_err(|e| ConfigurationError::Invalid { setting: "retry_policy".to_string(), source: e })
+    }
+
+    pub fn get_backoff(&self, attempt: u32) -> Duration {
+        let backoff: f64 = self.initial_backoff_ms as f64 * self.multiplier.powi(attempt as i32 - 1);
+        let backoff: f64 = backoff.min(self.max_backoff_ms as f64);
+        let jitter: f64 = backoff * self.jitter * (fastrand::f64() * 2.0 - 1.0);
+
+        Duration::from_millis((backoff + jitter).max(0.0) as u64)
+    }
+
+    pub async fn send(&self, request: RequestBuilder, provider: &str) -> Result<(Value, u64), LlmError> {
+        let (response, retries) = self.send_request(request, provider).await?;
+        let response: Value = read_json_response(response, provider).await?;
+        Ok((response, retries))
+    }
+
+    /// Sends the request until it succeeds, leaving the body unread for
+    /// streaming. Returns the response along with the number of retries it
+    /// took, which failures carry as `LlmError::Retried`.
+    pub async fn send_request(&self, request: RequestBuilder, provider: &str) -> Result<(Response, u64), LlmError> {
+        let mut attempt: u32 = 1;
+        let retried = |error: LlmError, attempt: u32| match attempt {
+            1 => error,
+            _ => LlmError::Retried { retries: (attempt - 1) as u64, source: Box::new(error) },
+        };
+
+        loop {
+            let can_retry: bool = attempt < self.max_attempts;
+            let attempt_request: RequestBuilder = request
+                .try_clone()
+                .ok_or_else(|| LlmError::Provider {
+                    provider: provider.to_string(),
+                    message: "the request cannot be sent".to_string(),
+                })?;
+
+            let delay: Duration = match attempt_request.send().await {
+                Ok(response) if can_retry && is_retryable(response.status()) => {
+                    get_retry_after(&response)
+                        .map(|retry_after| retry_after.min(Duration::from_millis(self.max_backoff_ms)))
+                        .unwrap_or_else(|| self.get_backoff(attempt))
+                },
+                Ok(response) if !response.status().is_success() => {
+                    return Err(retried(read_error_response(response, provider).await, attempt));
+                },
+                Ok(response) => {
+                    return Ok((response, (attempt - 1) as u64));
+                },
+                Err(e) if can_retry && e.is_timeout() => self.get_backoff(attempt),
+                Err(e) => {
+                    return Err(retried(LlmError::from_request_error(provider, e), attempt));
+                },
+            };
+
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+}
+
+fn is_retryable(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+}
+
+/// Only the delay in seconds form of `Retry-After` is supported.
+fn get_retry_after(response: &Response) -> Option<Duration> {
+    response
+        .headers()
+        .get(RETRY_AFTER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse::<u64>().ok())
+        .map(Duration::from_secs)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use reqwest::Client;
+    use wiremock::{matchers::method, Mock, MockServer, ResponseTemplate};
+
+    use super::*;
+
+    fn get_policy(max_attempts: u32) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts,
+            initial_backoff_ms: 50,
+            max_backoff_ms: 200,
+            multiplier: 2.0,
+            jitter: 0.0,
+        }
+    }
+
+    async fn mount_failure(server: &MockServer, response: ResponseTemplate, priority: u8) {
+        Mock::given(method("POST"))
+            .respond_with(response)
+            .up_to_n_times(1)
+            .with_priority(priority)
+            .expect(1)
+            .mount(server)
+            .await;
+    }
+
+    async fn send(policy: &RetryPolicy, server: &MockServer) -> Result<(Value, u64), LlmError> {
+        policy.send(Client::new().post(server.uri()), "tests").await
+    }
+
+    #[tokio::test]
+    async fn retries_until_success() {
+        let server: MockServer = MockServer::start().await;
+        mount_failure(&server, ResponseTemplate::new(429), 1).await;
+        mount_failure(&server, ResponseTemplate::new(503), 2).await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({ "answer": "Paris" })))
+            .with_priority(3)
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let policy: RetryPolicy = get_policy(3);
+        let started_at: Instant = Instant::now();
+        let (response, retries) = send(&policy, &server).await.unwrap();
+        let elapsed: Duration = started_at.elapsed();
+
+        assert_eq!(response["answer"], "Paris");
+        assert_eq!(retries, 2);
+        // Waits 50ms then 100ms between the three attempts
+        assert!(elapsed >= Duration::from_millis(150), "{:?}", elapsed);
+        assert!(elapsed < Duration::from_millis(1000), "{:?}", elapsed);
+    }
+
+    #[tokio::test]
+    async fn gives_up_after_the_last_attempt() {
+        let server: MockServer = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(500))
+            .expect(2)
+            .mount(&server)
+            .await;
+
+        let error: LlmError = send(&get_policy(2), &server).await.unwrap_err();
+
+        assert_eq!(error.get_retries(), 1);
+        match error {
+            LlmError::Retried { source, .. } => assert!(
+                matches!(*source, LlmError::Status { status: StatusCode::INTERNAL_SERVER_ERROR, .. }),
+                "{}", source,
+            ),
+            error => panic!("Unexpected error {}", error),
+        }
+    }
+
+    #[tokio::test]
+    async fn does_not_retry_client_errors() {
+        let server: MockServer = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(400).set_body_string("bad request"))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let error: LlmError = send(&get_policy(3), &server).await.unwrap_err();
+
+        assert!(matches!(error, LlmError::Status { status: StatusCode::BAD_REQUEST, .. }), "{}", error);
+        assert_eq!(error.get_retries(), 0);
+    }
+
+    #[tokio::test]
+    async fn caps_retry_after_to_the_max_backoff() {
+        let server: MockServer = MockServer::start().await;
+        mount_failure(&server, ResponseTemplate::new(429).insert_header("Retry-After", "120"), 1).await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({})))
+            .with_priority(2)
+            .mount(&server)
+            .await;
+
+        let started_at: Instant = Instant::now();
+        let (_, retries) = send(&get_policy(2), &server).await.unwrap();
+        assert_eq!(retries, 1);
+        let elapsed: Duration = started_at.elapsed();
+
+        assert!(elapsed >= Duration::from_millis(200), "{:?}", elapsed);
+        assert!(elapsed < Duration::from_millis(1000), "{:?}", elapsed);
+    }
+
+    #[test]
+    fn bounds_the_backoff() {
+        let policy: RetryPolicy = RetryPolicy { jitter: 0.5, ..get_policy(10) };
+
+        for attempt in 1..10 {
+            let expected: f64 = (50.0 * 2f64.powi(attempt as i32 - 1)).min(200.0);
+            let backoff: f64 = policy.get_backoff(attempt).as_millis() as f64;
+            assert!(backoff >= expected * 0.5 - 1.0 && backoff <= expected * 1.5, "{} {}", attempt, backoff);
+        }
+    }
+}
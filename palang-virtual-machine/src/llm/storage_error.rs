@@ -0,0 +1,39 @@
+use std::{io, path::PathBuf};
+
+use thiserror::Error;
+
+/// Why a file backing cassettes, caches, fixtures, prices, sessions or traces
+/// could not be used.
+#[derive(Debug, Error)]
+pub enum StorageError {
+    #[error("Could not read {file:?} ({source})")]
+    Read {
+        file: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+
+    #[error("Could not write {file:?} ({source})")]
+    Write {
+        file: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+
+    #[error("Invalid JSON in {file:?} ({source})")]
+    Json {
+        file: PathBuf,
+        #[source]
+        source: serde_json::Error,
+    },
+
+    #[error("Invalid YAML in {file:?} ({source})")]
+    Yaml {
+        file: PathBuf,
+        #[source]
+        source: serde_yaml::Error,
+    },
+
+    #[error("Cassette {0:?} not found, record it first")]
+    CassetteNotFound(PathBuf),
+}
@@ -0,0 +1,80 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+
+use super::tool_call_error::ToolCallError;
+
+#[derive(Debug, Clone)]
+pub struct Tool {
+    pub name: String,
+    pub task: String,
+    pub description: String,
+    pub parameters: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ToolCall {
+    pub id: String,
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub function: ToolCallFunction,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ToolCallFunction {
+    pub name: String,
+    pub arguments: String,
+}
+
+impl Tool {
+    pub fn new(
+        task: &str,
+        description: &str,
+        parameters: &[String],
+    ) -> Self {
+        Tool {
+            // Tool names may only contain letters, digits, underscores and dashes.
+            name: task.replace("/", "-"),
+            task: task.to_string(),
+            description: description.to_string(),
+            parameters: parameters.to_vec(),
+        }
+    }
+
+    pub fn to_json(&self) -> Value {
+        let mut properties: Map<String, Value> = Map::new();
+        for parameter in &self.parameters {
+            properties.insert(parameter.clone(), json!({ "type": "string" }));
+        }
+
+        json!({
+            "type": "function",
+            "function": {
+                "name": self.name,
+                "description": self.description,
+                "parameters": {
+                    "type": "object",
+                    "properties": properties,
+                    "required": self.parameters,
+                },
+            },
+        })
+    }
+}
+
+impl ToolCall {
+    pub fn get_arguments(&self, tool: &Tool) -> Result<Vec<String>, ToolCallError> {
+        let arguments: Value = serde_json::from_str(&self.function.arguments)
+                                          .map_err(|e| ToolCallError::InvalidArguments { tool: tool.name.clone(), source: e })?;
+
+        tool.parameters
+            .iter()
+            .map(|parameter| {
+                match arguments.get(parameter) {
+                    Some(Value::String(value)) => Ok(value.clone()),
+                    Some(value) => Ok(value.to_string()),
+                    None => Err(ToolCallError::MissingArgument { tool: tool.name.clone(), parameter: parameter.clone() }),
+                }
+            })
+            .collect()
+    }
+}
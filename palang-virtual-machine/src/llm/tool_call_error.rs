@@ -0,0 +1,18 @@
+use thiserror::Error;
+
+/// Why the arguments of a tool call requested by the model cannot be used.
+#[derive(Debug, Error)]
+pub enum ToolCallError {
+    #[error("Invalid arguments for tool {tool} ({source})")]
+    InvalidArguments {
+        tool: String,
+        #[source]
+        source: serde_json::Error,
+    },
+
+    #[error("Missing argument \"{parameter}\" for tool {tool}")]
+    MissingArgument {
+        tool: String,
+        parameter: String,
+    },
+}
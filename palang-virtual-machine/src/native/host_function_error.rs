@@ -0,0 +1,45 @@
+use jsonpath_rust::parser::errors::JsonPathError;
+use reqwest::StatusCode;
+use thiserror::Error;
+
+/// Why one of the example host functions failed.
+#[derive(Debug, Error)]
+pub enum HostFunctionError {
+    #[error("Could not get {url} ({source})")]
+    Request {
+        url: String,
+        #[source]
+        source: reqwest::Error,
+    },
+
+    #[error("{url} returned HTTP {status}: {body}")]
+    Status {
+        url: String,
+        status: StatusCode,
+        body: String,
+    },
+
+    #[error("Could not read {path} ({source})")]
+    File {
+        path: String,
+        #[source]
+        source: std::io::Error,
+    },
+
+    #[error("Invalid regex {pattern} ({source})")]
+    Regex {
+        pattern: String,
+        #[source]
+        source: regex::Error,
+    },
+
+    #[error("Invalid JSON ({0})")]
+    Json(#[from] serde_json::Error),
+
+    #[error("Invalid JSONPath {path} ({source})")]
+    JsonPath {
+        path: String,
+        #[source]
+        source: JsonPathError,
+    },
+}
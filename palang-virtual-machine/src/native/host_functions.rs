@@ -0,0 +1,189 @@
+use jsonpath_rust::JsonPath;
+use regex::Regex;
+use serde_json::Value;
+
+use super::{host_function_error::HostFunctionError, native_function::NativeFunction, native_parameter::NativeParameter};
+
+/// Example native functions giving Palang code access to the host:
+///
+/// | Function                                                      | Returns                                      |
+/// |---------------------------------------------------------------|----------------------------------------------|
+/// | `host::http::get(url: std::Text) -> std::Text`                | The body of a successful GET request.        |
+/// | `host::file::read(path: std::Text) -> std::Text`              | The content of a UTF-8 file.                 |
+/// | `host::regex::find(pattern: std::Text, text: std::Text) -> std::Text` | The first match, empty if none.      |
+/// | `host::json::query(json: std::Json, path: std::Text) -> std::Json`    | The JSON array of the values matched by a JSONPath. |
+pub fn get_host_functions() -> Vec<NativeFunction> {
+    vec![
+        NativeFunction::new_async(
+            "host/http/get",
+            vec![NativeParameter::new("url", "std/text")],
+            "std/text",
+            |arguments| Box::pin(async move { Ok(http_get(&arguments[0]).await?) }),
+        ),
+        NativeFunction::new_async(
+            "host/file/read",
+            vec![NativeParameter::new("path", "std/text")],
+            "std/text",
+            |arguments| Box::pin(async move { Ok(read_file(&arguments[0]).await?) }),
+        ),
+        NativeFunction::new(
+            "host/regex/find",
+            vec![NativeParameter::new("pattern", "std/text"), NativeParameter::new("text", "std/text")],
+            "std/text",
+            |arguments| Ok(find_regex(&arguments[0], &arguments[1])?),
+        ),
+        NativeFunction::new(
+            "host/json/query",
+            vec![NativeParameter::new("json", "std/json"), NativeParameter::new("path", "std/text")],
+            "std/json",
+            |arguments| Ok(query_json(&arguments[0], &arguments[1])?),
+        ),
+    ]
+}
+
+async fn http_get(url: &str) -> Result<String, HostFunctionError> {
+    let request_error = |e| HostFunctionError::Request { url: url.to_string(), source: e };
+
+    let response = reqwest::get(url)
+        .await
+        .map_err(request_error)?;
+
+    let status = response.status();
+    let body: String = response
+        .text()
+        .await
+        .map_err(request_error)?;
+
+    match status.is_success() {
+        true => Ok(body),
+        false => Err(HostFunctionError::Status { url: url.to_string(), status, body }),
+    }
+}
+
+async fn read_file(path: &str) -> Result<String, HostFunctionError> {
+    tokio::fs::read_to_string(path)
+        .await
+        .map_err(|e| HostFunctionError::File { path: path.to_string(), source: e })
+}
+
+fn find_regex(pattern: &str, text: &str) -> Result<String, HostFunctionError> {
+    let regex: Regex = Regex::new(pattern)
+        .map_err(|e| HostFunctionError::Regex { pattern: pattern.to_string(), source: e })?;
+
+    Ok(
+        regex.find(text)
+             .map(|found| found.as_str().to_string())
+             .unwrap_or_default()
+    )
+}
+
+fn query_json(json: &str, path: &str) -> Result<String, HostFunctionError> {
+    let value: Value = serde_json::from_str(json)?;
+    let matches: Vec<&Value> = value
+        .query(path)
+        .map_err(|e| HostFunctionError::JsonPath { path: path.to_string(), source: e })?;
+
+    Ok(serde_json::to_string(&matches).unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use wiremock::{matchers::{method, path}, Mock, MockServer, ResponseTemplate};
+
+    use crate::native::native_function_error::NativeFunctionError;
+
+    use super::*;
+
+    fn get_host_function(name: &str) -> NativeFunction {
+        get_host_functions()
+            .into_iter()
+            .find(|function| function.name == name)
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn http_get_returns_the_body() {
+        let server: MockServer = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/capital"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("Paris"))
+            .mount(&server)
+            .await;
+
+        let result = get_host_function("host/http/get")
+            .call(&[format!("{}/capital", server.uri())])
+            .await;
+
+        assert_eq!(result.unwrap(), "Paris");
+    }
+
+    #[tokio::test]
+    async fn http_get_fails_on_error_status() {
+        let server: MockServer = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(404).set_body_string("missing"))
+            .mount(&server)
+            .await;
+
+        let error: NativeFunctionError = get_host_function("host/http/get")
+            .call(&[server.uri()])
+            .await
+            .unwrap_err();
+
+        let NativeFunctionError::Failed(error) = error else { panic!("{:?}", error) };
+        assert!(matches!(
+            error.downcast_ref::<HostFunctionError>(),
+            Some(HostFunctionError::Status { status, .. }) if status.as_u16() == 404
+        ));
+    }
+
+    #[tokio::test]
+    async fn file_read_returns_the_content() {
+        let file = std::env::temp_dir().join(format!("palang-host-read-{}.txt", std::process::id()));
+        fs::write(&file, "first\nsecond").unwrap();
+
+        let result = get_host_function("host/file/read")
+            .call(&[file.to_string_lossy().to_string()])
+            .await;
+        fs::remove_file(&file).unwrap();
+
+        assert_eq!(result.unwrap(), "first\nsecond");
+    }
+
+    #[tokio::test]
+    async fn file_read_fails_on_missing_file() {
+        let result = get_host_function("host/file/read")
+            .call(&["/nonexistent/palang".to_string()])
+            .await;
+
+        assert!(matches!(result, Err(NativeFunctionError::Failed(_))));
+    }
+
+    #[test]
+    fn regex_find_returns_the_first_match() {
+        assert_eq!(find_regex(r"\d+", "in 2024 and 2025").unwrap(), "2024");
+        assert_eq!(find_regex(r"\d+", "no digits").unwrap(), "");
+        assert!(matches!(find_regex("(", "text"), Err(HostFunctionError::Regex { .. })));
+    }
+
+    #[test]
+    fn json_query_returns_the_matches() {
+        let json: &str = r#"{"cities": [{"name": "Paris"}, {"name": "Lyon"}]}"#;
+
+        assert_eq!(query_json(json, "$.cities[*].name").unwrap(), r#"["Paris","Lyon"]"#);
+        assert_eq!(query_json(json, "$.missing").unwrap(), "[]");
+        assert!(matches!(query_json("{", "$"), Err(HostFunctionError::Json(_))));
+        assert!(matches!(query_json(json, "cities"), Err(HostFunctionError::JsonPath { .. })));
+    }
+
+    #[tokio::test]
+    async fn calls_check_the_number_of_arguments() {
+        let result = get_host_function("host/regex/find")
+            .call(&["a".to_string()])
+            .await;
+
+        assert!(matches!(result, Err(NativeFunctionError::ArgumentCount { expected: 2, actual: 1 })));
+    }
+}
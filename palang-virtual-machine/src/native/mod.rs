@@ -0,0 +1,6 @@
+pub mod native_parameter;
+pub mod native_function;
+pub mod native_function_error;
+pub mod native_functions_registry;
+pub mod host_functions;
+pub mod host_function_error;
@@ -0,0 +1,76 @@
+use std::{error::Error, fmt, future::Future, pin::Pin, sync::Arc};
+
+use super::{native_function_error::NativeFunctionError, native_parameter::NativeParameter};
+
+/// What an implementation returns, failing with any error of the host application.
+pub type NativeFunctionResult = Result<String, Box<dyn Error + Send + Sync>>;
+
+pub type NativeFunctionFuture = Pin<Box<dyn Future<Output = NativeFunctionResult> + Send>>;
+
+type NativeImplementation = Arc<dyn Fn(Vec<String>) -> NativeFunctionFuture + Send + Sync>;
+
+/// A task implemented by the host application instead of by an assembly.
+#[derive(Clone)]
+pub struct NativeFunction {
+    pub name: String,
+    pub parameters: Vec<NativeParameter>,
+    pub return_type: String,
+    implementation: NativeImplementation,
+}
+
+impl NativeFunction {
+    pub fn new<F>(
+        name: &str,
+        parameters: Vec<NativeParameter>,
+        return_type: &str,
+        implementation: F,
+    ) -> Self
+        where F: Fn(Vec<String>) -> NativeFunctionResult + Send + Sync + 'static
+    {
+        let implementation: Arc<F> = Arc::new(implementation);
+
+        NativeFunction::new_async(name, parameters, return_type, move |arguments| {
+            let implementation: Arc<F> = implementation.clone();
+            Box::pin(async move { implementation(arguments) })
+        })
+    }
+
+    pub fn new_async<F>(
+        name: &str,
+        parameters: Vec<NativeParameter>,
+        return_type: &str,
+        implementation: F,
+    ) -> Self
+        where F: Fn(Vec<String>) -> NativeFunctionFuture + Send + Sync + 'static
+    {
+        NativeFunction {
+            name: name.to_lowercase(),
+            parameters,
+            return_type: return_type.to_lowercase(),
+            implementation: Arc::new(implementation),
+        }
+    }
+
+    pub async fn call(&self, arguments: &[String]) -> Result<String, NativeFunctionError> {
+        if arguments.len() != self.parameters.len() {
+            return Err(
+                NativeFunctionError::ArgumentCount {
+                    expected: self.parameters.len(),
+                    actual: arguments.len(),
+                }
+            );
+        }
+
+        Ok((self.implementation)(arguments.to_vec()).await?)
+    }
+}
+
+impl fmt::Debug for NativeFunction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("NativeFunction")
+         .field("name", &self.name)
+         .field("parameters", &self.parameters)
+         .field("return_type", &self.return_type)
+         .finish()
+    }
+}
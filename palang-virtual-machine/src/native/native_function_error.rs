@@ -0,0 +1,17 @@
+use std::error::Error as StdError;
+
+use thiserror::Error;
+
+/// Why a call to a native function failed.
+#[derive(Debug, Error)]
+pub enum NativeFunctionError {
+    #[error("Expected {expected} arguments, got {actual}")]
+    ArgumentCount {
+        expected: usize,
+        actual: usize,
+    },
+
+    /// The error returned by the implementation of the host application.
+    #[error(transparent)]
+    Failed(#[from] Box<dyn StdError + Send + Sync>),
+}
@@ -0,0 +1,28 @@
+use std::collections::HashMap;
+
+use super::native_function::NativeFunction;
+
+#[derive(Clone, Default)]
+pub struct NativeFunctionsRegistry {
+    functions: HashMap<String, NativeFunction>,
+}
+
+impl NativeFunctionsRegistry {
+    pub fn new() -> Self {
+        NativeFunctionsRegistry {
+            functions: HashMap::new(),
+        }
+    }
+
+    pub fn register(&mut self, function: &NativeFunction) {
+        self.functions.insert(function.name.clone(), function.clone());
+    }
+
+    pub fn get(&self, name: &String) -> Option<NativeFunction> {
+        self.functions.get(name).cloned()
+    }
+
+    pub fn get_all(&self) -> Vec<NativeFunction> {
+        self.functions.values().cloned().collect()
+    }
+}
@@ -0,0 +1,14 @@
+#[derive(Debug, Clone)]
+pub struct NativeParameter {
+    pub name: String,
+    pub full_type: String,
+}
+
+impl NativeParameter {
+    pub fn new(name: &str, full_type: &str) -> Self {
+        NativeParameter {
+            name: name.to_string(),
+            full_type: full_type.to_lowercase(),
+        }
+    }
+}
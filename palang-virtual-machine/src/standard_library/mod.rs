@@ -0,0 +1,9 @@
+use crate::assembly::{assembly::Assembly, load_error::LoadError, loader::load_assembly};
+
+/// Compiled from `std/std.palang`, recompile it and copy the sources to the
+/// compiler after changing them.
+pub const STANDARD_LIBRARY_ASSEMBLY: &str = include_str!("../../std/std.palasm");
+
+pub fn load_standard_library() -> Result<Assembly, LoadError> {
+    load_assembly(STANDARD_LIBRARY_ASSEMBLY)
+}
@@ -0,0 +1,348 @@
+use std::{fmt, time::{Duration, Instant}};
+
+use serde_json::Value;
+
+use super::execution_report::Usage;
+use crate::llm::{configuration_error::ConfigurationError, llm_response::LargeLanguageModelResponse};
+
+/// Limits on what a single execution may use. Hosts build one from a profile
+/// or from the request executing the task.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Budget {
+    pub max_llm_calls: Option<u64>,
+    pub max_tokens: Option<u64>,
+    pub max_duration: Option<Duration>,
+}
+
+impl Budget {
+    /// Builds the budget from a profile's `budget`:
+    ///
+    /// ```yaml
+    /// budget:
+    ///   max_llm_calls: 20
+    ///   max_tokens: 50000
+    ///   max_duration_seconds: 300
+    /// ```
+    pub fn from_configuration(configuration: &Value) -> Result<Self, ConfigurationError> {
+        let get_limit = |key: &str| -> Result<Option<u64>, ConfigurationError> {
+            match configuration.get(key) {
+                Some(Value::Null) | None => Ok(None),
+                Some(limit) => match limit.as_u64() {
+                    Some(limit) if limit > 0 => Ok(Some(limit)),
+                    _ => Err(ConfigurationError::NotPositive(format!("budget.{}", key))),
+                },
+            }
+        };
+
+        Ok(
+            Budget {
+                max_llm_calls: get_limit("max_llm_calls")?,
+                max_tokens: get_limit("max_tokens")?,
+                max_duration: get_limit("max_duration_seconds")?.map(Duration::from_secs),
+            }
+        )
+    }
+
+    /// Replaces the limits set in `other`.
+    pub fn with_overrides(mut self, other: &Budget) -> Self {
+        self.max_llm_calls = other.max_llm_calls.or(self.max_llm_calls);
+        self.max_tokens = other.max_tokens.or(self.max_tokens);
+        self.max_duration = other.max_duration.or(self.max_duration);
+        self
+    }
+
+    pub fn is_unlimited(&self) -> bool {
+        *self == Budget::default()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BudgetLimit {
+    LlmCalls(u64),
+    Tokens(u64),
+    Duration(Duration),
+}
+
+impl fmt::Display for BudgetLimit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BudgetLimit::LlmCalls(limit) => write!(f, "{} LLM calls", limit),
+            BudgetLimit::Tokens(limit) => write!(f, "{} tokens", limit),
+            BudgetLimit::Duration(limit) => write!(f, "{:?}", limit),
+        }
+    }
+}
+
+/// The error of an execution stopped by its budget, with what it used until then.
+#[derive(Debug, Clone)]
+pub struct BudgetExceeded {
+    pub limit: BudgetLimit,
+    pub usage: Usage,
+    pub elapsed: Duration,
+}
+
+impl fmt::Display for BudgetExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Budget exceeded: limit of {} reached after {} LLM calls, {} tokens ({} prompt, {} completion) and {:.1?}",
+            self.limit,
+            self.usage.invocations,
+            self.usage.tokens.prompt_tokens + self.usage.tokens.completion_tokens,
+            self.usage.tokens.prompt_tokens,
+            self.usage.tokens.completion_tokens,
+            self.elapsed,
+        )
+    }
+}
+
+impl std::error::Error for BudgetExceeded {}
+
+/// What an execution has used of its budget. Shared by the machines
+/// executing its calls concurrently.
+pub(crate) struct BudgetTracker {
+    budget: Budget,
+    started_at: Instant,
+    /// Counted before the calls complete, so that concurrent calls cannot
+    /// all take the last one.
+    started_invocations: u64,
+    usage: Usage,
+}
+
+impl BudgetTracker {
+    pub(crate) fn start(budget: &Budget) -> Self {
+        BudgetTracker {
+            budget: budget.clone(),
+            started_at: Instant::now(),
+            started_invocations: 0,
+            usage: Usage::default(),
+        }
+    }
+
+    pub(crate) fn get_deadline(&self) -> Option<Instant> {
+        self.budget.max_duration.map(|duration| self.started_at + duration)
+    }
+
+    fn exceeded(&self, limit: BudgetLimit) -> BudgetExceeded {
+        BudgetExceeded {
+            limit,
+            usage: self.usage.clone(),
+            elapsed: self.started_at.elapsed(),
+        }
+    }
+
+    pub(crate) fn duration_exceeded(&self) -> BudgetExceeded {
+        self.exceeded(BudgetLimit::Duration(self.budget.max_duration.unwrap_or_default()))
+    }
+
+    /// Fails once the execution has run for longer than allowed.
+    pub(crate) fn check_duration(&self) -> Result<(), BudgetExceeded> {
+        match self.budget.max_duration {
+            Some(limit) if self.started_at.elapsed() >= limit => Err(self.duration_exceeded()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Counts a call to the model, and fails if it would go over the budget.
+    pub(crate) fn start_invocation(&mut self) -> Result<(), BudgetExceeded> {
+        self.check_duration()?;
+
+        if let Some(limit) = self.budget.max_llm_calls {
+            if self.started_invocations >= limit {
+                return Err(self.exceeded(BudgetLimit::LlmCalls(limit)));
+            }
+        }
+        self.check_tokens(|tokens, limit| tokens >= limit)?;
+
+        self.started_invocations += 1;
+        Ok(())
+    }
+
+    /// Counts a response, and fails if its tokens went over the budget.
+    pub(crate) fn add(&mut self, response: &LargeLanguageModelResponse) -> Result<(), BudgetExceeded> {
+        self.usage.add(response);
+        self.check_tokens(|tokens, limit| tokens > limit)
+    }
+
+    fn check_tokens<F>(&self, is_exceeded: F) -> Result<(), BudgetExceeded>
+        where F: Fn(u64, u64) -> bool
+    {
+        let tokens: u64 = self.usage.tokens.prompt_tokens + self.usage.tokens.completion_tokens;
+        match self.budget.max_tokens {
+            Some(limit) if is_exceeded(tokens, limit) => Err(self.exceeded(BudgetLimit::Tokens(limit))),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::{
+        assembly::loader::load_assembly,
+        boot_machine,
+        llm::{
+            invokable_llm::{DeltaHandler, InvocationFuture, InvokableLargeLanguageModel},
+            llm::LargeLanguageModel,
+            llm_response::TokenUsage,
+            message::Message,
+            model_settings::ModelSettings,
+            tool::Tool
+        },
+        virtualization::{runtime_error::RuntimeError, virtual_machine::VirtualMachine}
+    };
+
+    use super::*;
+
+    /// Answers after a delay, using 10 prompt and 5 completion tokens.
+    struct MeteredLargeLanguageModel {
+        delay: Duration,
+    }
+
+    impl InvokableLargeLanguageModel for MeteredLargeLanguageModel {
+        fn invoke<'a>(
+            &'a self,
+            _messages: &'a [Message],
+            _tools: &'a [Tool],
+            _settings: &'a ModelSettings,
+        ) -> InvocationFuture<'a> {
+            Box::pin(async move {
+                tokio::time::sleep(self.delay).await;
+                Ok(LargeLanguageModelResponse {
+                    usage: TokenUsage { prompt_tokens: 10, completion_tokens: 5 },
+                    ..LargeLanguageModelResponse::new(&Message::assistant("answer"))
+                })
+            })
+        }
+
+        fn invoke_streaming<'a>(
+            &'a self,
+            messages: &'a [Message],
+            tools: &'a [Tool],
+            settings: &'a ModelSettings,
+            _on_delta: DeltaHandler<'a>,
+        ) -> InvocationFuture<'a> {
+            self.invoke(messages, tools, settings)
+        }
+    }
+
+    /// Asks three questions, each one about the previous answer.
+    const ASSEMBLY: &str = "PALASM 1
+MODULE tests
+PROMPT tests/ask
+ARGUMENTS question
+RETURNS std/text
+START
+Answer @{question}.
+END
+FUNCTION tests/askthrice
+ARGUMENTS question
+RETURNS std/text
+START
+INVOKE tests/ask question
+ASSIGN first @invocation_registry
+INVOKE tests/ask first
+ASSIGN second @invocation_registry
+INVOKE tests/ask second
+ASSIGN third @invocation_registry
+RETURN third
+END";
+
+    async fn execute(budget: &Budget, delay: Duration) -> Result<String, RuntimeError> {
+        let llm: LargeLanguageModel = LargeLanguageModel::new_custom(MeteredLargeLanguageModel { delay });
+        let mut vm: VirtualMachine = boot_machine(&llm);
+        vm.load_assembly(&load_assembly(ASSEMBLY).unwrap()).unwrap();
+        vm.set_budget(budget);
+
+        vm.execute(
+            &"tests/askthrice".to_string(),
+            &vec!["What is Palang?".to_string()],
+            &ModelSettings { model: "metered".to_string(), temperature: 0.0, max_tokens: 16 },
+        ).await.await
+    }
+
+    async fn get_budget_exceeded(budget: &Budget, delay: Duration) -> BudgetExceeded {
+        match execute(budget, delay).await.unwrap_err().without_stack_trace() {
+            RuntimeError::BudgetExceeded(exceeded) => exceeded.clone(),
+            error => panic!("Unexpected error {}", error),
+        }
+    }
+
+    #[tokio::test]
+    async fn runs_within_the_budget() {
+        let budget: Budget = Budget {
+            max_llm_calls: Some(3),
+            max_tokens: Some(45),
+            max_duration: Some(Duration::from_secs(10)),
+        };
+
+        assert_eq!(execute(&budget, Duration::ZERO).await.unwrap(), "answer");
+    }
+
+    #[tokio::test]
+    async fn stops_at_the_call_limit() {
+        let exceeded: BudgetExceeded = get_budget_exceeded(
+            &Budget { max_llm_calls: Some(2), ..Budget::default() },
+            Duration::ZERO,
+        ).await;
+
+        assert_eq!(exceeded.limit, BudgetLimit::LlmCalls(2));
+        assert_eq!(exceeded.usage.invocations, 2);
+        assert_eq!(exceeded.usage.tokens, TokenUsage { prompt_tokens: 20, completion_tokens: 10 });
+    }
+
+    #[tokio::test]
+    async fn stops_at_the_token_limit() {
+        let exceeded: BudgetExceeded = get_budget_exceeded(
+            &Budget { max_tokens: Some(20), ..Budget::default() },
+            Duration::ZERO,
+        ).await;
+
+        // The second response goes over the limit, and is counted
+        assert_eq!(exceeded.limit, BudgetLimit::Tokens(20));
+        assert_eq!(exceeded.usage.invocations, 2);
+        assert_eq!(exceeded.usage.tokens, TokenUsage { prompt_tokens: 20, completion_tokens: 10 });
+        assert!(exceeded.to_string().contains("after 2 LLM calls, 30 tokens (20 prompt, 10 completion)"), "{}", exceeded);
+    }
+
+    #[tokio::test]
+    async fn stops_at_the_duration_limit() {
+        let exceeded: BudgetExceeded = get_budget_exceeded(
+            &Budget { max_duration: Some(Duration::from_millis(150)), ..Budget::default() },
+            Duration::from_millis(100),
+        ).await;
+
+        // The second invocation is interrupted
+        assert_eq!(exceeded.limit, BudgetLimit::Duration(Duration::from_millis(150)));
+        assert_eq!(exceeded.usage.invocations, 1);
+        assert_eq!(exceeded.usage.tokens, TokenUsage { prompt_tokens: 10, completion_tokens: 5 });
+        assert!(exceeded.elapsed >= Duration::from_millis(150), "{:?}", exceeded.elapsed);
+        assert!(exceeded.elapsed < Duration::from_millis(250), "{:?}", exceeded.elapsed);
+    }
+
+    #[test]
+    fn reads_the_configuration() {
+        assert_eq!(
+            Budget::from_configuration(&json!({ "max_llm_calls": 20, "max_duration_seconds": 300 })).unwrap(),
+            Budget { max_llm_calls: Some(20), max_tokens: None, max_duration: Some(Duration::from_secs(300)) },
+        );
+        assert!(Budget::from_configuration(&Value::Null).unwrap().is_unlimited());
+        assert!(matches!(
+            Budget::from_configuration(&json!({ "max_tokens": 0 })),
+            Err(ConfigurationError::NotPositive(setting)) if setting == "budget.max_tokens"
+        ));
+        assert!(matches!(
+            Budget::from_configuration(&json!({ "max_tokens": "many" })),
+            Err(ConfigurationError::NotPositive(setting)) if setting == "budget.max_tokens"
+        ));
+    }
+
+    #[test]
+    fn overrides_the_limits_set() {
+        let budget: Budget = Budget { max_llm_calls: Some(20), max_tokens: Some(1000), max_duration: None }
+            .with_overrides(&Budget { max_tokens: Some(50), ..Budget::default() });
+
+        assert_eq!(budget, Budget { max_llm_calls: Some(20), max_tokens: Some(50), max_duration: None });
+    }
+}
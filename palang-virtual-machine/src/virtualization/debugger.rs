@@ -0,0 +1,278 @@
+use std::{collections::HashMap, fmt, str::FromStr};
+
+use crate::assembly::{function::Function, instruction::Instruction};
+
+use super::runtime_error::RuntimeError;
+
+/// Pauses a function before its first instruction, or before one of its instructions.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Breakpoint {
+    Function(String),
+    Instruction(String, usize),
+}
+
+/// Parses `function` or `function:instruction`, the instruction being its index.
+impl FromStr for Breakpoint {
+    type Err = String;
+
+    fn from_str(breakpoint: &str) -> Result<Self, Self::Err> {
+        match breakpoint.rsplit_once(':') {
+            Some((function, index)) => {
+                let index: usize = index
+                    .parse()
+                    .map_err(|_| format!("Invalid instruction index {} in breakpoint {}", index, breakpoint))?;
+                Ok(Breakpoint::Instruction(function.to_string(), index))
+            },
+            None => Ok(Breakpoint::Function(breakpoint.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for Breakpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Breakpoint::Function(function) => write!(f, "{}", function),
+            Breakpoint::Instruction(function, index) => write!(f, "{}:{}", function, index),
+        }
+    }
+}
+
+/// What to do once the debugger resumes the execution.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DebugCommand {
+    /// Runs until the next breakpoint.
+    Continue,
+    /// Pauses at the next instruction, inside the invoked function if there is one.
+    StepInto,
+    /// Pauses at the next instruction of the current function or of its callers.
+    StepOver,
+    /// Pauses once the current function has returned.
+    StepOut,
+    /// Stops the execution.
+    Abort,
+}
+
+/// The state of a paused function.
+pub struct DebugState<'a> {
+    pub function: &'a Function,
+    pub program_counter: usize,
+    pub instruction: &'a Instruction,
+    pub variables: &'a HashMap<String, String>,
+    pub invocation_registry: &'a Option<String>,
+    /// Names of the tasks being executed, the outermost first.
+    pub call_stack: Vec<String>,
+}
+
+pub trait DebugHandler: Send + Sync {
+    /// Called whenever the execution pauses. Breakpoints can be changed before resuming.
+    fn on_pause(&mut self, state: &DebugState, breakpoints: &mut Vec<Breakpoint>) -> DebugCommand;
+}
+
+pub struct Debugger {
+    breakpoints: Vec<Breakpoint>,
+    handler: Box<dyn DebugHandler>,
+    command: DebugCommand,
+    /// Call depth at which the last step command was given.
+    depth: usize,
+}
+
+impl Debugger {
+    pub fn new<T>(handler: T) -> Self
+        where T: DebugHandler + 'static
+    {
+        Debugger {
+            breakpoints: Vec::new(),
+            handler: Box::new(handler),
+            command: DebugCommand::Continue,
+            depth: 0,
+        }
+    }
+
+    pub fn with_breakpoints(mut self, breakpoints: &[Breakpoint]) -> Self {
+        self.breakpoints = breakpoints.to_vec();
+        self
+    }
+
+    /// Pauses at the very first instruction executed.
+    pub fn stop_on_entry(mut self) -> Self {
+        self.command = DebugCommand::StepInto;
+        self
+    }
+
+    /// Hands the state to the handler if the execution must pause before the
+    /// instruction, and fails if the handler aborts.
+    pub(crate) fn before_step(&mut self, state: &DebugState) -> Result<(), RuntimeError> {
+        let depth: usize = state.call_stack.len();
+
+        let at_breakpoint: bool = self.breakpoints.iter().any(|breakpoint| match breakpoint {
+            Breakpoint::Function(function) => *function == state.function.name && state.program_counter == 0,
+            Breakpoint::Instruction(function, index) => {
+                *function == state.function.name && *index == state.program_counter
+            },
+        });
+        let stepped: bool = match self.command {
+            DebugCommand::StepInto => true,
+            DebugCommand::StepOver => depth <= self.depth,
+            DebugCommand::StepOut => depth < self.depth,
+            DebugCommand::Continue | DebugCommand::Abort => false,
+        };
+
+        if !at_breakpoint && !stepped {
+            return Ok(());
+        }
+
+        self.command = self.handler.on_pause(state, &mut self.breakpoints);
+        self.depth = depth;
+
+        match self.command {
+            DebugCommand::Abort => Err(RuntimeError::Aborted),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+
+    /// Resumes with each command in turn, repeating the last one, and records
+    /// where the execution paused.
+    struct ScriptedHandler {
+        commands: Vec<DebugCommand>,
+        pauses: Arc<Mutex<Vec<(String, usize)>>>,
+    }
+
+    impl DebugHandler for ScriptedHandler {
+        fn on_pause(&mut self, state: &DebugState, _breakpoints: &mut Vec<Breakpoint>) -> DebugCommand {
+            let mut pauses = self.pauses.lock().unwrap();
+            pauses.push((state.function.name.clone(), state.program_counter));
+
+            self.commands[(pauses.len() - 1).min(self.commands.len() - 1)]
+        }
+    }
+
+    /// The instructions `tests/outer` executes, with the one at 0 invoking
+    /// `tests/inner`, and their call depths.
+    const STEPS: [(&str, usize, usize); 6] = [
+        ("tests/outer", 0, 1),
+        ("tests/inner", 0, 2),
+        ("tests/inner", 1, 2),
+        ("tests/inner", 2, 2),
+        ("tests/outer", 1, 1),
+        ("tests/outer", 2, 1),
+    ];
+
+    fn get_function(name: &str) -> Function {
+        Function {
+            name: name.to_string(),
+            parameters: Vec::new(),
+            return_type: "std/text".to_string(),
+            instructions: Vec::new(),
+        }
+    }
+
+    /// Runs the steps under a debugger built from `debugger` and returns where
+    /// it paused, and whether it aborted.
+    fn debug<F>(commands: &[DebugCommand], debugger: F) -> (Vec<(String, usize)>, bool)
+        where F: FnOnce(Debugger) -> Debugger
+    {
+        let pauses: Arc<Mutex<Vec<(String, usize)>>> = Arc::new(Mutex::new(Vec::new()));
+        let mut debugger: Debugger = debugger(Debugger::new(ScriptedHandler { commands: commands.to_vec(), pauses: pauses.clone() }));
+        let instruction: Instruction = Instruction::Return("result".to_string());
+        let variables: HashMap<String, String> = HashMap::new();
+
+        let mut aborted: bool = false;
+        for (function, program_counter, depth) in STEPS {
+            let function: Function = get_function(function);
+            let call_stack: Vec<String> = STEPS.iter().take(depth).map(|(name, _, _)| name.to_string()).collect();
+            let state: DebugState = DebugState {
+                function: &function,
+                program_counter,
+                instruction: &instruction,
+                variables: &variables,
+                invocation_registry: &None,
+                call_stack,
+            };
+
+            if let Err(e) = debugger.before_step(&state) {
+                assert!(matches!(e, RuntimeError::Aborted), "{}", e);
+                aborted = true;
+                break;
+            }
+        }
+
+        let pauses: Vec<(String, usize)> = pauses.lock().unwrap().clone();
+        (pauses, aborted)
+    }
+
+    fn get_pauses(pauses: &[(&str, usize)]) -> Vec<(String, usize)> {
+        pauses.iter().map(|(function, program_counter)| (function.to_string(), *program_counter)).collect()
+    }
+
+    #[test]
+    fn pauses_at_breakpoints() {
+        let (pauses, _) = debug(&[DebugCommand::Continue], |debugger| debugger.with_breakpoints(&[
+            Breakpoint::Function("tests/inner".to_string()),
+            Breakpoint::Instruction("tests/outer".to_string(), 2),
+        ]));
+
+        assert_eq!(pauses, get_pauses(&[("tests/inner", 0), ("tests/outer", 2)]));
+    }
+
+    #[test]
+    fn steps_into_invoked_functions() {
+        let (pauses, _) = debug(&[DebugCommand::StepInto], Debugger::stop_on_entry);
+
+        assert_eq!(pauses, get_pauses(&STEPS.map(|(function, program_counter, _)| (function, program_counter))));
+    }
+
+    #[test]
+    fn steps_over_invoked_functions() {
+        let (pauses, _) = debug(&[DebugCommand::StepOver], Debugger::stop_on_entry);
+        assert_eq!(pauses, get_pauses(&[("tests/outer", 0), ("tests/outer", 1), ("tests/outer", 2)]));
+
+        // Stepping over the last instruction of a function pauses in its caller
+        let (pauses, _) = debug(&[DebugCommand::StepOver], |debugger| {
+            debugger.with_breakpoints(&[Breakpoint::Instruction("tests/inner".to_string(), 2)])
+        });
+        assert_eq!(pauses, get_pauses(&[("tests/inner", 2), ("tests/outer", 1), ("tests/outer", 2)]));
+    }
+
+    #[test]
+    fn steps_out_of_functions() {
+        let (pauses, _) = debug(&[DebugCommand::StepOut], |debugger| {
+            debugger.with_breakpoints(&[Breakpoint::Function("tests/inner".to_string())])
+        });
+
+        // Stepping out of the outermost function runs until the end
+        assert_eq!(pauses, get_pauses(&[("tests/inner", 0), ("tests/outer", 1)]));
+    }
+
+    #[test]
+    fn continues_to_the_next_breakpoint_after_stepping() {
+        let (pauses, _) = debug(&[DebugCommand::StepInto, DebugCommand::Continue], |debugger| {
+            debugger.stop_on_entry().with_breakpoints(&[Breakpoint::Instruction("tests/outer".to_string(), 2)])
+        });
+
+        assert_eq!(pauses, get_pauses(&[("tests/outer", 0), ("tests/inner", 0), ("tests/outer", 2)]));
+    }
+
+    #[test]
+    fn aborts_the_execution() {
+        let (pauses, aborted) = debug(&[DebugCommand::Abort], |debugger| {
+            debugger.with_breakpoints(&[Breakpoint::Function("tests/inner".to_string())])
+        });
+
+        assert!(aborted);
+        assert_eq!(pauses, get_pauses(&[("tests/inner", 0)]));
+    }
+
+    #[test]
+    fn parses_breakpoints() {
+        assert_eq!("tests/inner".parse::<Breakpoint>().unwrap(), Breakpoint::Function("tests/inner".to_string()));
+        assert_eq!("tests/inner:2".parse::<Breakpoint>().unwrap(), Breakpoint::Instruction("tests/inner".to_string(), 2));
+        assert!("tests/inner:last".parse::<Breakpoint>().is_err());
+    }
+}
@@ -0,0 +1,105 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+
+use crate::llm::{message::Message, llm_response::TokenUsage};
+
+/// Something that happened while executing a task. Events belong to the call
+/// of the task they happened in, calls form a tree through their parent.
+#[derive(Debug, Clone, Serialize)]
+pub struct ExecutionEvent {
+    /// Milliseconds since the Unix epoch.
+    pub timestamp_ms: u64,
+    pub call_id: u64,
+    pub parent_call_id: Option<u64>,
+    #[serde(flatten)]
+    pub kind: ExecutionEventKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TaskKind {
+    Prompt,
+    Function,
+    Native,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum ExecutionEventKind {
+    TaskStarted {
+        task: String,
+        kind: TaskKind,
+        parameters: Vec<String>,
+    },
+    TaskEnded {
+        task: String,
+        output: Option<String>,
+        error: Option<String>,
+        duration_ms: u64,
+    },
+    InstructionStep {
+        function: String,
+        program_counter: usize,
+        instruction: String,
+    },
+    PromptRendered {
+        prompt: String,
+        system: String,
+        user: String,
+    },
+    LlmRequest {
+        prompt: String,
+        provider: String,
+        model: String,
+        temperature: f32,
+        max_tokens: u32,
+        messages: Vec<Message>,
+        tools: Vec<String>,
+    },
+    LlmResponse {
+        prompt: String,
+        model: String,
+        message: Message,
+        usage: TokenUsage,
+        finish_reason: Option<String>,
+        latency_ms: u64,
+        cached: bool,
+    },
+    Retried {
+        prompt: String,
+        model: String,
+        retries: u64,
+    },
+    Error {
+        task: String,
+        error: String,
+    },
+}
+
+impl ExecutionEvent {
+    pub fn new(call_id: u64, parent_call_id: Option<u64>, kind: ExecutionEventKind) -> Self {
+        ExecutionEvent {
+            timestamp_ms: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|timestamp| timestamp.as_millis() as u64)
+                .unwrap_or_default(),
+            call_id,
+            parent_call_id,
+            kind,
+        }
+    }
+}
+
+/// Receives the events of every task executed by a virtual machine.
+pub trait ExecutionObserver: Send + Sync {
+    fn on_event(&self, event: &ExecutionEvent);
+}
+
+impl<F> ExecutionObserver for F
+    where F: Fn(&ExecutionEvent) + Send + Sync
+{
+    fn on_event(&self, event: &ExecutionEvent) {
+        self(event)
+    }
+}
@@ -0,0 +1,146 @@
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+use crate::llm::llm_response::{LargeLanguageModelResponse, TokenUsage};
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Usage {
+    pub invocations: u64,
+    #[serde(flatten)]
+    pub tokens: TokenUsage,
+    pub latency_ms: u64,
+}
+
+impl Usage {
+    pub fn add(&mut self, response: &LargeLanguageModelResponse) {
+        self.invocations += 1;
+        self.tokens.prompt_tokens += response.usage.prompt_tokens;
+        self.tokens.completion_tokens += response.usage.completion_tokens;
+        self.latency_ms += response.latency.as_millis() as u64;
+    }
+
+    pub fn merge(&mut self, other: &Usage) {
+        self.invocations += other.invocations;
+        self.tokens.prompt_tokens += other.tokens.prompt_tokens;
+        self.tokens.completion_tokens += other.tokens.completion_tokens;
+        self.latency_ms += other.latency_ms;
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ExecutionReport {
+    pub cache_hits: u64,
+    pub cache_misses: u64,
+    pub retries: u64,
+    pub usage: Usage,
+    pub usage_per_model: BTreeMap<String, Usage>,
+    pub usage_per_prompt: BTreeMap<String, Usage>,
+    /// Includes the invocations of every task a function calls.
+    pub usage_per_function: BTreeMap<String, Usage>,
+    pub usage_per_task: BTreeMap<String, Usage>,
+}
+
+impl ExecutionReport {
+    pub fn record_usage(
+        &mut self,
+        model: &str,
+        prompt: &str,
+        functions: &[String],
+        task: &str,
+        response: &LargeLanguageModelResponse,
+    ) {
+        self.usage.add(response);
+        self.usage_per_model.entry(model.to_string()).or_default().add(response);
+        self.usage_per_prompt.entry(prompt.to_string()).or_default().add(response);
+        for function in functions {
+            self.usage_per_function.entry(function.clone()).or_default().add(response);
+        }
+        self.usage_per_task.entry(task.to_string()).or_default().add(response);
+    }
+
+    /// Adds the usage of a report kept separately, such as one of a task run concurrently.
+    pub fn merge(&mut self, other: &ExecutionReport) {
+        self.cache_hits += other.cache_hits;
+        self.cache_misses += other.cache_misses;
+        self.retries += other.retries;
+        self.usage.merge(&other.usage);
+
+        for (usages, other_usages) in [
+            (&mut self.usage_per_model, &other.usage_per_model),
+            (&mut self.usage_per_prompt, &other.usage_per_prompt),
+            (&mut self.usage_per_function, &other.usage_per_function),
+            (&mut self.usage_per_task, &other.usage_per_task),
+        ] {
+            for (key, usage) in other_usages {
+                usages.entry(key.clone()).or_default().merge(usage);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::llm::message::Message;
+
+    use super::*;
+
+    fn get_response(prompt_tokens: u64, completion_tokens: u64, latency_ms: u64) -> LargeLanguageModelResponse {
+        LargeLanguageModelResponse {
+            usage: TokenUsage { prompt_tokens, completion_tokens },
+            latency: Duration::from_millis(latency_ms),
+            ..LargeLanguageModelResponse::new(&Message::assistant("answer"))
+        }
+    }
+
+    fn get_usage(invocations: u64, prompt_tokens: u64, completion_tokens: u64, latency_ms: u64) -> Usage {
+        Usage { invocations, tokens: TokenUsage { prompt_tokens, completion_tokens }, latency_ms }
+    }
+
+    /// `tests/main` asks through `tests/outer`, which asks through `tests/inner`.
+    fn get_report() -> ExecutionReport {
+        let mut report: ExecutionReport = ExecutionReport::default();
+        let functions: Vec<String> = vec!["tests/main".to_string(), "tests/outer".to_string()];
+        report.record_usage("small", "tests/ask", &functions[..1], "tests/main", &get_response(10, 5, 100));
+        report.record_usage("large", "tests/ask", &functions, "tests/main", &get_response(20, 10, 200));
+        report.record_usage("large", "tests/check", &functions, "tests/main", &get_response(30, 15, 300));
+
+        report
+    }
+
+    #[test]
+    fn adds_usage_per_model_prompt_function_and_task() {
+        let report: ExecutionReport = get_report();
+
+        assert_eq!(report.usage, get_usage(3, 60, 30, 600));
+        assert_eq!(report.usage_per_model["small"], get_usage(1, 10, 5, 100));
+        assert_eq!(report.usage_per_model["large"], get_usage(2, 50, 25, 500));
+        assert_eq!(report.usage_per_prompt["tests/ask"], get_usage(2, 30, 15, 300));
+        assert_eq!(report.usage_per_prompt["tests/check"], get_usage(1, 30, 15, 300));
+        // Callers count the invocations of the functions they call
+        assert_eq!(report.usage_per_function["tests/main"], get_usage(3, 60, 30, 600));
+        assert_eq!(report.usage_per_function["tests/outer"], get_usage(2, 50, 25, 500));
+        assert_eq!(report.usage_per_task["tests/main"], get_usage(3, 60, 30, 600));
+    }
+
+    #[test]
+    fn merges_reports() {
+        let mut report: ExecutionReport = get_report();
+        report.cache_hits = 1;
+        report.retries = 2;
+
+        let mut other: ExecutionReport = ExecutionReport { cache_misses: 1, ..ExecutionReport::default() };
+        other.record_usage("small", "tests/ask", &[], "tests/ask", &get_response(10, 5, 100));
+
+        report.merge(&other);
+
+        assert_eq!((report.cache_hits, report.cache_misses, report.retries), (1, 1, 2));
+        assert_eq!(report.usage, get_usage(4, 70, 35, 700));
+        assert_eq!(report.usage_per_model["small"], get_usage(2, 20, 10, 200));
+        assert_eq!(report.usage_per_prompt["tests/ask"], get_usage(3, 40, 20, 400));
+        assert_eq!(report.usage_per_function["tests/main"], get_usage(3, 60, 30, 600));
+        assert_eq!(report.usage_per_task["tests/ask"], get_usage(1, 10, 5, 100));
+    }
+}
@@ -1,35 +1,76 @@
 use std::collections::HashMap;
 
+use futures_util::{stream::FuturesUnordered, StreamExt};
+
 use crate::{assembly::{function::Function, instruction::Instruction}, llm::model_settings::ModelSettings};
 
-use super::virtual_machine::VirtualMachine;
+use super::{execution_event::ExecutionEventKind, runtime_error::RuntimeError, virtual_machine::VirtualMachine};
 
 pub async fn run_function<'a>(
     function_info: &'a Function,
     parameters: &Vec<String>,
     model_settings: &ModelSettings,
     vm: &'a mut VirtualMachine,
-) -> Result<String, String> {
+) -> Result<String, RuntimeError> {
+    run_function_with_variables(
+        function_info,
+        parameters,
+        HashMap::new(),
+        model_settings,
+        vm,
+    ).await.map(|(value, _)| value)
+}
+
+pub async fn run_function_with_variables<'a>(
+    function_info: &'a Function,
+    parameters: &Vec<String>,
+    variables: HashMap<String, String>,
+    model_settings: &ModelSettings,
+    vm: &'a mut VirtualMachine,
+) -> Result<(String, HashMap<String, String>), RuntimeError> {
     let mut runner: FunctionRunner = FunctionRunner {
         model_settings,
         vm,
         function_info,
-        variables: HashMap::new(),
+        variables,
         invocation_registry: None,
+        invocation_error: None,
         program_counter: 0,
     };
 
     load_parameters_into_variables(&mut runner, function_info, parameters);
 
     loop {
+        runner.vm.check_interrupted()?;
+        runner.before_step()?;
+
         match runner.step().await {
             StepResult::Ok => continue,
-            StepResult::Return(value) => return Ok(value),
-            StepResult::Err(e) => return Err(e),
+            StepResult::Return(value) => return Ok((value, runner.variables)),
+            StepResult::Err(e) => {
+                runner.vm.emit(ExecutionEventKind::Error { task: function_info.name.clone(), error: e.to_string() });
+                return Err(e);
+            },
         }
     }
 }
 
+/// Whether some calls of the block can run at the same time, which is not
+/// the case when each call depends on the one before it.
+fn has_independent_calls(calls: &[ScheduledCall]) -> bool {
+    let mut depths: Vec<usize> = Vec::new();
+    for call in calls {
+        let depth: usize = call.dependencies
+            .iter()
+            .map(|dependency| depths[*dependency] + 1)
+            .max()
+            .unwrap_or_default();
+        depths.push(depth);
+    }
+
+    depths.iter().max().is_some_and(|depth| depth + 1 < calls.len())
+}
+
 fn load_parameters_into_variables(
     runner: &mut FunctionRunner,
     function_info: &Function,
@@ -42,25 +83,51 @@ fn load_parameters_into_variables(
     }
 }
 
+/// An invocation whose result is assigned to a variable.
+struct ScheduledCall<'a> {
+    task: &'a String,
+    arguments: &'a Vec<String>,
+    variable: &'a String,
+    /// The earlier calls of the block whose results are among its arguments.
+    dependencies: Vec<usize>,
+}
+
 pub struct FunctionRunner<'a> {
     model_settings: &'a ModelSettings,
     vm: &'a mut VirtualMachine,
     function_info: &'a Function,
     variables: HashMap<String, String>,
     invocation_registry: Option<String>,
+    /// Why the last invocation left the registry empty.
+    invocation_error: Option<RuntimeError>,
     program_counter: usize,
 }
 
 enum StepResult {
     Ok,
     Return(String),
-    Err(String),
+    Err(RuntimeError),
 }
 
 impl<'a> FunctionRunner<'a> {
+    fn before_step(&mut self) -> Result<(), RuntimeError> {
+        match self.function_info.instructions.get(self.program_counter) {
+            Some(instruction) => self.vm.before_step(
+                self.function_info,
+                self.program_counter,
+                instruction,
+                &self.variables,
+                &self.invocation_registry,
+            ),
+            None => Ok(()),
+        }
+    }
+
     async fn step(&mut self) -> StepResult {
         match self.function_info.instructions.get(self.program_counter) {
             Some(instruction) => {
+                self.emit_step(self.program_counter);
+
                 match instruction {
                     Instruction::Assign(to, from) => {
                         if from == "@invocation_registry" {
@@ -69,7 +136,7 @@ impl<'a> FunctionRunner<'a> {
                                     self.variables.insert(to.clone(), value.clone());
                                 },
                                 None => {
-                                    return StepResult::Err("Tried to assign value from empty invocation registry".to_string());
+                                    return StepResult::Err(self.get_empty_registry_error());
                                 },
                             }
                         }
@@ -79,7 +146,7 @@ impl<'a> FunctionRunner<'a> {
                                     self.variables.insert(to.clone(), value.clone());
                                 },
                                 None => {
-                                    return StepResult::Err(format!("Variable {} not found", from));
+                                    return StepResult::Err(RuntimeError::VariableNotFound(from.clone()));
                                 },
                             }
                         }
@@ -87,18 +154,38 @@ impl<'a> FunctionRunner<'a> {
                         self.program_counter += 1;
                     },
                     Instruction::Invoke(task, arguments) => {
-                        let argument_values: Vec<String> = arguments
-                            .iter()
-                            .map(|argument_name| self.variables.get(argument_name).unwrap().clone())
-                            .collect();
-
-                        self.invocation_registry = match self.vm.execute(
-                            task,
-                            &argument_values,
-                            &self.model_settings,
-                        ).await.await {
-                            Ok(value) => Some(value.clone()),
-                            Err(_) => None,
+                        let calls: Vec<ScheduledCall<'a>> = self.get_call_block();
+                        if has_independent_calls(&calls) && self.vm.can_run_in_parallel() {
+                            return self.invoke_in_parallel(calls).await;
+                        }
+
+                        let mut argument_values: Vec<String> = Vec::new();
+                        for argument in arguments {
+                            match self.variables.get(argument) {
+                                Some(value) => argument_values.push(value.clone()),
+                                None => {
+                                    return StepResult::Err(RuntimeError::VariableNotFound(argument.clone()));
+                                },
+                            }
+                        }
+
+                        let invocation = if self.is_tail_call() {
+                            self.vm.execute_tail_call(task, &argument_values, self.model_settings)
+                        }
+                        else {
+                            self.vm.execute_call(task, &argument_values, self.model_settings)
+                        };
+
+                        self.invocation_registry = match invocation.await {
+                            Ok(value) => {
+                                self.invocation_error = None;
+                                Some(value.clone())
+                            },
+                            Err(e) => {
+                                self.vm.emit(ExecutionEventKind::Error { task: task.clone(), error: e.to_string() });
+                                self.invocation_error = Some(e);
+                                None
+                            },
                         };
 
                         self.program_counter += 1;
@@ -116,9 +203,465 @@ impl<'a> FunctionRunner<'a> {
                 }
             },
             None => {
-                return StepResult::Err("Tried to execute instruction outside of function bounds.".to_string())
+                return StepResult::Err(RuntimeError::InstructionOutOfBounds {
+                    function: self.function_info.name.clone(),
+                    program_counter: self.program_counter,
+                })
+            }
+        }
+        StepResult::Ok
+    }
+
+    /// The invocations starting at the program counter, each followed by the
+    /// assignment of its result, along with the calls each one depends on.
+    /// The block ends before a call assigning a variable an earlier call
+    /// assigns or reads, as both calls would then have to keep their order.
+    fn get_call_block(&self) -> Vec<ScheduledCall<'a>> {
+        let instructions: &'a Vec<Instruction> = &self.function_info.instructions;
+        let mut calls: Vec<ScheduledCall<'a>> = Vec::new();
+        let mut index: usize = self.program_counter;
+
+        while let (
+            Some(Instruction::Invoke(task, arguments)),
+            Some(Instruction::Assign(variable, from)),
+        ) = (instructions.get(index), instructions.get(index + 1)) {
+            let reassigns_variable: bool = calls
+                .iter()
+                .any(|call| call.variable == variable || call.arguments.contains(variable));
+            if from != "@invocation_registry" || reassigns_variable {
+                break;
+            }
+
+            let dependencies: Vec<usize> = calls
+                .iter()
+                .enumerate()
+                .filter(|(_, call)| arguments.contains(call.variable))
+                .map(|(index, _)| index)
+                .collect();
+
+            calls.push(ScheduledCall { task, arguments, variable, dependencies });
+            index += 2;
+        }
+
+        calls
+    }
+
+    /// Runs each call in a fork of the machine as soon as the calls it depends
+    /// on have returned, then assigns their results in order as if they had
+    /// run one after another. No call is started after one before it failed,
+    /// and the first failure in program order is the one returned.
+    async fn invoke_in_parallel(&mut self, calls: Vec<ScheduledCall<'a>>) -> StepResult {
+        let mut results: Vec<Option<Result<String, RuntimeError>>> = calls.iter().map(|_| None).collect();
+        let mut forks: Vec<Option<VirtualMachine>> = calls.iter().map(|_| None).collect();
+        let mut started: Vec<bool> = vec![false; calls.len()];
+        let mut first_failure: Option<usize> = None;
+        let mut invocations = FuturesUnordered::new();
+
+        loop {
+            for (index, call) in calls.iter().enumerate() {
+                let is_ready: bool = call.dependencies
+                    .iter()
+                    .all(|dependency| matches!(results[*dependency], Some(Ok(_))));
+                if started[index] || !is_ready || first_failure.is_some_and(|failure| failure < index) {
+                    continue;
+                }
+
+                let mut argument_values: Vec<String> = Vec::new();
+                for argument in call.arguments {
+                    let value: Option<&String> = match call.dependencies.iter().rev().find(|dependency| calls[**dependency].variable == argument) {
+                        Some(dependency) => results[*dependency].as_ref().and_then(|result| result.as_ref().ok()),
+                        None => self.variables.get(argument),
+                    };
+                    match value {
+                        Some(value) => argument_values.push(value.clone()),
+                        None => {
+                            return StepResult::Err(RuntimeError::VariableNotFound(argument.clone()));
+                        },
+                    }
+                }
+
+                // The first invocation was reported by step
+                if index > 0 {
+                    self.emit_step(self.program_counter + 2 * index);
+                }
+
+                let mut vm: VirtualMachine = self.vm.fork();
+                vm.set_instruction(self.program_counter + 2 * index);
+                let task: &String = call.task;
+                let model_settings: &ModelSettings = self.model_settings;
+                started[index] = true;
+                invocations.push(async move {
+                    let result: Result<String, RuntimeError> = vm.execute_call(task, &argument_values, model_settings).await;
+                    (index, vm, result)
+                });
+            }
+
+            match invocations.next().await {
+                Some((index, vm, result)) => {
+                    if result.is_err() && first_failure.is_none_or(|failure| index < failure) {
+                        first_failure = Some(index);
+                    }
+                    forks[index] = Some(vm);
+                    results[index] = Some(result);
+                },
+                None => break,
+            }
+        }
+
+        // Calls started after the first failure still count in the report
+        for vm in forks.iter().flatten() {
+            self.vm.join(vm);
+        }
+
+        for (call, result) in calls.iter().zip(results) {
+            let result: Result<String, RuntimeError> = match result {
+                Some(result) => result,
+                None => break,
+            };
+
+            self.program_counter += 1;
+            self.emit_step(self.program_counter);
+
+            match result {
+                Ok(value) => {
+                    self.variables.insert(call.variable.clone(), value.clone());
+                    self.invocation_registry = Some(value);
+                    self.invocation_error = None;
+                },
+                Err(e) => {
+                    self.vm.emit(ExecutionEventKind::Error { task: call.task.clone(), error: e.to_string() });
+                    self.invocation_registry = None;
+                    self.invocation_error = Some(e);
+                    return StepResult::Err(self.get_empty_registry_error());
+                },
             }
+
+            self.program_counter += 1;
         }
+
         StepResult::Ok
     }
+
+    /// The error of the failed invocation, if that is why the registry is empty.
+    fn get_empty_registry_error(&mut self) -> RuntimeError {
+        self.invocation_error.take().unwrap_or(RuntimeError::EmptyInvocationRegistry)
+    }
+
+    fn emit_step(&self, program_counter: usize) {
+        if let Some(instruction) = self.function_info.instructions.get(program_counter) {
+            self.vm.emit(ExecutionEventKind::InstructionStep {
+                function: self.function_info.name.clone(),
+                program_counter,
+                instruction: instruction.to_string(),
+            });
+        }
+    }
+
+    /// Whether the invocation at the program counter is directly returned,
+    /// as in `return task(...)`.
+    fn is_tail_call(&self) -> bool {
+        let instructions: &Vec<Instruction> = &self.function_info.instructions;
+
+        match (instructions.get(self.program_counter + 1), instructions.get(self.program_counter + 2)) {
+            (Some(Instruction::Assign(to, from)), Some(Instruction::Return(to_return))) => {
+                from == "@invocation_registry" && to == to_return
+            },
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::{Arc, Mutex}, time::Duration};
+
+    use tokio::time::Instant;
+
+    use wiremock::{matchers::{body_string_contains, method}, Mock, MockServer, ResponseTemplate};
+
+    use crate::{
+        assembly::loader::load_assembly,
+        boot_machine,
+        llm::{llm::LargeLanguageModel, mock_llm::{MockFixtures, MockLargeLanguageModel}, openai_compatible_llm::OpenAiCompatibleLargeLanguageModel, retry_policy::RetryPolicy},
+        native::{native_function::NativeFunction, native_parameter::NativeParameter},
+        virtualization::{execution_event::ExecutionEvent, stack_trace::{StackFrame, StackTrace}}
+    };
+
+    use super::*;
+
+    const ASSEMBLY: &str = "PALASM 1
+MODULE tests
+FUNCTION tests/collect
+ARGUMENTS first second third
+RETURNS std/text
+START
+INVOKE tests/echo first
+ASSIGN x @invocation_registry
+INVOKE tests/echo second
+ASSIGN y @invocation_registry
+INVOKE tests/echo third
+ASSIGN z @invocation_registry
+INVOKE tests/join x y z
+ASSIGN result @invocation_registry
+RETURN result
+END
+FUNCTION tests/pipeline
+ARGUMENTS first second
+RETURNS std/text
+START
+INVOKE tests/echo first
+ASSIGN x @invocation_registry
+INVOKE tests/echo second
+ASSIGN y @invocation_registry
+INVOKE tests/echo x
+ASSIGN z @invocation_registry
+INVOKE tests/pair y z
+ASSIGN result @invocation_registry
+RETURN result
+END
+FUNCTION tests/branch
+ARGUMENTS value
+RETURNS std/text
+START
+INVOKE tests/echo value
+ASSIGN result @invocation_registry
+RETURN result
+END
+FUNCTION tests/branches
+ARGUMENTS first second third
+RETURNS std/text
+START
+INVOKE tests/branch first
+ASSIGN x @invocation_registry
+INVOKE tests/branch second
+ASSIGN y @invocation_registry
+INVOKE tests/branch third
+ASSIGN z @invocation_registry
+INVOKE tests/join x y z
+ASSIGN result @invocation_registry
+RETURN result
+END";
+
+    /// Waits the milliseconds before the first colon, then returns what
+    /// follows it, or fails with it if it starts with `fail`.
+    fn get_echo_function() -> NativeFunction {
+        NativeFunction::new_async(
+            "tests/echo",
+            vec![NativeParameter::new("value", "std/text")],
+            "std/text",
+            |arguments| Box::pin(async move {
+                let (delay, value) = arguments[0].split_once(':').unwrap();
+                tokio::time::sleep(Duration::from_millis(delay.parse().unwrap())).await;
+                match value.starts_with("fail") {
+                    true => Err(value.into()),
+                    false => Ok(value.to_string()),
+                }
+            }),
+        )
+    }
+
+    fn get_join_function(name: &str, parameters: &[&str]) -> NativeFunction {
+        NativeFunction::new(
+            name,
+            parameters.iter().map(|parameter| NativeParameter::new(parameter, "std/text")).collect(),
+            "std/text",
+            |arguments| Ok(arguments.join(",")),
+        )
+    }
+
+    fn get_machine(llm: &LargeLanguageModel, assembly: &str) -> (VirtualMachine, Arc<Mutex<Vec<ExecutionEvent>>>) {
+        let mut vm: VirtualMachine = boot_machine(llm);
+        vm.register_native_function(&get_echo_function());
+        vm.register_native_function(&get_join_function("tests/join", &["first", "second", "third"]));
+        vm.register_native_function(&get_join_function("tests/pair", &["first", "second"]));
+        vm.load_assembly(&load_assembly(assembly).unwrap()).unwrap();
+
+        let events: Arc<Mutex<Vec<ExecutionEvent>>> = Arc::new(Mutex::new(Vec::new()));
+        let recorded_events: Arc<Mutex<Vec<ExecutionEvent>>> = events.clone();
+        vm.add_observer(move |event: &ExecutionEvent| recorded_events.lock().unwrap().push(event.clone()));
+
+        (vm, events)
+    }
+
+    fn get_mock_llm() -> LargeLanguageModel {
+        LargeLanguageModel::new_mock(MockLargeLanguageModel::new(&MockFixtures::default()).unwrap())
+    }
+
+    async fn execute(task: &str, arguments: &[&str]) -> (Result<String, RuntimeError>, Duration, Vec<ExecutionEvent>) {
+        let (mut vm, events) = get_machine(&get_mock_llm(), ASSEMBLY);
+
+        let started_at: Instant = Instant::now();
+        let result: Result<String, RuntimeError> = vm.execute(
+            &task.to_string(),
+            &arguments.iter().map(|argument| argument.to_string()).collect(),
+            &ModelSettings { model: "mock".to_string(), temperature: 0.0, max_tokens: 16 },
+        ).await.await;
+        let elapsed: Duration = started_at.elapsed();
+
+        let events: Vec<ExecutionEvent> = events.lock().unwrap().clone();
+        (result, elapsed, events)
+    }
+
+    fn get_steps(events: &[ExecutionEvent], function: &str) -> Vec<usize> {
+        events.iter()
+              .filter_map(|event| match &event.kind {
+                  ExecutionEventKind::InstructionStep { function: name, program_counter, .. } if name == function => Some(*program_counter),
+                  _ => None,
+              })
+              .collect()
+    }
+
+    fn get_started_tasks(events: &[ExecutionEvent]) -> Vec<String> {
+        events.iter()
+              .filter_map(|event| match &event.kind {
+                  ExecutionEventKind::TaskStarted { task, .. } => Some(task.clone()),
+                  _ => None,
+              })
+              .collect()
+    }
+
+    // The clock only advances while every call is waiting, so durations are exact
+    #[tokio::test(start_paused = true)]
+    async fn assigns_concurrent_results_in_order() {
+        let (result, elapsed, events) = execute("tests/collect", &["150:a", "10:b", "80:c"]).await;
+
+        assert_eq!(result.unwrap(), "a,b,c");
+        assert_eq!(elapsed, Duration::from_millis(150));
+        // The calls start together, the join once they are done, then the
+        // results are assigned in program order
+        assert_eq!(get_steps(&events, "tests/collect"), vec![0, 2, 4, 6, 1, 3, 5, 7, 8]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn starts_calls_once_their_arguments_are_ready() {
+        let (result, elapsed, _) = execute("tests/pipeline", &["80:80:c", "160:b"]).await;
+
+        // The third call waits for the first, while the second runs
+        assert_eq!(result.unwrap(), "b,c");
+        assert_eq!(elapsed, Duration::from_millis(160));
+    }
+
+    #[tokio::test]
+    async fn runs_chained_calls_one_after_another() {
+        let (mut vm, events) = get_machine(&get_mock_llm(), "PALASM 1
+MODULE tests
+FUNCTION tests/chain
+ARGUMENTS first
+RETURNS std/text
+START
+INVOKE tests/echo first
+ASSIGN x @invocation_registry
+INVOKE tests/echo x
+ASSIGN y @invocation_registry
+RETURN y
+END");
+
+        let result: Result<String, RuntimeError> = vm.execute(
+            &"tests/chain".to_string(),
+            &vec!["1:1:a".to_string()],
+            &ModelSettings { model: "mock".to_string(), temperature: 0.0, max_tokens: 16 },
+        ).await.await;
+
+        assert_eq!(result.unwrap(), "a");
+        assert_eq!(get_steps(&events.lock().unwrap(), "tests/chain"), vec![0, 1, 2, 3, 4]);
+    }
+
+    #[tokio::test]
+    async fn returns_the_error_of_the_failing_branch() {
+        let (result, _, events) = execute("tests/branches", &["20:a", "60:fail-second", "1:c"]).await;
+
+        let error: RuntimeError = result.unwrap_err();
+        assert!(matches!(
+            error.without_stack_trace(),
+            RuntimeError::NativeFunction { function, source } if function == "tests/echo" && source.to_string() == "fail-second"
+        ), "{}", error);
+        assert_eq!(
+            error.get_stack_trace(),
+            Some(&StackTrace {
+                frames: vec![
+                    StackFrame { task: "tests/echo".to_string(), instruction: None },
+                    StackFrame { task: "tests/branch".to_string(), instruction: Some(0) },
+                    StackFrame { task: "tests/branches".to_string(), instruction: Some(2) },
+                ],
+            }),
+        );
+        assert!(!get_started_tasks(&events).contains(&"tests/join".to_string()));
+    }
+
+    #[tokio::test]
+    async fn returns_the_first_failure_in_program_order() {
+        let (result, _, events) = execute("tests/branches", &["100:fail-first", "1:b", "1:fail-third"]).await;
+
+        let error: RuntimeError = result.unwrap_err();
+        assert!(error.to_string().contains("fail-first"), "{}", error);
+        assert!(!error.to_string().contains("fail-third"), "{}", error);
+        assert_eq!(error.get_stack_trace().unwrap().frames.last().unwrap().instruction, Some(0));
+        assert_eq!(get_steps(&events, "tests/branches"), vec![0, 2, 4, 1]);
+    }
+
+    #[tokio::test]
+    async fn counts_the_retries_of_each_call() {
+        let server: MockServer = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(body_string_contains("First"))
+            .respond_with(ResponseTemplate::new(503))
+            .up_to_n_times(1)
+            .with_priority(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(serde_json::json!({ "choices": [{ "message": { "role": "assistant", "content": "Paris" } }] }))
+                    .set_delay(Duration::from_millis(50))
+            )
+            .with_priority(2)
+            .mount(&server)
+            .await;
+
+        let llm: LargeLanguageModel = LargeLanguageModel::new_openai_compatible(
+            OpenAiCompatibleLargeLanguageModel::new(&server.uri(), &None, &Default::default(), &Default::default())
+        ).with_retry_policy(&RetryPolicy { initial_backoff_ms: 10, jitter: 0.0, ..RetryPolicy::default() });
+        let (mut vm, events) = get_machine(&llm, "PALASM 1
+MODULE tests
+PROMPT tests/first
+ARGUMENTS city
+RETURNS std/text
+START
+First @{city}
+END
+PROMPT tests/second
+ARGUMENTS city
+RETURNS std/text
+START
+Second @{city}
+END
+FUNCTION tests/both
+ARGUMENTS city
+RETURNS std/text
+START
+INVOKE tests/first city
+ASSIGN x @invocation_registry
+INVOKE tests/second city
+ASSIGN y @invocation_registry
+RETURN y
+END");
+
+        let result: Result<String, RuntimeError> = vm.execute(
+            &"tests/both".to_string(),
+            &vec!["France".to_string()],
+            &ModelSettings { model: "llama3".to_string(), temperature: 0.0, max_tokens: 16 },
+        ).await.await;
+
+        assert_eq!(result.unwrap(), "Paris");
+        assert_eq!(vm.get_execution_report().retries, 1);
+        let retried: Vec<(String, u64)> = events.lock().unwrap()
+            .iter()
+            .filter_map(|event| match &event.kind {
+                ExecutionEventKind::Retried { prompt, retries, .. } => Some((prompt.clone(), *retries)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(retried, vec![("tests/first".to_string(), 1)]);
+    }
 }
@@ -1,2 +1,15 @@
+pub mod budget;
+pub mod cancellation_token;
+pub mod debugger;
+pub mod execution_event;
+pub mod execution_report;
+pub mod runtime_error;
 pub mod function_runner;
+pub mod session;
+pub mod stack_trace;
+#[cfg(feature = "opentelemetry")]
+pub mod telemetry;
+#[cfg(feature = "opentelemetry")]
+pub mod telemetry_error;
+pub mod trace_writer;
 pub mod virtual_machine;
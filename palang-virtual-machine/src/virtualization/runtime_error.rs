@@ -0,0 +1,106 @@
+use std::time::Duration;
+
+use thiserror::Error;
+
+use crate::{llm::{llm_error::LlmError, storage_error::StorageError, tool_call_error::ToolCallError}, native::native_function_error::NativeFunctionError};
+
+use super::{budget::BudgetExceeded, stack_trace::StackTrace};
+
+/// Why the execution of a task failed.
+#[derive(Debug, Error)]
+pub enum RuntimeError {
+    #[error("{0} not found")]
+    TaskNotFound(String),
+
+    #[error("Model {0} not found")]
+    ModelNotFound(String),
+
+    #[error("Tool {0} not found")]
+    ToolNotFound(String),
+
+    #[error("Session {0} not found")]
+    SessionNotFound(String),
+
+    #[error("Variable {0} not found")]
+    VariableNotFound(String),
+
+    #[error("Tried to assign value from empty invocation registry")]
+    EmptyInvocationRegistry,
+
+    #[error("Tried to execute instruction {program_counter} outside of function {function}")]
+    InstructionOutOfBounds {
+        function: String,
+        program_counter: usize,
+    },
+
+    #[error("Invalid tool call ({0})")]
+    InvalidToolCall(#[from] ToolCallError),
+
+    #[error("Prompt {prompt} exceeded {rounds} rounds of tool calls")]
+    TooManyToolRounds {
+        prompt: String,
+        rounds: usize,
+    },
+
+    #[error("Native function {function} failed ({source})")]
+    NativeFunction {
+        function: String,
+        #[source]
+        source: NativeFunctionError,
+    },
+
+    #[error(transparent)]
+    Llm(#[from] LlmError),
+
+    #[error("Prompt {prompt} timed out after {timeout:?}")]
+    InvocationTimeout {
+        prompt: String,
+        timeout: Duration,
+    },
+
+    #[error("Task exceeded its deadline of {0:?}")]
+    DeadlineExceeded(Duration),
+
+    #[error(transparent)]
+    BudgetExceeded(#[from] BudgetExceeded),
+
+    #[error("Execution cancelled")]
+    Cancelled,
+
+    #[error("Execution aborted by the debugger")]
+    Aborted,
+
+    #[error("Maximum call depth of {max_call_depth} exceeded calling {task}")]
+    MaxCallDepthExceeded {
+        task: String,
+        max_call_depth: usize,
+    },
+
+    #[error("Could not update the response cache ({0})")]
+    ResponseCache(#[source] StorageError),
+
+    /// The error of an outermost call, with the calls it went through.
+    #[error("{error}\n{trace}")]
+    WithStackTrace {
+        #[source]
+        error: Box<RuntimeError>,
+        trace: StackTrace,
+    },
+}
+
+impl RuntimeError {
+    /// The error itself, without where it happened.
+    pub fn without_stack_trace(&self) -> &RuntimeError {
+        match self {
+            RuntimeError::WithStackTrace { error, .. } => error.without_stack_trace(),
+            error => error,
+        }
+    }
+
+    pub fn get_stack_trace(&self) -> Option<&StackTrace> {
+        match self {
+            RuntimeError::WithStackTrace { trace, .. } => Some(trace),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,69 @@
+use std::{collections::HashMap, fs, path::PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::llm::{message::Message, storage_error::StorageError};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Session {
+    pub name: String,
+    pub history: Vec<Message>,
+    pub variables: HashMap<String, String>,
+}
+
+impl Session {
+    pub fn new(name: &str) -> Self {
+        Session {
+            name: name.to_string(),
+            history: Vec::new(),
+            variables: HashMap::new(),
+        }
+    }
+
+    pub fn load(file: &PathBuf) -> Result<Self, StorageError> {
+        let raw_session: String = fs::read_to_string(file)
+                                     .map_err(|e| StorageError::Read { file: file.clone(), source: e })?;
+
+        serde_json::from_str(&raw_session).map_err(|e| StorageError::Json { file: file.clone(), source: e })
+    }
+
+    pub fn save(&self, file: &PathBuf) -> Result<(), StorageError> {
+        let raw_session: String = serde_json::to_string_pretty(self)
+                                             .map_err(|e| StorageError::Json { file: file.clone(), source: e })?;
+
+        fs::write(file, raw_session).map_err(|e| StorageError::Write { file: file.clone(), source: e })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn saves_and_loads_sessions() {
+        let file: PathBuf = std::env::temp_dir().join(format!("palang-session-{}.json", std::process::id()));
+        let mut session: Session = Session::new("chat");
+        session.history = vec![Message::user("Capital of France?"), Message::assistant("Paris")];
+        session.variables.insert("city".to_string(), "Paris".to_string());
+
+        session.save(&file).unwrap();
+        let loaded: Session = Session::load(&file).unwrap();
+        fs::remove_file(&file).unwrap();
+
+        assert_eq!(loaded.name, session.name);
+        assert_eq!(loaded.history, session.history);
+        assert_eq!(loaded.variables, session.variables);
+    }
+
+    #[test]
+    fn fails_to_load_invalid_sessions() {
+        let file: PathBuf = std::env::temp_dir().join(format!("palang-invalid-session-{}.json", std::process::id()));
+        fs::write(&file, "{\"name\": \"chat\"}").unwrap();
+
+        let result: Result<Session, StorageError> = Session::load(&file);
+        fs::remove_file(&file).unwrap();
+
+        assert!(matches!(result, Err(StorageError::Json { .. })));
+        assert!(matches!(Session::load(&file), Err(StorageError::Read { .. })));
+    }
+}
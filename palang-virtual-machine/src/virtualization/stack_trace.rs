@@ -0,0 +1,106 @@
+use std::fmt;
+
+use serde::Serialize;
+
+/// A task being executed, with the instruction its function was at.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StackFrame {
+    pub task: String,
+    pub instruction: Option<usize>,
+}
+
+/// The Palang calls being executed when an error happened, the innermost first.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct StackTrace {
+    pub frames: Vec<StackFrame>,
+}
+
+/// Recursive calls are written once per cycle, followed by how many frames
+/// repeat it.
+impl fmt::Display for StackTrace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Palang stack trace:")?;
+
+        let mut index: usize = 0;
+        while index < self.frames.len() {
+            let (cycle, repetitions) = self.get_cycle(index);
+            for frame in &self.frames[index..index + cycle] {
+                match frame.instruction {
+                    Some(instruction) => write!(f, "\n    at {} (instruction {})", frame.task, instruction)?,
+                    None => write!(f, "\n    at {}", frame.task)?,
+                }
+            }
+            if repetitions > 1 {
+                write!(f, "\n    ... {} more", (repetitions - 1) * cycle)?;
+            }
+
+            index += cycle * repetitions;
+        }
+
+        Ok(())
+    }
+}
+
+impl StackTrace {
+    /// The length of the shortest cycle of frames repeating from `start`, and
+    /// how many times it does, or a single frame if none repeats.
+    fn get_cycle(&self, start: usize) -> (usize, usize) {
+        let frames: &[StackFrame] = &self.frames[start..];
+
+        for cycle in 1..=frames.len() / 2 {
+            let repetitions: usize = frames
+                .chunks_exact(cycle)
+                .take_while(|chunk| *chunk == &frames[..cycle])
+                .count();
+            if repetitions > 1 {
+                return (cycle, repetitions);
+            }
+        }
+
+        (1, 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_trace(frames: &[(&str, Option<usize>)]) -> StackTrace {
+        StackTrace {
+            frames: frames.iter().map(|(task, instruction)| StackFrame { task: task.to_string(), instruction: *instruction }).collect(),
+        }
+    }
+
+    #[test]
+    fn writes_every_frame() {
+        let trace: StackTrace = get_trace(&[("tests/echo", None), ("tests/main", Some(2))]);
+
+        assert_eq!(trace.to_string(), "Palang stack trace:\n    at tests/echo\n    at tests/main (instruction 2)");
+    }
+
+    #[test]
+    fn collapses_repeated_frames() {
+        let mut frames: Vec<(&str, Option<usize>)> = vec![("tests/loop", None)];
+        frames.extend([("tests/loop", Some(0)); 63]);
+        frames.push(("tests/main", Some(1)));
+
+        assert_eq!(
+            get_trace(&frames).to_string(),
+            "Palang stack trace:\n    at tests/loop\n    at tests/loop (instruction 0)\n    ... 62 more\n    at tests/main (instruction 1)",
+        );
+    }
+
+    #[test]
+    fn collapses_repeated_cycles_of_frames() {
+        let mut frames: Vec<(&str, Option<usize>)> = Vec::new();
+        for _ in 0..3 {
+            frames.extend([("tests/ping", Some(0)), ("tests/pong", Some(1))]);
+        }
+        frames.push(("tests/ping", Some(0)));
+
+        assert_eq!(
+            get_trace(&frames).to_string(),
+            "Palang stack trace:\n    at tests/ping (instruction 0)\n    at tests/pong (instruction 1)\n    ... 4 more\n    at tests/ping (instruction 0)",
+        );
+    }
+}
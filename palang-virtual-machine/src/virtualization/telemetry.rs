@@ -0,0 +1,300 @@
+use std::{collections::HashMap, sync::Mutex, time::{Duration, SystemTime, UNIX_EPOCH}};
+
+use opentelemetry::{
+    trace::{SpanKind, Status, TraceContextExt, Tracer, TracerProvider},
+    Array, Context, KeyValue, StringValue, Value
+};
+use opentelemetry_otlp::{Protocol, SpanExporter, WithExportConfig};
+use opentelemetry_sdk::{trace::{SdkTracer, SdkTracerProvider}, Resource};
+
+use super::{execution_event::{ExecutionEvent, ExecutionEventKind, ExecutionObserver, TaskKind}, telemetry_error::TelemetryError};
+
+/// Whether a collector endpoint is set in the environment.
+pub fn is_configured() -> bool {
+    ["OTEL_EXPORTER_OTLP_ENDPOINT", "OTEL_EXPORTER_OTLP_TRACES_ENDPOINT"]
+        .iter()
+        .any(|variable| std::env::var(variable).is_ok_and(|endpoint| !endpoint.is_empty()))
+}
+
+/// Builds a tracer provider exporting spans over OTLP/HTTP. The collector is
+/// configured with the standard `OTEL_EXPORTER_OTLP_*` and `OTEL_SERVICE_NAME`
+/// environment variables.
+pub fn new_tracer_provider() -> Result<SdkTracerProvider, TelemetryError> {
+    let protocol: Protocol = match std::env::var("OTEL_EXPORTER_OTLP_PROTOCOL").as_deref() {
+        Ok("http/protobuf") | Err(_) => Protocol::HttpBinary,
+        Ok("http/json") => Protocol::HttpJson,
+        Ok(protocol) => {
+            return Err(TelemetryError::UnsupportedProtocol(protocol.to_string()));
+        },
+    };
+
+    let exporter: SpanExporter = SpanExporter::builder()
+        .with_http()
+        .with_protocol(protocol)
+        .build()?;
+
+    let resource: Resource = match std::env::var("OTEL_SERVICE_NAME") {
+        Ok(_) => Resource::builder().build(),
+        Err(_) => Resource::builder().with_service_name("palang").build(),
+    };
+
+    Ok(
+        SdkTracerProvider::builder()
+            .with_batch_exporter(exporter)
+            .with_resource(resource)
+            .build()
+    )
+}
+
+#[derive(Default)]
+struct OpenSpans {
+    /// The `execute` spans of the outermost calls.
+    executions: HashMap<u64, Context>,
+    tasks: HashMap<u64, Context>,
+    /// The invocation in progress in each call.
+    invocations: HashMap<u64, Context>,
+}
+
+/// Turns execution events into spans. Large language model invocations follow
+/// the OpenTelemetry semantic conventions for generative AI.
+pub struct OpenTelemetryObserver {
+    tracer: SdkTracer,
+    spans: Mutex<OpenSpans>,
+}
+
+impl OpenTelemetryObserver {
+    pub fn new(provider: &SdkTracerProvider) -> Self {
+        OpenTelemetryObserver {
+            tracer: provider.tracer("palang"),
+            spans: Mutex::new(OpenSpans::default()),
+        }
+    }
+
+    fn start_span(
+        &self,
+        name: String,
+        kind: SpanKind,
+        attributes: Vec<KeyValue>,
+        start_time: SystemTime,
+        parent: &Context,
+    ) -> Context {
+        let span = self.tracer
+            .span_builder(name)
+            .with_kind(kind)
+            .with_attributes(attributes)
+            .with_start_time(start_time)
+            .start_with_context(&self.tracer, parent);
+
+        parent.with_span(span)
+    }
+}
+
+impl ExecutionObserver for OpenTelemetryObserver {
+    fn on_event(&self, event: &ExecutionEvent) {
+        let timestamp: SystemTime = UNIX_EPOCH + Duration::from_millis(event.timestamp_ms);
+        let mut spans = self.spans.lock().unwrap();
+
+        match &event.kind {
+            ExecutionEventKind::TaskStarted { task, kind, .. } => {
+                let parent: Context = match event.parent_call_id.and_then(|id| spans.tasks.get(&id)) {
+                    Some(parent) => parent.clone(),
+                    None => {
+                        let execution: Context = self.start_span(
+                            "execute".to_string(),
+                            SpanKind::Internal,
+                            vec![KeyValue::new("palang.task", task.clone())],
+                            timestamp,
+                            &Context::new(),
+                        );
+                        spans.executions.insert(event.call_id, execution.clone());
+                        execution
+                    },
+                };
+
+                let name: &str = match kind {
+                    TaskKind::Prompt => "execute_prompt",
+                    TaskKind::Function => "execute_function",
+                    TaskKind::Native => "execute_native_function",
+                };
+                let task_span: Context = self.start_span(
+                    name.to_string(),
+                    SpanKind::Internal,
+                    vec![KeyValue::new("palang.task", task.clone())],
+                    timestamp,
+                    &parent,
+                );
+                spans.tasks.insert(event.call_id, task_span);
+            },
+            ExecutionEventKind::TaskEnded { error, .. } => {
+                for task_span in [spans.tasks.remove(&event.call_id), spans.executions.remove(&event.call_id)].iter().flatten() {
+                    if let Some(error) = error {
+                        task_span.span().set_status(Status::error(error.clone()));
+                    }
+                    task_span.span().end_with_timestamp(timestamp);
+                }
+            },
+            ExecutionEventKind::LlmRequest { prompt, provider, model, temperature, max_tokens, .. } => {
+                let parent: Context = spans.tasks.get(&event.call_id).cloned().unwrap_or_default();
+                let invocation: Context = self.start_span(
+                    format!("chat {}", model),
+                    SpanKind::Client,
+                    vec![
+                        KeyValue::new("gen_ai.operation.name", "chat"),
+                        KeyValue::new("gen_ai.provider.name", provider.clone()),
+                        KeyValue::new("gen_ai.request.model", model.clone()),
+                        KeyValue::new("gen_ai.request.temperature", *temperature as f64),
+                        KeyValue::new("gen_ai.request.max_tokens", *max_tokens as i64),
+                        KeyValue::new("palang.prompt", prompt.clone()),
+                    ],
+                    timestamp,
+                    &parent,
+                );
+                spans.invocations.insert(event.call_id, invocation);
+            },
+            ExecutionEventKind::LlmResponse { usage, finish_reason, cached, .. } => {
+                if let Some(invocation) = spans.invocations.remove(&event.call_id) {
+                    let span = invocation.span();
+                    span.set_attribute(KeyValue::new("gen_ai.usage.input_tokens", usage.prompt_tokens as i64));
+                    span.set_attribute(KeyValue::new("gen_ai.usage.output_tokens", usage.completion_tokens as i64));
+                    if let Some(finish_reason) = finish_reason {
+                        span.set_attribute(KeyValue::new(
+                            "gen_ai.response.finish_reasons",
+                            Value::Array(Array::String(vec![StringValue::from(finish_reason.clone())])),
+                        ));
+                    }
+                    span.set_attribute(KeyValue::new("palang.cache_hit", *cached));
+                    span.end_with_timestamp(timestamp);
+                }
+            },
+            ExecutionEventKind::Retried { retries, .. } => {
+                if let Some(invocation) = spans.invocations.get(&event.call_id) {
+                    invocation.span().set_attribute(KeyValue::new("palang.retries", *retries as i64));
+                }
+            },
+            ExecutionEventKind::Error { error, .. } => {
+                if let Some(invocation) = spans.invocations.remove(&event.call_id) {
+                    let span = invocation.span();
+                    span.set_attribute(KeyValue::new("error.type", "invocation_failed"));
+                    span.set_status(Status::error(error.clone()));
+                    span.end_with_timestamp(timestamp);
+                }
+            },
+            ExecutionEventKind::InstructionStep { .. } | ExecutionEventKind::PromptRendered { .. } => {},
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use opentelemetry::trace::{SpanId, Status};
+    use opentelemetry_sdk::trace::{InMemorySpanExporter, SpanData};
+
+    use crate::{
+        assembly::loader::load_assembly,
+        boot_machine,
+        llm::{llm::LargeLanguageModel, mock_llm::{MockFixtures, MockLargeLanguageModel}, model_settings::ModelSettings},
+        virtualization::{runtime_error::RuntimeError, virtual_machine::VirtualMachine}
+    };
+
+    use super::*;
+
+    const ASSEMBLY: &str = "PALASM 1
+MODULE tests
+PROMPT tests/greet
+ARGUMENTS name
+RETURNS std/text
+START
+Greet @{name}.
+END
+FUNCTION tests/welcome
+ARGUMENTS name
+RETURNS std/text
+START
+INVOKE tests/greet name
+ASSIGN greeting @invocation_registry
+RETURN greeting
+END";
+
+    async fn trace(response: Option<&str>) -> (Result<String, RuntimeError>, Vec<SpanData>) {
+        let exporter: InMemorySpanExporter = InMemorySpanExporter::default();
+        let provider: SdkTracerProvider = SdkTracerProvider::builder()
+            .with_simple_exporter(exporter.clone())
+            .build();
+
+        let llm: LargeLanguageModel = LargeLanguageModel::new_mock(MockLargeLanguageModel::new(&MockFixtures {
+            responses: Vec::new(),
+            default: response.map(str::to_string),
+        }).unwrap());
+        let mut vm: VirtualMachine = boot_machine(&llm);
+        vm.load_assembly(&load_assembly(ASSEMBLY).unwrap()).unwrap();
+        vm.add_observer(OpenTelemetryObserver::new(&provider));
+
+        let result: Result<String, RuntimeError> = vm.execute(
+            &"tests/welcome".to_string(),
+            &vec!["Ada".to_string()],
+            &ModelSettings { model: "mock-model".to_string(), temperature: 0.5, max_tokens: 32 },
+        ).await.await;
+
+        provider.force_flush().unwrap();
+        (result, exporter.get_finished_spans().unwrap())
+    }
+
+    fn get_span<'a>(spans: &'a [SpanData], name: &str) -> &'a SpanData {
+        spans.iter()
+             .find(|span| span.name == name)
+             .unwrap_or_else(|| panic!("No span {} in {:?}", name, spans.iter().map(|span| &span.name).collect::<Vec<_>>()))
+    }
+
+    fn get_attribute(span: &SpanData, key: &str) -> Option<Value> {
+        span.attributes
+            .iter()
+            .find(|attribute| attribute.key.as_str() == key)
+            .map(|attribute| attribute.value.clone())
+    }
+
+    #[tokio::test]
+    async fn nests_the_spans_of_an_execution() {
+        let (result, spans) = trace(Some("Hello Ada")).await;
+        assert_eq!(result.unwrap(), "Hello Ada");
+
+        let execute: &SpanData = get_span(&spans, "execute");
+        let function: &SpanData = get_span(&spans, "execute_function");
+        let prompt: &SpanData = get_span(&spans, "execute_prompt");
+        let chat: &SpanData = get_span(&spans, "chat mock-model");
+
+        assert_eq!(spans.len(), 4);
+        assert_eq!(execute.parent_span_id, SpanId::INVALID);
+        assert_eq!(function.parent_span_id, execute.span_context.span_id());
+        assert_eq!(prompt.parent_span_id, function.span_context.span_id());
+        assert_eq!(chat.parent_span_id, prompt.span_context.span_id());
+        assert!(spans.iter().all(|span| span.span_context.trace_id() == execute.span_context.trace_id()));
+
+        assert_eq!(get_attribute(function, "palang.task"), Some(Value::from("tests/welcome")));
+        assert_eq!(get_attribute(prompt, "palang.task"), Some(Value::from("tests/greet")));
+        assert_eq!(chat.span_kind, SpanKind::Client);
+        assert_eq!(get_attribute(chat, "gen_ai.operation.name"), Some(Value::from("chat")));
+        assert_eq!(get_attribute(chat, "gen_ai.provider.name"), Some(Value::from("mock")));
+        assert_eq!(get_attribute(chat, "gen_ai.request.model"), Some(Value::from("mock-model")));
+        assert_eq!(get_attribute(chat, "gen_ai.request.temperature"), Some(Value::from(0.5)));
+        assert_eq!(get_attribute(chat, "gen_ai.request.max_tokens"), Some(Value::from(32_i64)));
+        assert_eq!(get_attribute(chat, "palang.prompt"), Some(Value::from("tests/greet")));
+        assert_eq!(get_attribute(chat, "palang.cache_hit"), Some(Value::from(false)));
+        assert!(get_attribute(chat, "gen_ai.usage.input_tokens").is_some());
+        assert!(get_attribute(chat, "gen_ai.usage.output_tokens").is_some());
+        assert!(spans.iter().all(|span| span.status == Status::Unset));
+    }
+
+    #[tokio::test]
+    async fn marks_failed_invocations() {
+        let (result, spans) = trace(None).await;
+        assert!(result.is_err());
+
+        let chat: &SpanData = get_span(&spans, "chat mock-model");
+        assert_eq!(get_attribute(chat, "error.type"), Some(Value::from("invocation_failed")));
+        assert!(matches!(chat.status, Status::Error { .. }), "{:?}", chat.status);
+        for name in ["execute", "execute_function", "execute_prompt"] {
+            let span: &SpanData = get_span(&spans, name);
+            assert!(matches!(span.status, Status::Error { .. }), "{} {:?}", name, span.status);
+        }
+    }
+}
@@ -0,0 +1,12 @@
+use opentelemetry_otlp::ExporterBuildError;
+use thiserror::Error;
+
+/// Why spans cannot be exported to the configured collector.
+#[derive(Debug, Error)]
+pub enum TelemetryError {
+    #[error("Unsupported OTLP protocol {0}, use http/protobuf or http/json")]
+    UnsupportedProtocol(String),
+
+    #[error("Could not create the OTLP exporter ({0})")]
+    Exporter(#[from] ExporterBuildError),
+}
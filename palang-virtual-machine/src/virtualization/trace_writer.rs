@@ -0,0 +1,32 @@
+use std::{fs::{File, OpenOptions}, io::{LineWriter, Write}, path::PathBuf, sync::Mutex};
+
+use crate::llm::storage_error::StorageError;
+
+use super::execution_event::{ExecutionEvent, ExecutionObserver};
+
+/// Writes every event to a file as a line of JSON.
+pub struct TraceWriter {
+    file: Mutex<LineWriter<File>>,
+}
+
+impl TraceWriter {
+    pub fn create(file: &PathBuf) -> Result<Self, StorageError> {
+        let trace_file: File = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(file)
+            .map_err(|e| StorageError::Write { file: file.clone(), source: e })?;
+
+        Ok(TraceWriter { file: Mutex::new(LineWriter::new(trace_file)) })
+    }
+}
+
+impl ExecutionObserver for TraceWriter {
+    /// Tracing must not fail the execution, so events that cannot be written are dropped.
+    fn on_event(&self, event: &ExecutionEvent) {
+        if let Ok(line) = serde_json::to_string(event) {
+            let _ = writeln!(self.file.lock().unwrap(), "{}", line);
+        }
+    }
+}
@@ -1,33 +1,343 @@
-use std::{future::Future, pin::Pin};
+use std::{collections::HashMap, future::Future, pin::Pin, sync::{atomic::{AtomicU64, Ordering}, Arc, Mutex}, time::{Duration, Instant}};
 
 use crate::{
     assembly::{
         assemblies_cache::AssembliesCache,
         assembly::Assembly,
         function::Function,
+        instruction::Instruction,
+        load_error::LoadError,
+        parameter::Parameter,
         prompt::Prompt,
         task::Task
     },
-    llm::{llm::LargeLanguageModel, model_settings::ModelSettings}
+    llm::{llm::LargeLanguageModel, llm_response::LargeLanguageModelResponse, message::Message, model_settings::ModelSettings, response_cache::ResponseCache, tool::{Tool, ToolCall}},
+    native::{native_function::NativeFunction, native_functions_registry::NativeFunctionsRegistry}
 };
 
-use super::function_runner::run_function;
+use super::{budget::{Budget, BudgetExceeded, BudgetTracker}, cancellation_token::CancellationToken, debugger::{DebugState, Debugger}, execution_event::{ExecutionEvent, ExecutionEventKind, ExecutionObserver, TaskKind}, execution_report::ExecutionReport, function_runner::{run_function, run_function_with_variables}, runtime_error::RuntimeError, session::Session, stack_trace::{StackFrame, StackTrace}};
+
+const MAX_TOOL_ROUNDS: usize = 16;
+const DEFAULT_MAX_CALL_DEPTH: usize = 64;
+
+pub type StreamHandler = Arc<dyn Fn(&str) + Send + Sync>;
+
+#[derive(Clone)]
+struct CallFrame {
+    id: u64,
+    task: String,
+    started_at: Instant,
+    kind: TaskKind,
+    /// The instruction a function is executing.
+    instruction: Option<usize>,
+    /// Whether the frame's result is the result of the outermost task, in
+    /// which case its response is streamed.
+    streams: bool,
+}
+
+/// The future of a task's execution.
+pub type ExecutionFuture<'a> = Pin<Box<dyn Future<Output = Result<String, RuntimeError>> + Send + 'a>>;
 
 pub struct VirtualMachine {
-    assemblies: AssembliesCache,
+    assemblies: Arc<AssembliesCache>,
+    native_functions: Arc<NativeFunctionsRegistry>,
     llm: LargeLanguageModel,
+    response_cache: Option<ResponseCache>,
+    sessions: HashMap<String, Session>,
+    active_session: Option<String>,
+    report: ExecutionReport,
+    invocation_timeout: Option<Duration>,
+    task_timeout: Option<Duration>,
+    deadline: Option<Instant>,
+    budget: Budget,
+    /// What the execution in progress has used of its budget.
+    budget_tracker: Option<Arc<Mutex<BudgetTracker>>>,
+    cancellation: CancellationToken,
+    call_stack: Vec<CallFrame>,
+    stream_handler: Option<StreamHandler>,
+    tail_call: bool,
+    observers: Vec<Arc<dyn ExecutionObserver>>,
+    next_call_id: Arc<AtomicU64>,
+    debugger: Option<Debugger>,
+    parallel_execution: bool,
+    max_call_depth: usize,
+    /// Where the error being propagated happened.
+    error_trace: Option<StackTrace>,
 }
 
+// Hosts move forks of a machine to other threads to execute tasks concurrently
+const _: fn() = || {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<VirtualMachine>();
+};
+
 impl VirtualMachine {
     pub fn new(llm: &LargeLanguageModel) -> Self {
         VirtualMachine {
-            assemblies: AssembliesCache::new(),
+            assemblies: Arc::new(AssembliesCache::new()),
+            native_functions: Arc::new(NativeFunctionsRegistry::new()),
             llm: llm.clone(),
+            response_cache: None,
+            sessions: HashMap::new(),
+            active_session: None,
+            report: ExecutionReport::default(),
+            invocation_timeout: None,
+            task_timeout: None,
+            deadline: None,
+            budget: Budget::default(),
+            budget_tracker: None,
+            cancellation: CancellationToken::new(),
+            call_stack: Vec::new(),
+            stream_handler: None,
+            tail_call: false,
+            observers: Vec::new(),
+            next_call_id: Arc::new(AtomicU64::new(1)),
+            debugger: None,
+            parallel_execution: true,
+            max_call_depth: DEFAULT_MAX_CALL_DEPTH,
+            error_trace: None,
+        }
+    }
+
+    /// A machine sharing the assemblies, the native functions, the model and
+    /// the observers of this one, to execute tasks concurrently with it, on
+    /// any thread. Its report and sessions are its own.
+    pub fn fork(&self) -> VirtualMachine {
+        VirtualMachine {
+            assemblies: self.assemblies.clone(),
+            native_functions: self.native_functions.clone(),
+            llm: self.llm.clone(),
+            response_cache: self.response_cache.clone(),
+            sessions: HashMap::new(),
+            active_session: None,
+            report: ExecutionReport::default(),
+            invocation_timeout: self.invocation_timeout,
+            task_timeout: self.task_timeout,
+            deadline: self.deadline,
+            budget: self.budget.clone(),
+            budget_tracker: self.budget_tracker.clone(),
+            cancellation: self.cancellation.clone(),
+            call_stack: self.call_stack.clone(),
+            stream_handler: self.stream_handler.clone(),
+            tail_call: false,
+            observers: self.observers.clone(),
+            next_call_id: self.next_call_id.clone(),
+            debugger: None,
+            parallel_execution: self.parallel_execution,
+            max_call_depth: self.max_call_depth,
+            error_trace: None,
+        }
+    }
+
+    pub(crate) fn join(&mut self, fork: &VirtualMachine) {
+        self.report.merge(&fork.report);
+        if self.error_trace.is_none() {
+            self.error_trace = fork.error_trace.clone();
+        }
+    }
+
+    /// Whether independent calls may run concurrently. Calls made in a
+    /// session or under a debugger always run one after another.
+    pub fn set_parallel_execution(&mut self, parallel_execution: bool) {
+        self.parallel_execution = parallel_execution;
+    }
+
+    pub(crate) fn can_run_in_parallel(&self) -> bool {
+        self.parallel_execution && self.active_session.is_none() && self.debugger.is_none()
+    }
+
+    /// Loads an assembly after checking that the models and tasks it uses
+    /// exist in it, in the assemblies already loaded or among the native
+    /// functions. Dependencies and native functions must be loaded first.
+    pub fn load_assembly(&mut self, assembly: &Assembly) -> Result<(), LoadError> {
+        self.check_references(assembly)?;
+        Arc::make_mut(&mut self.assemblies).load(assembly);
+        Ok(())
+    }
+
+    fn check_references(&self, assembly: &Assembly) -> Result<(), LoadError> {
+        let has_model = |model: &String| {
+            assembly.models.contains_key(model) || self.assemblies.get_model(model).is_some()
+        };
+        let has_task = |task: &String| {
+            assembly.prompts.contains_key(task)
+                || assembly.functions.contains_key(task)
+                || self.assemblies.get_task(task).is_some()
+                || self.native_functions.get(task).is_some()
+        };
+        let unknown_model = |model: &String, used_by: &String| LoadError::UnknownModel {
+            model: model.clone(),
+            used_by: used_by.clone(),
+        };
+        let unknown_task = |task: &String, used_by: &String| LoadError::UnknownTask {
+            task: task.clone(),
+            used_by: used_by.clone(),
+        };
+
+        let mut prompts: Vec<&Prompt> = assembly.prompts.values().collect();
+        prompts.sort_by(|a, b| a.name.cmp(&b.name));
+        for prompt in prompts {
+            if !has_model(&prompt.return_type) {
+                return Err(unknown_model(&prompt.return_type, &prompt.name));
+            }
+            if let Some(tool) = prompt.tools.iter().find(|tool| !has_task(tool)) {
+                return Err(unknown_task(tool, &prompt.name));
+            }
+        }
+
+        let mut functions: Vec<&Function> = assembly.functions.values().collect();
+        functions.sort_by(|a, b| a.name.cmp(&b.name));
+        for function in functions {
+            if !has_model(&function.return_type) {
+                return Err(unknown_model(&function.return_type, &function.name));
+            }
+            for instruction in &function.instructions {
+                if let Instruction::Invoke(task, _) = instruction {
+                    if !has_task(task) {
+                        return Err(unknown_task(task, &function.name));
+                    }
+                }
+            }
         }
+
+        Ok(())
+    }
+
+    pub fn register_native_function(&mut self, function: &NativeFunction) {
+        Arc::make_mut(&mut self.native_functions).register(function);
+    }
+
+    pub fn get_native_functions(&self) -> Vec<NativeFunction> {
+        self.native_functions.get_all()
     }
 
-    pub fn load_assembly(&mut self, assembly: &Assembly) {
-        self.assemblies.load(assembly);
+    pub fn set_response_cache(&mut self, response_cache: &ResponseCache) {
+        self.response_cache = Some(response_cache.clone());
+    }
+
+    /// What the executions used since the machine was created or the report
+    /// was last reset. Reports add up across executions.
+    pub fn get_execution_report(&self) -> &ExecutionReport {
+        &self.report
+    }
+
+    pub fn reset_execution_report(&mut self) {
+        self.report = ExecutionReport::default();
+    }
+
+    /// Maximum time a single large language model invocation may take.
+    pub fn set_invocation_timeout(&mut self, timeout: Option<Duration>) {
+        self.invocation_timeout = timeout;
+    }
+
+    /// Maximum time a task, including every task it calls, may take.
+    pub fn set_task_timeout(&mut self, timeout: Option<Duration>) {
+        self.task_timeout = timeout;
+    }
+
+    /// Limits what each execution may use, aborting it once one of the limits is reached.
+    pub fn set_budget(&mut self, budget: &Budget) {
+        self.budget = budget.clone();
+    }
+
+    /// Cancelling the token stops the tasks being executed at their next step or invocation.
+    pub fn set_cancellation_token(&mut self, cancellation: &CancellationToken) {
+        self.cancellation = cancellation.clone();
+    }
+
+    /// Receives the response of the task being executed piece by piece, as
+    /// the large language model writes it.
+    pub fn set_stream_handler<F>(&mut self, handler: F)
+        where F: Fn(&str) + Send + Sync + 'static
+    {
+        self.stream_handler = Some(Arc::new(handler));
+    }
+
+    pub fn add_observer<T>(&mut self, observer: T)
+        where T: ExecutionObserver + 'static
+    {
+        self.observers.push(Arc::new(observer));
+    }
+
+    /// Sends an event, attributed to the task being executed, to every observer.
+    pub(crate) fn emit(&self, kind: ExecutionEventKind) {
+        if self.observers.is_empty() {
+            return;
+        }
+
+        let mut frames = self.call_stack.iter().rev();
+        let call_id: u64 = frames.next().map(|frame| frame.id).unwrap_or_default();
+        let parent_call_id: Option<u64> = frames.next().map(|frame| frame.id);
+
+        let event: ExecutionEvent = ExecutionEvent::new(call_id, parent_call_id, kind);
+        for observer in &self.observers {
+            observer.on_event(&event);
+        }
+    }
+
+    /// Maximum number of nested calls, beyond which tasks fail instead of
+    /// recursing. Every call uses native stack, so deep limits need large stacks.
+    pub fn set_max_call_depth(&mut self, max_call_depth: usize) {
+        self.max_call_depth = max_call_depth;
+    }
+
+    /// The calls being executed, the innermost first.
+    pub fn get_stack_trace(&self) -> StackTrace {
+        StackTrace {
+            frames: self.call_stack
+                .iter()
+                .rev()
+                .map(|frame| StackFrame { task: frame.task.clone(), instruction: frame.instruction })
+                .collect(),
+        }
+    }
+
+    /// Records the instruction the current function is at.
+    pub(crate) fn set_instruction(&mut self, program_counter: usize) {
+        if let Some(frame) = self.call_stack.last_mut() {
+            frame.instruction = Some(program_counter);
+        }
+    }
+
+    pub fn set_debugger(&mut self, debugger: Debugger) {
+        self.debugger = Some(debugger);
+    }
+
+    /// Lets the debugger pause the function before it executes the instruction.
+    pub(crate) fn before_step(
+        &mut self,
+        function: &Function,
+        program_counter: usize,
+        instruction: &Instruction,
+        variables: &HashMap<String, String>,
+        invocation_registry: &Option<String>,
+    ) -> Result<(), RuntimeError> {
+        self.set_instruction(program_counter);
+        let call_stack: Vec<String> = self.call_stack.iter().map(|frame| frame.task.clone()).collect();
+
+        match &mut self.debugger {
+            Some(debugger) => debugger.before_step(&DebugState {
+                function,
+                program_counter,
+                instruction,
+                variables,
+                invocation_registry,
+                call_stack,
+            }),
+            None => Ok(()),
+        }
+    }
+
+    pub fn open_session(&mut self, session: &Session) {
+        self.sessions.insert(session.name.clone(), session.clone());
+    }
+
+    pub fn get_session(&self, session: &String) -> Option<&Session> {
+        self.sessions.get(session)
+    }
+
+    pub fn close_session(&mut self, session: &String) -> Option<Session> {
+        self.sessions.remove(session)
     }
 
     pub async fn execute<'a>(
@@ -35,32 +345,254 @@ impl VirtualMachine {
         task: &'a String,
         parameters: &'a Vec<String>,
         settings: &'a ModelSettings,
-    ) -> Pin<Box<dyn Future<Output = Result<String, String>> + 'a>> {
+    ) -> ExecutionFuture<'a> {
+        self.execute_call(task, parameters, settings)
+    }
+
+    /// Tasks call each other recursively, so their executions are boxed.
+    pub(crate) fn execute_call<'a>(
+        &'a mut self,
+        task: &'a String,
+        parameters: &'a Vec<String>,
+        settings: &'a ModelSettings,
+    ) -> ExecutionFuture<'a> {
         Box::pin(async move {
-            match self.assemblies.get_task(task) {
-                Some(task) => {
-                    match task {
-                        Task::Prompt(prompt) => {
-                            return self.execute_prompt(&prompt, parameters, settings).await;
-                        },
-                        Task::Function(function) => {
-                            return self.execute_function(&function, parameters, settings).await;
-                        },
-                    }
-                },
-                None => {
-                    return Err(format!("{} not found", task));
-                }
+            let is_outermost: bool = self.call_stack.is_empty();
+            let owns_deadline: bool = self.start_deadline();
+            let owns_budget: bool = self.start_budget();
+            let result: Result<String, RuntimeError> = self.execute_task(task, parameters, settings).await;
+            self.end_budget(owns_budget);
+            self.end_deadline(owns_deadline);
+
+            match is_outermost {
+                true => self.add_stack_trace(result),
+                false => result,
             }
         })
     }
 
+    /// Executes a task whose result is returned as is by the calling function,
+    /// so that its response can be streamed in place of the caller's.
+    pub(crate) fn execute_tail_call<'a>(
+        &'a mut self,
+        task: &'a String,
+        parameters: &'a Vec<String>,
+        settings: &'a ModelSettings,
+    ) -> ExecutionFuture<'a> {
+        self.tail_call = true;
+        self.execute_call(task, parameters, settings)
+    }
+
+    /// Fails once the execution has been cancelled or has run past its deadline
+    /// or the duration of its budget.
+    pub fn check_interrupted(&self) -> Result<(), RuntimeError> {
+        if self.cancellation.is_cancelled() {
+            return Err(RuntimeError::Cancelled);
+        }
+
+        if let Some(budget_tracker) = &self.budget_tracker {
+            budget_tracker.lock().unwrap().check_duration()?;
+        }
+
+        match (self.deadline, self.task_timeout) {
+            (Some(deadline), Some(timeout)) if Instant::now() >= deadline => {
+                Err(RuntimeError::DeadlineExceeded(timeout))
+            },
+            _ => Ok(()),
+        }
+    }
+
+    fn enter(&mut self, task: &str, parameters: &[String], kind: TaskKind, tail_call: bool) -> Result<(), RuntimeError> {
+        if self.call_stack.len() >= self.max_call_depth {
+            let mut trace: StackTrace = self.get_stack_trace();
+            trace.frames.insert(0, StackFrame { task: task.to_string(), instruction: None });
+            self.error_trace = Some(trace);
+
+            return Err(RuntimeError::MaxCallDepthExceeded { task: task.to_string(), max_call_depth: self.max_call_depth });
+        }
+
+        let streams: bool = self.stream_handler.is_some() && match self.call_stack.last() {
+            Some(caller) => caller.streams && tail_call,
+            None => true,
+        };
+
+        self.call_stack.push(CallFrame {
+            id: self.next_call_id.fetch_add(1, Ordering::Relaxed),
+            task: task.to_string(),
+            started_at: Instant::now(),
+            kind,
+            instruction: None,
+            streams,
+        });
+
+        self.emit(ExecutionEventKind::TaskStarted { task: task.to_string(), kind, parameters: parameters.to_vec() });
+
+        Ok(())
+    }
+
+    fn leave(&mut self, result: &Result<String, RuntimeError>) {
+        // The innermost failing call knows where the error happened
+        match result {
+            Ok(_) => self.error_trace = None,
+            Err(_) if self.error_trace.is_none() => self.error_trace = Some(self.get_stack_trace()),
+            Err(_) => {},
+        }
+
+        if let Some(frame) = self.call_stack.last() {
+            self.emit(ExecutionEventKind::TaskEnded {
+                task: frame.task.clone(),
+                output: result.as_ref().ok().cloned(),
+                error: result.as_ref().err().map(|e| e.to_string()),
+                duration_ms: frame.started_at.elapsed().as_millis() as u64,
+            });
+        }
+
+        self.call_stack.pop();
+    }
+
+    /// Appends where the error happened to the error of an outermost call.
+    fn add_stack_trace(&mut self, result: Result<String, RuntimeError>) -> Result<String, RuntimeError> {
+        match (result, self.error_trace.take()) {
+            (Err(e), Some(trace)) => Err(RuntimeError::WithStackTrace { error: Box::new(e), trace }),
+            (result, _) => result,
+        }
+    }
+
+    /// Starts the task deadline unless an enclosing task already did.
+    fn start_deadline(&mut self) -> bool {
+        let owns_deadline: bool = self.deadline.is_none() && self.task_timeout.is_some();
+        if owns_deadline {
+            self.deadline = self.task_timeout.map(|timeout| Instant::now() + timeout);
+        }
+
+        owns_deadline
+    }
+
+    fn end_deadline(&mut self, owns_deadline: bool) {
+        if owns_deadline {
+            self.deadline = None;
+        }
+    }
+
+    /// Starts counting what the execution uses unless an enclosing task already did.
+    fn start_budget(&mut self) -> bool {
+        let owns_budget: bool = self.budget_tracker.is_none() && !self.budget.is_unlimited();
+        if owns_budget {
+            self.budget_tracker = Some(Arc::new(Mutex::new(BudgetTracker::start(&self.budget))));
+        }
+
+        owns_budget
+    }
+
+    fn end_budget(&mut self, owns_budget: bool) {
+        if owns_budget {
+            self.budget_tracker = None;
+        }
+    }
+
+    async fn execute_task(
+        &mut self,
+        task: &String,
+        parameters: &Vec<String>,
+        settings: &ModelSettings,
+    ) -> Result<String, RuntimeError> {
+        let tail_call: bool = std::mem::take(&mut self.tail_call);
+        self.check_interrupted()?;
+
+        match self.assemblies.get_task(task) {
+            Some(task) => {
+                match task {
+                    Task::Prompt(prompt) => {
+                        self.enter(&prompt.name, parameters, TaskKind::Prompt, tail_call)?;
+                        let result: Result<String, RuntimeError> = self.execute_prompt(&prompt, parameters, settings).await;
+                        self.leave(&result);
+                        result
+                    },
+                    Task::Function(function) => {
+                        self.enter(&function.name, parameters, TaskKind::Function, tail_call)?;
+                        let result: Result<String, RuntimeError> = self.execute_function(&function, parameters, settings).await;
+                        self.leave(&result);
+                        result
+                    },
+                }
+            },
+            None => {
+                match self.native_functions.get(task) {
+                    Some(function) => {
+                        self.enter(&function.name, parameters, TaskKind::Native, tail_call)?;
+                        let call = async {
+                            function
+                                .call(parameters)
+                                .await
+                                .map_err(|e| RuntimeError::NativeFunction { function: function.name.clone(), source: e })
+                        };
+                        let result: Result<String, RuntimeError> = self.run_interruptibly(call).await;
+                        self.leave(&result);
+                        result
+                    },
+                    None => Err(RuntimeError::TaskNotFound(task.clone())),
+                }
+            }
+        }
+    }
+
+    pub async fn execute_in_session(
+        &mut self,
+        session: &String,
+        task: &String,
+        parameters: &Vec<String>,
+        settings: &ModelSettings,
+    ) -> Result<String, RuntimeError> {
+        let variables: HashMap<String, String> = match self.sessions.get(session) {
+            Some(session) => session.variables.clone(),
+            None => {
+                return Err(RuntimeError::SessionNotFound(session.clone()));
+            }
+        };
+
+        self.active_session = Some(session.clone());
+        let owns_deadline: bool = self.start_deadline();
+        let owns_budget: bool = self.start_budget();
+        let result: Result<String, RuntimeError> = match self.assemblies.get_task(task) {
+            Some(Task::Prompt(prompt)) => match self.enter(&prompt.name, parameters, TaskKind::Prompt, false) {
+                Ok(()) => {
+                    let result: Result<String, RuntimeError> = self.execute_prompt(&prompt, parameters, settings).await;
+                    self.leave(&result);
+                    result
+                },
+                Err(e) => Err(e),
+            },
+            Some(Task::Function(function)) => match self.enter(&function.name, parameters, TaskKind::Function, false) {
+                Ok(()) => {
+                    let result: Result<String, RuntimeError> = match run_function_with_variables(&function, parameters, variables, settings, self).await {
+                        Ok((value, variables)) => {
+                            if let Some(session) = self.sessions.get_mut(session) {
+                                session.variables = variables;
+                            }
+                            Ok(value)
+                        },
+                        Err(e) => Err(e),
+                    };
+                    self.leave(&result);
+                    result
+                },
+                Err(e) => Err(e),
+            },
+            None => self.execute_call(task, parameters, settings).await,
+        };
+        self.end_budget(owns_budget);
+        self.end_deadline(owns_deadline);
+        self.active_session = None;
+
+        self.add_stack_trace(result)
+    }
+
     async fn execute_prompt(
         &mut self,
         prompt: &Prompt,
         parameters: &Vec<String>,
         settings: &ModelSettings,
-    ) -> Result<String, String> {
+    ) -> Result<String, RuntimeError> {
         let system: String = "
             You will reply with the wanted response only and nothing else.
             You will not add any personal remark.
@@ -82,10 +614,251 @@ impl VirtualMachine {
             instructions += &format!("Parameter \"{}\" is formatted as follows: {}\n", parameter.name, value);
         }
 
-        let return_type_model: String = self.assemblies.get_model(&prompt.return_type).unwrap().text.clone();
+        let return_type_model: String = self.assemblies
+            .get_model(&prompt.return_type)
+            .ok_or_else(|| RuntimeError::ModelNotFound(prompt.return_type.clone()))?
+            .text;
         instructions += &format!("Your response will be formatted as follows: {}", return_type_model);
 
-        self.llm.invoke(&system, &instructions, &settings).await
+        let mut messages: Vec<Message> = vec![Message::system(&system)];
+        if let Some(session) = self.get_active_session() {
+            messages.extend(session.history.iter().cloned());
+        }
+        messages.push(Message::user(&instructions));
+
+        self.emit(ExecutionEventKind::PromptRendered {
+            prompt: prompt.name.clone(),
+            system: system.clone(),
+            user: instructions.clone(),
+        });
+
+        let tools: Vec<Tool> = self.get_tools(&prompt.tools)?;
+        let mut response: Message = self.invoke_llm(prompt, &messages, &tools, settings).await?.message;
+
+        let mut tool_rounds: usize = 0;
+        while !response.tool_calls.is_empty() {
+            tool_rounds += 1;
+            if tool_rounds > MAX_TOOL_ROUNDS {
+                return Err(RuntimeError::TooManyToolRounds { prompt: prompt.name.clone(), rounds: MAX_TOOL_ROUNDS });
+            }
+
+            messages.push(response.clone());
+            for tool_call in &response.tool_calls {
+                let result: String = match self.execute_tool_call(tool_call, &tools, settings).await {
+                    Ok(value) => value,
+                    Err(e) => format!("Error: {}", e),
+                };
+                messages.push(Message::tool(&tool_call.id, &result));
+            }
+
+            response = self.invoke_llm(prompt, &messages, &tools, settings).await?.message;
+        }
+        let response: String = response.content;
+
+        if let Some(session) = self.get_active_session_mut() {
+            session.history.push(Message::user(&instructions));
+            session.history.push(Message::assistant(&response));
+        }
+
+        Ok(response)
+    }
+
+    async fn invoke_llm(
+        &mut self,
+        prompt: &Prompt,
+        messages: &Vec<Message>,
+        tools: &Vec<Tool>,
+        settings: &ModelSettings,
+    ) -> Result<LargeLanguageModelResponse, RuntimeError> {
+        let response_cache: Option<(ResponseCache, String)> = match &self.response_cache {
+            Some(response_cache) if prompt.is_cacheable() => Some((
+                response_cache.clone(),
+                ResponseCache::get_key(&self.llm, messages, tools, settings),
+            )),
+            _ => None,
+        };
+
+        self.emit(ExecutionEventKind::LlmRequest {
+            prompt: prompt.name.clone(),
+            provider: self.llm.get_provider_name(),
+            model: settings.model.clone(),
+            temperature: settings.temperature,
+            max_tokens: settings.max_tokens,
+            messages: messages.clone(),
+            tools: tools.iter().map(|tool| tool.name.clone()).collect(),
+        });
+
+        if let Some((response_cache, key)) = &response_cache {
+            if let Some(message) = response_cache.get(key) {
+                self.report.cache_hits += 1;
+                if let Some(stream_handler) = self.get_stream_handler() {
+                    stream_handler(&message.content);
+                }
+
+                let response: LargeLanguageModelResponse = LargeLanguageModelResponse::new(&message);
+                self.emit_response(prompt, settings, &response, true);
+                return Ok(response);
+            }
+            self.report.cache_misses += 1;
+        }
+
+        let response: Result<LargeLanguageModelResponse, RuntimeError> = self.invoke_interruptibly(prompt, messages, tools, settings).await;
+        let retries: u64 = match &response {
+            Ok(response) => response.retries,
+            Err(RuntimeError::Llm(e)) => e.get_retries(),
+            Err(_) => 0,
+        };
+        self.report.retries += retries;
+        if retries > 0 {
+            self.emit(ExecutionEventKind::Retried {
+                prompt: prompt.name.clone(),
+                model: settings.model.clone(),
+                retries,
+            });
+        }
+
+        let response: LargeLanguageModelResponse = match response {
+            Ok(response) => response,
+            Err(e) => {
+                self.emit(ExecutionEventKind::Error { task: prompt.name.clone(), error: e.to_string() });
+                return Err(e);
+            },
+        };
+        self.record_usage(prompt, settings, &response);
+        self.emit_response(prompt, settings, &response, false);
+        if let Some((response_cache, key)) = &response_cache {
+            response_cache.put(key, &response.message).map_err(RuntimeError::ResponseCache)?;
+        }
+
+        if let Some(budget_tracker) = &self.budget_tracker {
+            let spent: Result<(), BudgetExceeded> = budget_tracker.lock().unwrap().add(&response);
+            if let Err(e) = spent {
+                self.emit(ExecutionEventKind::Error { task: prompt.name.clone(), error: e.to_string() });
+                return Err(e.into());
+            }
+        }
+
+        Ok(response)
+    }
+
+    fn emit_response(&self, prompt: &Prompt, settings: &ModelSettings, response: &LargeLanguageModelResponse, cached: bool) {
+        self.emit(ExecutionEventKind::LlmResponse {
+            prompt: prompt.name.clone(),
+            model: settings.model.clone(),
+            message: response.message.clone(),
+            usage: response.usage,
+            finish_reason: response.finish_reason.clone(),
+            latency_ms: response.latency.as_millis() as u64,
+            cached,
+        });
+    }
+
+    fn record_usage(&mut self, prompt: &Prompt, settings: &ModelSettings, response: &LargeLanguageModelResponse) {
+        let mut functions: Vec<String> = Vec::new();
+        for frame in self.call_stack.iter().filter(|frame| frame.kind == TaskKind::Function) {
+            if !functions.contains(&frame.task) {
+                functions.push(frame.task.clone());
+            }
+        }
+        let task: String = self.call_stack
+            .first()
+            .map(|frame| frame.task.clone())
+            .unwrap_or_else(|| prompt.name.clone());
+
+        self.report.record_usage(&settings.model, &prompt.name, &functions, &task, response);
+    }
+
+    async fn invoke_interruptibly(
+        &self,
+        prompt: &Prompt,
+        messages: &Vec<Message>,
+        tools: &Vec<Tool>,
+        settings: &ModelSettings,
+    ) -> Result<LargeLanguageModelResponse, RuntimeError> {
+        self.check_interrupted()?;
+        if let Some(budget_tracker) = &self.budget_tracker {
+            budget_tracker.lock().unwrap().start_invocation()?;
+        }
+
+        let invocation_timeout = async {
+            match self.invocation_timeout {
+                Some(timeout) => tokio::time::sleep(timeout).await,
+                None => std::future::pending().await,
+            }
+        };
+
+        let stream_handler: Option<StreamHandler> = self.get_stream_handler();
+        let on_delta = |delta: &str| {
+            if let Some(stream_handler) = &stream_handler {
+                stream_handler(delta);
+            }
+        };
+        let invocation = async {
+            let response = match &stream_handler {
+                Some(_) => self.llm.invoke_streaming(&prompt.name, messages, tools, settings, &on_delta).await,
+                None => self.llm.invoke(&prompt.name, messages, tools, settings).await,
+            };
+
+            Ok(response?)
+        };
+
+        self.run_interruptibly(async {
+            tokio::select! {
+                response = invocation => response,
+                _ = invocation_timeout => Err(RuntimeError::InvocationTimeout {
+                    prompt: prompt.name.clone(),
+                    timeout: self.invocation_timeout.unwrap_or_default(),
+                }),
+            }
+        }).await
+    }
+
+    /// Awaits a call, unless the execution is cancelled or runs past its
+    /// deadline or the duration of its budget first.
+    async fn run_interruptibly<T>(&self, call: impl Future<Output = Result<T, RuntimeError>>) -> Result<T, RuntimeError> {
+        let deadline = async {
+            match self.deadline {
+                Some(deadline) => tokio::time::sleep_until(deadline.into()).await,
+                None => std::future::pending().await,
+            }
+        };
+        let budget_deadline = async {
+            match &self.budget_tracker {
+                Some(budget_tracker) => {
+                    let deadline: Option<Instant> = budget_tracker.lock().unwrap().get_deadline();
+                    match deadline {
+                        Some(deadline) => tokio::time::sleep_until(deadline.into()).await,
+                        None => std::future::pending().await,
+                    }
+                    budget_tracker.lock().unwrap().duration_exceeded()
+                },
+                None => std::future::pending().await,
+            }
+        };
+
+        tokio::select! {
+            result = call => result,
+            _ = self.cancellation.cancelled() => Err(RuntimeError::Cancelled),
+            _ = deadline => Err(RuntimeError::DeadlineExceeded(self.task_timeout.unwrap_or_default())),
+            exceeded = budget_deadline => Err(exceeded.into()),
+        }
+    }
+
+    async fn execute_tool_call(
+        &mut self,
+        tool_call: &ToolCall,
+        tools: &[Tool],
+        settings: &ModelSettings,
+    ) -> Result<String, RuntimeError> {
+        let tool: &Tool = match tools.iter().find(|tool| tool.name == tool_call.function.name) {
+            Some(tool) => tool,
+            None => {
+                return Err(RuntimeError::ToolNotFound(tool_call.function.name.clone()));
+            }
+        };
+
+        let arguments: Vec<String> = tool_call.get_arguments(tool)?;
+        self.execute_call(&tool.task, &arguments, settings).await
     }
 
     async fn execute_function(
@@ -93,7 +866,492 @@ impl VirtualMachine {
         function: &Function,
         parameters: &Vec<String>,
         model_settings: &ModelSettings,
-    ) -> Result<String, String> {
+    ) -> Result<String, RuntimeError> {
         run_function(function, parameters, model_settings, self).await
     }
+
+    fn get_tools(&self, tasks: &Vec<String>) -> Result<Vec<Tool>, RuntimeError> {
+        let mut tools: Vec<Tool> = Vec::new();
+
+        for task in tasks {
+            let (description, parameters, return_type) = match self.assemblies.get_task(task) {
+                Some(Task::Prompt(prompt)) => (prompt.text, prompt.parameters, prompt.return_type),
+                Some(Task::Function(function)) => {
+                    (format!("Palang function {}", function.name), function.parameters, function.return_type)
+                },
+                None => {
+                    match self.native_functions.get(task) {
+                        Some(function) => (
+                            format!("Native function {}", function.name),
+                            function.parameters
+                                    .iter()
+                                    .map(|parameter| Parameter { name: parameter.name.clone() })
+                                    .collect(),
+                            function.return_type,
+                        ),
+                        None => {
+                            return Err(RuntimeError::ToolNotFound(task.clone()));
+                        }
+                    }
+                }
+            };
+
+            let description: String = match self.assemblies.get_model(&return_type) {
+                Some(model) => format!("{}\nReturns: {}", description, model.text),
+                None => description,
+            };
+
+            tools.push(
+                Tool::new(
+                    task,
+                    &description,
+                    &parameters.iter().map(|parameter| parameter.name.clone()).collect::<Vec<String>>(),
+                )
+            );
+        }
+
+        Ok(tools)
+    }
+
+    /// The stream handler, if the task being executed streams its response.
+    fn get_stream_handler(&self) -> Option<StreamHandler> {
+        match self.call_stack.last() {
+            Some(frame) if frame.streams => self.stream_handler.clone(),
+            _ => None,
+        }
+    }
+
+    fn get_active_session(&self) -> Option<&Session> {
+        match &self.active_session {
+            Some(session) => self.sessions.get(session),
+            None => None,
+        }
+    }
+
+    fn get_active_session_mut(&mut self) -> Option<&mut Session> {
+        match &self.active_session {
+            Some(session) => self.sessions.get_mut(session),
+            None => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::sync::Barrier;
+
+    use crate::{
+        assembly::loader::load_assembly,
+        boot_machine,
+        llm::{
+            invokable_llm::{DeltaHandler, InvocationFuture, InvokableLargeLanguageModel},
+            message::Role,
+            mock_llm::{MockCall, MockFixtures, MockLargeLanguageModel},
+            tool::ToolCallFunction
+        },
+        native::{native_function::NativeFunctionFuture, native_parameter::NativeParameter}
+    };
+
+    use super::*;
+
+    /// Never answers.
+    struct UnresponsiveLargeLanguageModel;
+
+    impl InvokableLargeLanguageModel for UnresponsiveLargeLanguageModel {
+        fn invoke<'a>(
+            &'a self,
+            _messages: &'a [Message],
+            _tools: &'a [Tool],
+            _settings: &'a ModelSettings,
+        ) -> InvocationFuture<'a> {
+            Box::pin(std::future::pending())
+        }
+
+        fn invoke_streaming<'a>(
+            &'a self,
+            messages: &'a [Message],
+            tools: &'a [Tool],
+            settings: &'a ModelSettings,
+            _on_delta: DeltaHandler<'a>,
+        ) -> InvocationFuture<'a> {
+            self.invoke(messages, tools, settings)
+        }
+    }
+
+    /// Calls `tests/wait` with each of its arguments in turn, then answers
+    /// with the results of the calls.
+    struct ScriptedLargeLanguageModel {
+        arguments: Vec<String>,
+        conversations: Arc<Mutex<Vec<Vec<Message>>>>,
+    }
+
+    impl InvokableLargeLanguageModel for ScriptedLargeLanguageModel {
+        fn invoke<'a>(
+            &'a self,
+            messages: &'a [Message],
+            _tools: &'a [Tool],
+            _settings: &'a ModelSettings,
+        ) -> InvocationFuture<'a> {
+            Box::pin(async move {
+                self.conversations.lock().unwrap().push(messages.to_vec());
+
+                let results: Vec<&str> = messages.iter()
+                                                 .filter(|message| message.role == Role::Tool)
+                                                 .map(|message| message.content.as_str())
+                                                 .collect();
+                let message: Message = match self.arguments.get(results.len()) {
+                    Some(argument) => Message {
+                        tool_calls: vec![ToolCall {
+                            id: format!("call-{}", results.len()),
+                            kind: "function".to_string(),
+                            function: ToolCallFunction {
+                                name: "tests-wait".to_string(),
+                                arguments: serde_json::json!({ "value": argument }).to_string(),
+                            },
+                        }],
+                        ..Message::assistant("")
+                    },
+                    None => Message::assistant(&results.join(";")),
+                };
+
+                Ok(LargeLanguageModelResponse::new(&message))
+            })
+        }
+
+        fn invoke_streaming<'a>(
+            &'a self,
+            messages: &'a [Message],
+            tools: &'a [Tool],
+            settings: &'a ModelSettings,
+            _on_delta: DeltaHandler<'a>,
+        ) -> InvocationFuture<'a> {
+            self.invoke(messages, tools, settings)
+        }
+    }
+
+    const ASSEMBLY: &str = "PALASM 1
+MODULE tests
+PROMPT tests/ask
+ARGUMENTS question
+RETURNS std/text
+START
+Answer @{question}.
+END
+FUNCTION tests/askfirst
+ARGUMENTS question
+RETURNS std/text
+START
+INVOKE tests/ask question
+ASSIGN result @invocation_registry
+RETURN result
+END
+PROMPT tests/asknow
+ARGUMENTS question
+RETURNS std/text
+ANNOTATIONS nocache
+START
+Answer @{question} now.
+END
+FUNCTION tests/remember
+ARGUMENTS question
+RETURNS std/text
+START
+INVOKE tests/ask question
+ASSIGN memory @invocation_registry
+RETURN memory
+END
+FUNCTION tests/recall
+ARGUMENTS
+RETURNS std/text
+START
+RETURN memory
+END
+FUNCTION tests/loop
+ARGUMENTS question
+RETURNS std/text
+START
+INVOKE tests/loop question
+ASSIGN result @invocation_registry
+RETURN result
+END
+PROMPT tests/research
+ARGUMENTS question
+RETURNS std/text
+TOOLS tests/wait
+START
+Research @{question}.
+END
+FUNCTION tests/meet
+ARGUMENTS value
+RETURNS std/text
+START
+INVOKE tests/wait value
+ASSIGN result @invocation_registry
+RETURN result
+END";
+
+    fn get_settings() -> ModelSettings {
+        ModelSettings { model: "mock".to_string(), temperature: 0.0, max_tokens: 16 }
+    }
+
+    fn get_mock_llm() -> LargeLanguageModel {
+        LargeLanguageModel::new_mock(MockLargeLanguageModel::new(&MockFixtures::default()).unwrap())
+    }
+
+    /// Loads the assembly with `wait` as the implementation of `tests/wait`.
+    fn get_machine<F>(llm: &LargeLanguageModel, wait: F) -> VirtualMachine
+        where F: Fn(Vec<String>) -> NativeFunctionFuture + Send + Sync + 'static
+    {
+        let mut vm: VirtualMachine = boot_machine(llm);
+        vm.register_native_function(&NativeFunction::new_async(
+            "tests/wait",
+            vec![NativeParameter::new("value", "std/text")],
+            "std/text",
+            wait,
+        ));
+        vm.load_assembly(&load_assembly(ASSEMBLY).unwrap()).unwrap();
+
+        vm
+    }
+
+    fn get_unresponsive_machine() -> VirtualMachine {
+        get_machine(
+            &LargeLanguageModel::new_custom(UnresponsiveLargeLanguageModel),
+            |_| Box::pin(std::future::pending()),
+        )
+    }
+
+    /// Researches with a model calling the tool with each of the arguments,
+    /// and returns what the model was sent on each invocation.
+    async fn research(arguments: &[&str]) -> (Result<String, RuntimeError>, Vec<Vec<Message>>) {
+        let conversations: Arc<Mutex<Vec<Vec<Message>>>> = Arc::new(Mutex::new(Vec::new()));
+        let llm: LargeLanguageModel = LargeLanguageModel::new_custom(ScriptedLargeLanguageModel {
+            arguments: arguments.iter().map(|argument| argument.to_string()).collect(),
+            conversations: conversations.clone(),
+        });
+        let mut vm: VirtualMachine = get_machine(&llm, |arguments| Box::pin(async move {
+            match arguments[0].as_str() {
+                "fail" => Err("no results".into()),
+                value => Ok(format!("found {}", value)),
+            }
+        }));
+
+        let result: Result<String, RuntimeError> = execute(&mut vm, "tests/research").await;
+        let conversations: Vec<Vec<Message>> = conversations.lock().unwrap().clone();
+        (result, conversations)
+    }
+
+    async fn execute(vm: &mut VirtualMachine, task: &str) -> Result<String, RuntimeError> {
+        vm.execute(&task.to_string(), &vec!["Why?".to_string()], &get_settings()).await.await
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn forks_execute_tasks_concurrently() {
+        const TASKS: usize = 4;
+
+        // Each call waits for all the others, so none returns unless they all run at once
+        let barrier: Arc<Barrier> = Arc::new(Barrier::new(TASKS));
+        let vm: VirtualMachine = get_machine(&get_mock_llm(), move |arguments| {
+            let barrier: Arc<Barrier> = barrier.clone();
+            Box::pin(async move {
+                barrier.wait().await;
+                Ok(arguments[0].clone())
+            })
+        });
+
+        let executions: Vec<tokio::task::JoinHandle<Result<String, RuntimeError>>> = (0..TASKS)
+            .map(|index| {
+                let mut vm: VirtualMachine = vm.fork();
+                tokio::spawn(async move {
+                    vm.execute(&"tests/meet".to_string(), &vec![index.to_string()], &get_settings()).await.await
+                })
+            })
+            .collect();
+
+        let executions = tokio::time::timeout(Duration::from_secs(5), futures_util::future::join_all(executions))
+            .await
+            .expect("The forks did not execute concurrently");
+
+        for (index, execution) in executions.into_iter().enumerate() {
+            assert_eq!(execution.unwrap().unwrap(), index.to_string());
+        }
+    }
+
+    #[tokio::test]
+    async fn times_out_invocations() {
+        let mut vm: VirtualMachine = get_unresponsive_machine();
+        vm.set_invocation_timeout(Some(Duration::from_millis(20)));
+
+        let error: RuntimeError = execute(&mut vm, "tests/askfirst").await.unwrap_err();
+
+        assert!(matches!(
+            error.without_stack_trace(),
+            RuntimeError::InvocationTimeout { prompt, timeout } if prompt == "tests/ask" && *timeout == Duration::from_millis(20)
+        ), "{}", error);
+    }
+
+    #[tokio::test]
+    async fn stops_tasks_past_their_deadline() {
+        let mut vm: VirtualMachine = get_unresponsive_machine();
+        vm.set_task_timeout(Some(Duration::from_millis(20)));
+
+        for task in ["tests/askfirst", "tests/meet"] {
+            let error: RuntimeError = execute(&mut vm, task).await.unwrap_err();
+
+            assert!(matches!(error.without_stack_trace(), RuntimeError::DeadlineExceeded(_)), "{}: {}", task, error);
+        }
+    }
+
+    #[tokio::test]
+    async fn stops_cancelled_tasks() {
+        let mut vm: VirtualMachine = get_unresponsive_machine();
+
+        for task in ["tests/askfirst", "tests/meet"] {
+            let cancellation: CancellationToken = CancellationToken::new();
+            vm.set_cancellation_token(&cancellation);
+            let cancel = async {
+                tokio::time::sleep(Duration::from_millis(20)).await;
+                cancellation.cancel();
+            };
+            let (result, _) = tokio::join!(execute(&mut vm, task), cancel);
+
+            let error: RuntimeError = result.unwrap_err();
+            assert!(matches!(error.without_stack_trace(), RuntimeError::Cancelled), "{}: {}", task, error);
+        }
+    }
+
+    #[tokio::test]
+    async fn feeds_tool_results_back_to_the_model() {
+        let (result, conversations) = research(&["a", "b"]).await;
+
+        assert_eq!(result.unwrap(), "found a;found b");
+        assert_eq!(conversations.len(), 3);
+
+        let last: &Vec<Message> = conversations.last().unwrap();
+        let calls: Vec<&ToolCall> = last.iter().flat_map(|message| &message.tool_calls).collect();
+        let results: Vec<(Option<&str>, &str)> = last.iter()
+                                                     .filter(|message| message.role == Role::Tool)
+                                                     .map(|message| (message.tool_call_id.as_deref(), message.content.as_str()))
+                                                     .collect();
+        assert_eq!(calls.iter().map(|call| call.id.as_str()).collect::<Vec<&str>>(), vec!["call-0", "call-1"]);
+        assert_eq!(results, vec![(Some("call-0"), "found a"), (Some("call-1"), "found b")]);
+    }
+
+    #[tokio::test]
+    async fn reports_tool_errors_to_the_model() {
+        let (result, conversations) = research(&["fail"]).await;
+
+        let error: &str = "Error: Native function tests/wait failed (no results)";
+        assert_eq!(result.unwrap(), error);
+        assert_eq!(conversations[1].last().unwrap(), &Message::tool("call-0", error));
+    }
+
+    #[tokio::test]
+    async fn stops_after_too_many_tool_rounds() {
+        let (result, _) = research(&["a"; MAX_TOOL_ROUNDS]).await;
+        assert!(result.is_ok());
+
+        let (result, conversations) = research(&["a"; MAX_TOOL_ROUNDS + 1]).await;
+
+        let error: RuntimeError = result.unwrap_err();
+        assert!(matches!(
+            error.without_stack_trace(),
+            RuntimeError::TooManyToolRounds { prompt, rounds } if prompt == "tests/research" && *rounds == MAX_TOOL_ROUNDS
+        ), "{}", error);
+        assert_eq!(conversations.len(), MAX_TOOL_ROUNDS + 1);
+    }
+
+    #[tokio::test]
+    async fn adds_up_reports_until_they_are_reset() {
+        let llm: LargeLanguageModel = LargeLanguageModel::new_custom(ScriptedLargeLanguageModel {
+            arguments: Vec::new(),
+            conversations: Arc::new(Mutex::new(Vec::new())),
+        });
+        let mut vm: VirtualMachine = get_machine(&llm, |_| Box::pin(std::future::pending()));
+
+        for invocations in [1, 2] {
+            execute(&mut vm, "tests/askfirst").await.unwrap();
+
+            let report: &ExecutionReport = vm.get_execution_report();
+            assert_eq!(report.usage.invocations, invocations);
+            assert_eq!(report.usage_per_prompt["tests/ask"].invocations, invocations);
+            assert_eq!(report.usage_per_function["tests/askfirst"].invocations, invocations);
+            assert_eq!(report.usage_per_task["tests/askfirst"].invocations, invocations);
+        }
+
+        vm.reset_execution_report();
+        assert_eq!(vm.get_execution_report().usage.invocations, 0);
+        assert!(vm.get_execution_report().usage_per_task.is_empty());
+    }
+
+    #[tokio::test]
+    async fn caches_responses_unless_prompts_opt_out() {
+        let conversations: Arc<Mutex<Vec<Vec<Message>>>> = Arc::new(Mutex::new(Vec::new()));
+        let llm: LargeLanguageModel = LargeLanguageModel::new_custom(ScriptedLargeLanguageModel {
+            arguments: Vec::new(),
+            conversations: conversations.clone(),
+        });
+        let mut vm: VirtualMachine = get_machine(&llm, |_| Box::pin(std::future::pending()));
+        vm.set_response_cache(&ResponseCache::new_in_memory(16, None));
+
+        for task in ["tests/ask", "tests/ask", "tests/asknow", "tests/asknow"] {
+            execute(&mut vm, task).await.unwrap();
+        }
+
+        let prompts: Vec<String> = conversations.lock().unwrap().iter().map(|messages| messages.last().unwrap().content.clone()).collect();
+        assert_eq!(prompts.len(), 3);
+        assert!(prompts[0].starts_with("Answer {parameter \"question\": Why?}."), "{}", prompts[0]);
+        assert!(prompts[1..].iter().all(|prompt| prompt.contains("now.")), "{:?}", prompts);
+
+        let report: &ExecutionReport = vm.get_execution_report();
+        assert_eq!((report.cache_hits, report.cache_misses), (1, 1));
+    }
+
+    #[tokio::test]
+    async fn carries_history_and_variables_over_in_sessions() {
+        let mock: MockLargeLanguageModel = MockLargeLanguageModel::new(&MockFixtures {
+            responses: Vec::new(),
+            default: Some("Because.".to_string()),
+        }).unwrap();
+        let mut vm: VirtualMachine = get_machine(&LargeLanguageModel::new_mock(mock.clone()), |_| Box::pin(std::future::pending()));
+        let session: String = "chat".to_string();
+        vm.open_session(&Session::new(&session));
+
+        let mut execute_in_session = async |task: &str, arguments: &[&str]| {
+            let arguments: Vec<String> = arguments.iter().map(|argument| argument.to_string()).collect();
+            vm.execute_in_session(&session, &task.to_string(), &arguments, &get_settings()).await
+        };
+        assert_eq!(execute_in_session("tests/remember", &["Why?"]).await.unwrap(), "Because.");
+        assert_eq!(execute_in_session("tests/recall", &[]).await.unwrap(), "Because.");
+        execute_in_session("tests/ask", &["Really?"]).await.unwrap();
+
+        let calls: Vec<MockCall> = mock.get_calls();
+        let contents: Vec<&str> = calls[1].messages[1..].iter().map(|message| message.content.as_str()).collect();
+        assert_eq!(contents.len(), 3);
+        assert!(contents[0].starts_with("Answer {parameter \"question\": Why?}."), "{}", contents[0]);
+        assert_eq!(contents[1], "Because.");
+        assert!(contents[2].starts_with("Answer {parameter \"question\": Really?}."), "{}", contents[2]);
+
+        let session: Session = vm.close_session(&session).unwrap();
+        assert_eq!(session.history.len(), 4);
+        assert_eq!(session.variables["memory"], "Because.");
+    }
+
+    #[tokio::test]
+    async fn stops_endless_recursion() {
+        let mut vm: VirtualMachine = get_machine(&get_mock_llm(), |_| Box::pin(std::future::pending()));
+
+        let error: RuntimeError = execute(&mut vm, "tests/loop").await.unwrap_err();
+
+        assert!(matches!(
+            error.without_stack_trace(),
+            RuntimeError::MaxCallDepthExceeded { task, max_call_depth: DEFAULT_MAX_CALL_DEPTH } if task == "tests/loop"
+        ), "{}", error);
+        let trace: &StackTrace = error.get_stack_trace().unwrap();
+        assert_eq!(trace.frames.len(), DEFAULT_MAX_CALL_DEPTH + 1);
+        assert_eq!(
+            trace.to_string(),
+            format!("Palang stack trace:\n    at tests/loop\n    at tests/loop (instruction 0)\n    ... {} more", DEFAULT_MAX_CALL_DEPTH - 1),
+        );
+    }
 }